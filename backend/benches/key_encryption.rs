@@ -0,0 +1,30 @@
+//! Per-call cost of ephemeral key encryption, dominated by the PBKDF2 derivation
+//! (`KDF_ITERATIONS`). Run with `cargo bench -p backend --bench key_encryption`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use solana_sdk::signature::Keypair;
+
+// The backend is a binary crate; pull the module in directly.
+#[allow(dead_code)]
+#[path = "../src/transaction_signer.rs"]
+mod transaction_signer;
+
+const KEK: &str = "bench-key-encryption-key-0123456789abcdef";
+
+fn key_encryption(c: &mut Criterion) {
+    let keypair = Keypair::new();
+    let ciphertext = transaction_signer::encrypt_keypair(&keypair, KEK).unwrap();
+
+    let mut group = c.benchmark_group("key_encryption");
+    group.sample_size(20);
+    group.bench_function("encrypt_keypair", |b| {
+        b.iter(|| transaction_signer::encrypt_keypair(&keypair, KEK).unwrap())
+    });
+    group.bench_function("decrypt_keypair", |b| {
+        b.iter(|| transaction_signer::decrypt_keypair(&ciphertext, KEK))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, key_encryption);
+criterion_main!(benches);
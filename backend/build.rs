@@ -0,0 +1,21 @@
+use std::process::Command;
+
+/// Embeds the git commit in the binary as `EVS_GIT_COMMIT` for the `/health` payload.
+fn main() {
+    let commit = std::env::var("EVS_GIT_COMMIT")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()
+                .filter(|out| out.status.success())
+                .and_then(|out| String::from_utf8(out.stdout).ok())
+                .map(|s| s.trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".into());
+
+    println!("cargo:rustc-env=EVS_GIT_COMMIT={commit}");
+    println!("cargo:rerun-if-env-changed=EVS_GIT_COMMIT");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+}
@@ -0,0 +1,3 @@
+-- Non-sensitive fingerprint of the current ephemeral key, used to audit rotations.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS key_fingerprint TEXT NOT NULL DEFAULT '';
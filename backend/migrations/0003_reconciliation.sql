@@ -0,0 +1,7 @@
+-- Marker for sessions whose on-chain state changed but whose DB update could not be committed.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS needs_reconciliation BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS reconciliation_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_needs_reconciliation
+    ON sessions(needs_reconciliation) WHERE needs_reconciliation;
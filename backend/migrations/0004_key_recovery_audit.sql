@@ -0,0 +1,12 @@
+-- Audit trail for every attempt to recover a session's decrypted ephemeral key.
+
+CREATE TABLE IF NOT EXISTS key_recovery_audit (
+    id              UUID PRIMARY KEY,
+    session_id      UUID NOT NULL,
+    requester       TEXT NOT NULL,
+    authorized      BOOLEAN NOT NULL,
+    reason          TEXT NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_key_recovery_audit_session ON key_recovery_audit(session_id);
@@ -0,0 +1,3 @@
+-- Sessions whose ephemeral key is generated and held by the client store no encrypted key.
+
+ALTER TABLE sessions ALTER COLUMN encrypted_ephemeral_key DROP NOT NULL;
@@ -0,0 +1,3 @@
+-- When the session's ephemeral key was last replaced by `rotate-key`; NULL if never rotated.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS key_rotated_at TIMESTAMPTZ;
@@ -0,0 +1,6 @@
+-- Retry bookkeeping for on-chain cleanup. Sessions that exhaust their attempts move to
+-- status NEEDS_MANUAL.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS cleanup_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS last_cleanup_attempt_at TIMESTAMPTZ;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS cleanup_last_error TEXT;
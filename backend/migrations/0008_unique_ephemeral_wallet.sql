@@ -0,0 +1,5 @@
+-- One session per ephemeral wallet. The vault PDA is derived from (parent, ephemeral), so a
+-- reused ephemeral key would alias another session's vault and delegation.
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_sessions_ephemeral_wallet_unique
+    ON sessions(ephemeral_wallet);
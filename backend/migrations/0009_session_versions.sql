@@ -0,0 +1,67 @@
+-- Per-session version counter, bumped on every change to the row, plus a snapshot of the
+-- client-visible fields at each version so `GET /session/changes` can diff against any
+-- version a client has seen. Triggers keep both in step with every writer.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS session_versions (
+    session_id      UUID NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    version         BIGINT NOT NULL,
+    snapshot        JSONB NOT NULL,
+    recorded_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (session_id, version)
+);
+
+-- Keys match the serialized `Session` fields. Only used to detect which fields changed, so
+-- the value encoding need not match the API's.
+CREATE OR REPLACE FUNCTION session_snapshot(s sessions) RETURNS JSONB AS $$
+    SELECT jsonb_build_object(
+        'id', s.id,
+        'parent_wallet', s.parent_wallet,
+        'ephemeral_wallet', s.ephemeral_wallet,
+        'vault_pubkey', s.vault_pubkey,
+        'status', s.status,
+        'session_start', s.session_start,
+        'session_expiry', s.session_expiry,
+        'last_activity', s.last_activity,
+        'max_deposit', s.max_deposit,
+        'total_deposited', s.total_deposited,
+        'total_spent', s.total_spent,
+        'key_fingerprint', s.key_fingerprint,
+        'server_managed_key', s.encrypted_ephemeral_key IS NOT NULL
+    )
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION sessions_bump_version() RETURNS trigger AS $$
+BEGIN
+    IF session_snapshot(NEW) IS DISTINCT FROM session_snapshot(OLD) THEN
+        NEW.version := OLD.version + 1;
+    ELSE
+        NEW.version := OLD.version;
+    END IF;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION sessions_record_version() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO session_versions (session_id, version, snapshot)
+    VALUES (NEW.id, NEW.version, session_snapshot(NEW))
+    ON CONFLICT (session_id, version) DO NOTHING;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS sessions_bump_version ON sessions;
+CREATE TRIGGER sessions_bump_version
+    BEFORE UPDATE ON sessions
+    FOR EACH ROW EXECUTE FUNCTION sessions_bump_version();
+
+DROP TRIGGER IF EXISTS sessions_record_version ON sessions;
+CREATE TRIGGER sessions_record_version
+    AFTER INSERT OR UPDATE ON sessions
+    FOR EACH ROW EXECUTE FUNCTION sessions_record_version();
+
+INSERT INTO session_versions (session_id, version, snapshot)
+SELECT s.id, s.version, session_snapshot(s) FROM sessions s
+ON CONFLICT (session_id, version) DO NOTHING;
@@ -0,0 +1,7 @@
+-- Optional per-session callback fired once when the session becomes ACTIVE. `claimed_at` is
+-- set atomically before sending so the callback goes out at most once; `delivered_at` records
+-- a successful delivery.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS activation_webhook_url TEXT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS activation_webhook_claimed_at TIMESTAMPTZ;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS activation_webhook_delivered_at TIMESTAMPTZ;
@@ -0,0 +1,11 @@
+-- Outstanding login challenges for `POST /auth/challenge` / `POST /auth/verify`. A row is
+-- deleted when its challenge is redeemed, so each nonce mints at most one token.
+
+CREATE TABLE IF NOT EXISTS auth_challenges (
+    nonce           TEXT PRIMARY KEY,
+    parent_wallet   TEXT NOT NULL,
+    expires_at      TIMESTAMPTZ NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS idx_auth_challenges_expires_at ON auth_challenges(expires_at);
@@ -0,0 +1,5 @@
+-- The latest top-up `/session/deposit` computed and handed to the parent to sign. Cleared only
+-- by the next request; the on-chain `total_deposited` remains the source of truth.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS pending_deposit_lamports BIGINT;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS pending_deposit_requested_at TIMESTAMPTZ;
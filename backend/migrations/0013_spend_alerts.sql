@@ -0,0 +1,10 @@
+-- Optional per-session alert fired once `total_spent` reaches `spend_alert_bps` of
+-- `max_deposit`. `spend_alert_fired_at` is set atomically with the spend update that crosses
+-- the threshold, so the alert goes out at most once.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS spend_alert_bps INTEGER
+    CHECK (spend_alert_bps BETWEEN 1 AND 10000);
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS spend_alert_fired_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_sessions_spend_alert_pending
+    ON sessions(status) WHERE spend_alert_bps IS NOT NULL AND spend_alert_fired_at IS NULL;
@@ -1,32 +1,104 @@
 use crate::{
-    auto_deposit::PriorityLevel,
+    api_error::{ApiError, ErrorCode},
+    auth,
+    batch::BatchIds,
+    auto_deposit::{fee_oracle_from_config, AutoDepositCalculator, PriorityLevel, SessionCostEstimate},
     config::Config,
-    delegation_manager::DelegationManager,
-    session_manager::{Session, SessionManager},
+    delegation_manager::{DelegationError, DelegationManager},
+    pubsub::SharedPubsub,
+    rate_limit::{CompositeRateLimiter, RateLimiter},
+    rpc_pool::RpcPool,
+    shutdown::InFlight,
+    session_manager::{
+        AttentionItem, BootstrapOutcome, HealthSnapshot, KeyMeta, Session, SessionError,
+        SessionFilter, SessionManager, SessionStatus, SpendAlert,
+    },
+    session_store::SessionStore,
 };
 use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
 use axum::{
-    extract::{Query, State, WebSocketUpgrade},
-    http::StatusCode,
+    body::Body,
+    extract::{ConnectInfo, Query, State, WebSocketUpgrade},
+    Extension,
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+};
 use sqlx::{Pool, Postgres};
-use tokio::sync::broadcast;
+use futures::{SinkExt, StreamExt};
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, Notify};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Pool<Postgres>,
+    /// Read replica for read-only endpoints, if configured. See `read_with_fallback`.
+    pub read_db: Option<Pool<Postgres>>,
     pub cfg: Config,
     pub tx_events: broadcast::Sender<SessionEvent>,
+    /// Key recoveries, limited per authenticated requester (`admin` or the parent wallet).
+    pub recover_key_limiter: Arc<RateLimiter<String>>,
+    /// Session creation, limited per client IP and per parent wallet.
+    pub create_session_limiter: Arc<CompositeRateLimiter>,
+    /// Total events dropped across all WebSocket clients that could not keep up.
+    pub ws_dropped_events: Arc<AtomicU64>,
+    /// The one validator WebSocket connection shared by all on-chain subscriptions.
+    pub pubsub: Arc<SharedPubsub>,
+    /// RPC clients for request handlers, handed out healthiest first.
+    pub rpc_pool: Arc<RpcPool>,
+    /// Requests and WebSocket sessions still running; aborted once the shutdown grace
+    /// period runs out.
+    pub in_flight: InFlight,
+    pub started_at: Instant,
 }
 
 impl AppState {
-    pub async fn new(db: Pool<Postgres>, cfg: Config) -> Result<Self> {
+    pub async fn new(
+        db: Pool<Postgres>,
+        read_db: Option<Pool<Postgres>>,
+        cfg: Config,
+    ) -> Result<Self> {
         let (tx_events, _rx) = broadcast::channel(1024);
-        Ok(Self { db, cfg, tx_events })
+        let recover_key_limiter = Arc::new(RateLimiter::new(3, Duration::from_secs(3600)));
+        let create_session_limiter = Arc::new(CompositeRateLimiter::new(
+            cfg.security.rate_limit_ip_sessions_per_minute as usize,
+            cfg.security.rate_limit_sessions_per_minute as usize,
+            Duration::from_secs(60),
+        ));
+        let pubsub = SharedPubsub::new(
+            cfg.solana.ws_url.clone(),
+            cfg.solana.default_commitment()?,
+        );
+        let rpc_pool = Arc::new(RpcPool::from_config(&cfg.solana)?);
+        Ok(Self {
+            db,
+            read_db,
+            cfg,
+            tx_events,
+            recover_key_limiter,
+            create_session_limiter,
+            ws_dropped_events: Arc::new(AtomicU64::new(0)),
+            pubsub,
+            rpc_pool,
+            in_flight: InFlight::default(),
+            started_at: Instant::now(),
+        })
     }
 }
 
@@ -37,17 +109,96 @@ pub enum SessionEvent {
     Active(Session),
     Revoked(Session),
     Expired(Session),
+    KeyRotated(Session),
+    SpendAlert(SpendAlert),
+}
+
+/// Maps lifecycle errors to API error codes; anything else is an internal error and its
+/// detail is logged rather than returned.
+fn session_error(err: &anyhow::Error) -> ApiError {
+    let code = match err.downcast_ref::<SessionError>() {
+        Some(SessionError::NotFound(_)) => ErrorCode::SessionNotFound,
+        Some(SessionError::InvalidTransition { .. }) => ErrorCode::InvalidSessionState,
+        Some(SessionError::ClientManagedKey(_)) => ErrorCode::ClientManagedKey,
+        Some(SessionError::EphemeralWalletTaken(_)) => ErrorCode::EphemeralWalletTaken,
+        None => return internal_error(err),
+    };
+    ApiError::new(code, err.to_string())
+}
+
+/// Maps on-chain delegation lookups to API error codes: an unreachable RPC node is an
+/// upstream failure, a missing account is the caller's problem.
+fn delegation_error(err: &DelegationError) -> ApiError {
+    let code = match err {
+        DelegationError::Rpc(_) => ErrorCode::RpcUnavailable,
+        DelegationError::AccountNotFound(_) => ErrorCode::DelegationNotFound,
+        DelegationError::InvalidDelegation(_) => ErrorCode::InvalidDelegation,
+        DelegationError::Deserialize { .. } | DelegationError::Signing(_) => {
+            return internal_error(err)
+        }
+    };
+    ApiError::new(code, err.to_string())
+}
+
+/// Runs a read-only lookup on the read replica when one is configured, retrying on the
+/// primary if the replica errors, so a brief outage of either side does not fail reads.
+/// Replicas lag the primary: a session written moments ago may be missing or stale here, so
+/// never use this for read-modify-write paths.
+async fn read_with_fallback<T, F, Fut>(state: &AppState, read: F) -> Result<T>
+where
+    F: Fn(SessionManager) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    if let Some(replica) = &state.read_db {
+        match read(SessionManager::new(replica.clone(), state.cfg.clone())).await {
+            Ok(value) => return Ok(value),
+            Err(err) => tracing::warn!(error = %err, "read replica query failed; using primary"),
+        }
+    }
+    read(SessionManager::new(state.db.clone(), state.cfg.clone())).await
 }
 
-pub async fn health() -> impl IntoResponse {
-    (StatusCode::OK, "ok")
+fn internal_error(err: &dyn std::fmt::Display) -> ApiError {
+    tracing::error!(error = %err, "internal_error");
+    ErrorCode::Internal.into()
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub uptime_secs: u64,
+}
+
+pub async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let resp = HealthResponse {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: env!("EVS_GIT_COMMIT"),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+    };
+    (StatusCode::OK, Json(resp))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreateSessionRequest {
     pub parent_wallet: String,
     pub session_duration_secs: i64,
+    #[serde(with = "crate::string_u64")]
     pub max_deposit_lamports: u64,
+    /// Client-generated ephemeral pubkey. When set, the server never holds the private key
+    /// and server-side signing is disabled for the session.
+    #[serde(default)]
+    pub ephemeral_wallet: Option<String>,
+    /// Called once with a `SessionEvent::Active` payload when the session's on-chain
+    /// delegation is confirmed and it becomes `ACTIVE`.
+    #[serde(default)]
+    pub activation_webhook_url: Option<String>,
+    /// Fires `SessionEvent::SpendAlert` once `total_spent` reaches this many basis points of
+    /// `max_deposit` (1 to 10,000).
+    #[serde(default)]
+    pub spend_alert_bps: Option<u16>,
 }
 
 #[derive(Debug, Serialize)]
@@ -58,31 +209,123 @@ pub struct CreateSessionResponse {
 
 pub async fn create_session(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
     Json(req): Json<CreateSessionRequest>,
-) -> Result<Response, StatusCode> {
-    let parent_wallet = req
+) -> Result<Response, ApiError> {
+    let parent_wallet: Pubkey = req
         .parent_wallet
         .parse()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid parent_wallet"))?;
+    if parent_wallet.to_string() != caller.0 {
+        return Err(ApiError::new(
+            ErrorCode::Unauthorized,
+            "token was issued to a different parent wallet",
+        ));
+    }
+    if !state
+        .create_session_limiter
+        .check(peer.ip(), Some(&parent_wallet.to_string()))
+    {
+        return Err(ErrorCode::RateLimited.into());
+    }
+    let client_ephemeral_wallet: Option<Pubkey> = req
+        .ephemeral_wallet
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid ephemeral_wallet"))?;
+    // PDAs have no private key and so could never sign trades. The program rejects existing
+    // program accounts, but only this curve check also catches PDAs not yet created.
+    if client_ephemeral_wallet.is_some_and(|pubkey| !pubkey.is_on_curve()) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidWallet,
+            "ephemeral_wallet must be a keypair pubkey, not a PDA",
+        ));
+    }
+    if let Some(url) = req.activation_webhook_url.as_deref() {
+        let valid = reqwest::Url::parse(url)
+            .is_ok_and(|parsed| matches!(parsed.scheme(), "http" | "https"));
+        if !valid {
+            return Err(ApiError::new(
+                ErrorCode::InvalidRequest,
+                "activation_webhook_url must be an http(s) URL",
+            ));
+        }
+    }
+
+    if req.spend_alert_bps.is_some_and(|bps| bps == 0 || bps > 10_000) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "spend_alert_bps must be between 1 and 10000",
+        ));
+    }
+    if req.spend_alert_bps.is_some() && req.max_deposit_lamports == 0 {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "spend_alert_bps requires a non-zero max_deposit_lamports",
+        ));
+    }
 
     let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
-    let (session, ephemeral_kp) = sm
-        .create_session(parent_wallet, req.session_duration_secs, req.max_deposit_lamports)
+    let (session, _ephemeral_kp) = sm
+        .create_session(
+            parent_wallet,
+            req.session_duration_secs,
+            req.max_deposit_lamports,
+            client_ephemeral_wallet,
+            req.activation_webhook_url.as_deref(),
+            req.spend_alert_bps,
+        )
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| session_error(&e))?;
 
     let _ = state
         .tx_events
         .send(SessionEvent::Created(session.clone()));
 
     let resp = CreateSessionResponse {
+        ephemeral_wallet: session.ephemeral_wallet.clone(),
         session,
-        ephemeral_wallet: ephemeral_kp.pubkey().to_string(),
     };
 
     Ok((StatusCode::OK, Json(resp)).into_response())
 }
 
+/// Broadcasts `SessionEvent::Active` and fires the session's activation callback, if any.
+fn announce_activation(state: &AppState, session: &Session) {
+    let _ = state.tx_events.send(SessionEvent::Active(session.clone()));
+    crate::webhook::spawn_activation_callback(
+        SessionManager::new(state.db.clone(), state.cfg.clone()),
+        session.clone(),
+    );
+}
+
+/// `404` unless `session` belongs to `caller`, so a token cannot probe other parents'
+/// sessions.
+fn ensure_owner(caller: &auth::AuthenticatedParent, session: &Session) -> Result<(), ApiError> {
+    if session.parent_wallet == caller.0 {
+        Ok(())
+    } else {
+        Err(ErrorCode::SessionNotFound.into())
+    }
+}
+
+/// Loads `session_id` from the primary, answering `404` if it is missing or not `caller`'s.
+async fn owned_session(
+    sm: &SessionManager,
+    caller: &auth::AuthenticatedParent,
+    session_id: Uuid,
+) -> Result<Session, ApiError> {
+    let session = sm
+        .get(session_id)
+        .await
+        .map_err(|e| internal_error(&e))?
+        .ok_or(ErrorCode::SessionNotFound)?;
+    ensure_owner(caller, &session)?;
+    Ok(session)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ApproveSessionRequest {
     pub session_id: Uuid,
@@ -91,26 +334,167 @@ pub struct ApproveSessionRequest {
 
 pub async fn approve_session(
     State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
     Json(req): Json<ApproveSessionRequest>,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     let vault_pubkey = req
         .vault_pubkey
         .parse()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid vault_pubkey"))?;
 
     let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let session = owned_session(&sm, &caller, req.session_id).await?;
+    let program_id = state
+        .cfg
+        .solana
+        .program_id()
+        .map_err(|e| internal_error(&e))?;
+    DelegationManager::new(state.cfg.clone())
+        .verify_delegation_onchain(&session, vault_pubkey, program_id)
+        .await
+        .map_err(|e| delegation_error(&e))?;
+
     sm.mark_active(req.session_id, vault_pubkey)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| session_error(&e))?;
 
     if let Ok(Some(session)) = sm.get(req.session_id).await {
-        let _ = state.tx_events.send(SessionEvent::Active(session.clone()));
+        announce_activation(&state, &session);
         Ok((StatusCode::OK, Json(session)).into_response())
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ErrorCode::SessionNotFound.into())
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ResumeBootstrapRequest {
+    pub session_id: Uuid,
+}
+
+/// `transaction` is an unsigned, bincode-serialized transaction (base64) for the parent wallet
+/// to sign and submit.
+#[derive(Debug, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ResumeBootstrapResponse {
+    Activated {
+        session: Session,
+    },
+    AwaitingVault {
+        session: Session,
+        vault_pubkey: String,
+        transaction: String,
+    },
+    AwaitingApproval {
+        session: Session,
+        vault_pubkey: String,
+        transaction: String,
+    },
+}
+
+fn bootstrap_error(err: &anyhow::Error) -> ApiError {
+    match err.downcast_ref::<DelegationError>() {
+        Some(e) => delegation_error(e),
+        None => session_error(err),
+    }
+}
+
+/// Completes a bootstrap interrupted before the vault was confirmed. Activates the session
+/// when the vault and delegation are already on-chain (`200`), otherwise hands back the
+/// unsigned `create_vault` or `approve_delegate` transaction still needed (`202`).
+pub async fn resume_bootstrap(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    Json(req): Json<ResumeBootstrapRequest>,
+) -> Result<Response, ApiError> {
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let dm = DelegationManager::new(state.cfg.clone());
+    let was_active = owned_session(&sm, &caller, req.session_id).await?.status
+        == SessionStatus::Active;
+    let outcome = sm
+        .resume_bootstrap(req.session_id, &dm)
+        .await
+        .map_err(|e| bootstrap_error(&e))?;
+
+    let program_id = state
+        .cfg
+        .solana
+        .program_id()
+        .map_err(|e| internal_error(&e))?;
+    let encode = |tx: solana_sdk::transaction::Transaction| -> Result<String, ApiError> {
+        let bytes = bincode::serialize(&tx).map_err(|e| internal_error(&e))?;
+        Ok(general_purpose::STANDARD.encode(bytes))
+    };
+    let pubkeys = |session: &Session| -> Result<(Pubkey, Pubkey), ApiError> {
+        let parent = session.parent_wallet.parse().map_err(|e| internal_error(&e))?;
+        let ephemeral = session
+            .ephemeral_wallet
+            .parse()
+            .map_err(|e| internal_error(&e))?;
+        Ok((parent, ephemeral))
+    };
+
+    let (status, resp) = match outcome {
+        BootstrapOutcome::Activated(session) => {
+            if !was_active {
+                announce_activation(&state, &session);
+            }
+            (StatusCode::OK, ResumeBootstrapResponse::Activated { session })
+        }
+        BootstrapOutcome::AwaitingVault { session, vault_pubkey } => {
+            // The vault must expire when the session row does; a lapsed window cannot resume.
+            let remaining_secs = (session.session_expiry - chrono::Utc::now()).num_seconds();
+            if remaining_secs <= 0 {
+                return Err(ErrorCode::SessionExpired.into());
+            }
+            let (parent, ephemeral) = pubkeys(&session)?;
+            let allowed_programs = state
+                .cfg
+                .solana
+                .dex_programs()
+                .map_err(|e| internal_error(&e))?;
+            let ix = dm.build_create_vault_ix(
+                program_id,
+                parent,
+                ephemeral,
+                remaining_secs,
+                session.max_deposit,
+                allowed_programs,
+            );
+            let tx = dm
+                .build_unsigned_transaction(parent, vec![ix])
+                .await
+                .map_err(|e| delegation_error(&e))?;
+            let transaction = encode(tx)?;
+            (
+                StatusCode::ACCEPTED,
+                ResumeBootstrapResponse::AwaitingVault {
+                    session,
+                    vault_pubkey: vault_pubkey.to_string(),
+                    transaction,
+                },
+            )
+        }
+        BootstrapOutcome::AwaitingApproval { session, vault_pubkey } => {
+            let (parent, ephemeral) = pubkeys(&session)?;
+            let ix = dm.build_approve_delegate_ix(program_id, parent, vault_pubkey, ephemeral);
+            let tx = dm
+                .build_unsigned_transaction(parent, vec![ix])
+                .await
+                .map_err(|e| delegation_error(&e))?;
+            let transaction = encode(tx)?;
+            (
+                StatusCode::ACCEPTED,
+                ResumeBootstrapResponse::AwaitingApproval {
+                    session,
+                    vault_pubkey: vault_pubkey.to_string(),
+                    transaction,
+                },
+            )
+        }
+    };
+    Ok((status, Json(resp)).into_response())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RevokeSessionRequest {
     pub session_id: Uuid,
@@ -118,21 +502,519 @@ pub struct RevokeSessionRequest {
 
 pub async fn revoke_session(
     State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
     Json(req): Json<RevokeSessionRequest>,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, ApiError> {
     let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    owned_session(&sm, &caller, req.session_id).await?;
     sm.revoke(req.session_id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| session_error(&e))?;
 
     if let Ok(Some(session)) = sm.get(req.session_id).await {
         let _ = state.tx_events.send(SessionEvent::Revoked(session.clone()));
         Ok((StatusCode::OK, Json(session)).into_response())
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ErrorCode::SessionNotFound.into())
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RotateKeyRequest {
+    pub session_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RotateKeyResponse {
+    pub session: Session,
+    pub ephemeral_wallet: String,
+}
+
+pub async fn rotate_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    Json(req): Json<RotateKeyRequest>,
+) -> Result<Response, ApiError> {
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    owned_session(&sm, &caller, req.session_id).await?;
+    let Some((session, ephemeral_kp)) = sm
+        .rotate_key(req.session_id)
+        .await
+        .map_err(|e| session_error(&e))?
+    else {
+        return Err(ErrorCode::SessionNotFound.into());
+    };
+
+    let _ = state
+        .tx_events
+        .send(SessionEvent::KeyRotated(session.clone()));
+
+    let resp = RotateKeyResponse {
+        session,
+        ephemeral_wallet: ephemeral_kp.pubkey().to_string(),
+    };
+
+    Ok((StatusCode::OK, Json(resp)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecoverKeyRequest {
+    pub session_id: Uuid,
+    /// Nonce from `POST /auth/challenge` for the session's parent wallet. Not needed with the
+    /// admin token.
+    pub nonce: Option<String>,
+    /// Base58 Ed25519 signature by the parent wallet over [`recover_key_challenge`].
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoverKeyResponse {
+    pub session_id: Uuid,
+    pub ephemeral_wallet: String,
+    /// 64-byte keypair, in the same layout as a Solana CLI keypair file.
+    pub secret_key: Vec<u8>,
+}
+
+/// Message the parent must sign to recover a session key. `nonce` is single-use, so a
+/// captured signature cannot be replayed.
+pub fn recover_key_challenge(session_id: Uuid, nonce: &str) -> String {
+    format!("evs-recover-key:{session_id}:{nonce}")
+}
+
+fn is_admin(headers: &HeaderMap, cfg: &Config) -> bool {
+    let Some(expected) = cfg.security.admin_token.as_deref() else {
+        return false;
+    };
+    headers
+        .get("x-admin-token")
+        .and_then(|v| v.to_str().ok())
+        .map(|provided| {
+            ring::constant_time::verify_slices_are_equal(provided.as_bytes(), expected.as_bytes())
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Checks the parent's signature over the recovery challenge and returns the nonce it
+/// signed. The nonce is not consumed here.
+fn verify_parent_signature<'a>(
+    req: &'a RecoverKeyRequest,
+    parent_wallet: &str,
+) -> Result<&'a str, &'static str> {
+    let signature = req.signature.as_deref().ok_or("missing signature")?;
+    let nonce = req.nonce.as_deref().ok_or("missing nonce")?;
+    let parent: Pubkey = parent_wallet.parse().map_err(|_| "invalid parent wallet")?;
+    let signature: Signature = signature.parse().map_err(|_| "malformed signature")?;
+    let message = recover_key_challenge(req.session_id, nonce);
+    if !signature.verify(parent.as_ref(), message.as_bytes()) {
+        return Err("bad signature");
+    }
+    Ok(nonce)
+}
+
+/// Disaster-recovery endpoint returning a session's decrypted ephemeral key. Requires either
+/// the admin token or a challenge nonce signed by the session's parent wallet. Authenticated
+/// requesters are then rate limited, and every attempt on a known session, successful or
+/// not, is written to `key_recovery_audit`.
+pub async fn recover_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<RecoverKeyRequest>,
+) -> Result<Response, ApiError> {
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let admin = is_admin(&headers, &state.cfg);
+    let resp = recover_key_with(&sm, &state.recover_key_limiter, admin, &req).await?;
+    Ok((StatusCode::OK, Json(resp)).into_response())
+}
+
+/// `recover_key` over any session store.
+async fn recover_key_with<S: SessionStore>(
+    sm: &SessionManager<S>,
+    limiter: &RateLimiter<String>,
+    admin: bool,
+    req: &RecoverKeyRequest,
+) -> Result<RecoverKeyResponse, ApiError> {
+    let audit = |requester: String, authorized: bool, reason: &'static str| {
+        tracing::warn!(
+            session_id = %req.session_id,
+            %requester,
+            authorized,
+            reason,
+            "ephemeral_key_recovery_attempt"
+        );
+        async move {
+            sm.record_key_recovery(req.session_id, &requester, authorized, reason)
+                .await
+                .map_err(|e| internal_error(&e))
+        }
+    };
+
+    let Some(session) = sm
+        .get(req.session_id)
+        .await
+        .map_err(|e| internal_error(&e))?
+    else {
+        return Err(ErrorCode::SessionNotFound.into());
+    };
+
+    let requester = if admin {
+        "admin".to_string()
+    } else {
+        // The signature is checked before the nonce is touched, so a forged request cannot
+        // burn the parent's challenge.
+        let mut verified = verify_parent_signature(req, &session.parent_wallet);
+        if let Ok(nonce) = verified {
+            let redeemed = sm
+                .consume_auth_challenge(&session.parent_wallet, nonce)
+                .await
+                .map_err(|e| internal_error(&e))?;
+            if !redeemed {
+                verified = Err("unknown, expired or already used challenge");
+            }
+        }
+        if let Err(reason) = verified {
+            audit(session.parent_wallet.clone(), false, reason).await?;
+            return Err(ApiError::new(ErrorCode::Unauthorized, reason));
+        }
+        session.parent_wallet.clone()
+    };
+
+    if !limiter.check(&requester) {
+        audit(requester, false, "rate limited").await?;
+        return Err(ErrorCode::RateLimited.into());
+    }
+
+    let Some(keypair) = sm
+        .load_ephemeral_keypair(req.session_id)
+        .await
+        .map_err(|e| session_error(&e))?
+    else {
+        return Err(ErrorCode::SessionNotFound.into());
+    };
+    audit(requester, true, "key recovered").await?;
+
+    Ok(RecoverKeyResponse {
+        session_id: session.id,
+        ephemeral_wallet: keypair.pubkey().to_string(),
+        secret_key: keypair.to_bytes().to_vec(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthChallengeRequest {
+    pub parent_wallet: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthChallengeResponse {
+    pub nonce: String,
+    /// The exact message to sign with the parent wallet, see [`auth::login_challenge`].
+    pub message: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Issues a single-use nonce for `parent_wallet` to sign and redeem at `POST /auth/verify`.
+pub async fn auth_challenge(
+    State(state): State<AppState>,
+    Json(req): Json<AuthChallengeRequest>,
+) -> Result<Response, ApiError> {
+    let parent_wallet: Pubkey = req
+        .parent_wallet
+        .parse()
+        .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid parent_wallet"))?;
+    let parent_wallet = parent_wallet.to_string();
+
+    let nonce = auth::new_nonce();
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(auth::CHALLENGE_TTL_SECS);
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    sm.create_auth_challenge(&parent_wallet, &nonce, expires_at)
+        .await
+        .map_err(|e| internal_error(&e))?;
+
+    let resp = AuthChallengeResponse {
+        message: auth::login_challenge(&parent_wallet, &nonce),
+        nonce,
+        expires_at,
+    };
+    Ok((StatusCode::OK, Json(resp)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthVerifyRequest {
+    pub parent_wallet: String,
+    pub nonce: String,
+    /// Base58 Ed25519 signature by the parent wallet over the challenge message.
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthVerifyResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub scope: &'static str,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Redeems a challenge from `POST /auth/challenge` for a JWT scoped to the parent's
+/// sessions. The signature is checked before the nonce is consumed, so a forged request
+/// cannot burn someone else's challenge; a valid one can be redeemed only once.
+pub async fn auth_verify(
+    State(state): State<AppState>,
+    Json(req): Json<AuthVerifyRequest>,
+) -> Result<Response, ApiError> {
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let (token, expires_at) = verify_login(&sm, &state.cfg.security.jwt_secret, &req).await?;
+    let resp = AuthVerifyResponse {
+        token,
+        token_type: "Bearer",
+        scope: auth::PARENT_SCOPE,
+        expires_at,
+    };
+    Ok((StatusCode::OK, Json(resp)).into_response())
+}
+
+/// `auth_verify` over any session store: the parent token and its expiry.
+async fn verify_login<S: SessionStore>(
+    sm: &SessionManager<S>,
+    jwt_secret: &str,
+    req: &AuthVerifyRequest,
+) -> Result<(String, chrono::DateTime<chrono::Utc>), ApiError> {
+    let parent: Pubkey = req
+        .parent_wallet
+        .parse()
+        .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid parent_wallet"))?;
+    let parent_wallet = parent.to_string();
+    let signature: Signature = req
+        .signature
+        .parse()
+        .map_err(|_| ApiError::new(ErrorCode::Unauthorized, "malformed signature"))?;
+    let message = auth::login_challenge(&parent_wallet, &req.nonce);
+    if !signature.verify(parent.as_ref(), message.as_bytes()) {
+        return Err(ApiError::new(ErrorCode::Unauthorized, "bad signature"));
+    }
+
+    let redeemed = sm
+        .consume_auth_challenge(&parent_wallet, &req.nonce)
+        .await
+        .map_err(|e| internal_error(&e))?;
+    if !redeemed {
+        return Err(ApiError::new(
+            ErrorCode::Unauthorized,
+            "unknown, expired or already used challenge",
+        ));
+    }
+
+    auth::issue_token(&parent_wallet, jwt_secret, chrono::Utc::now())
+        .map_err(|e| internal_error(&e))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionTokenRequest {
+    pub session_id: Uuid,
+    pub permissions: Vec<auth::SessionPermission>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionTokenResponse {
+    pub token: String,
+    pub token_type: &'static str,
+    pub scope: &'static str,
+    pub session_id: Uuid,
+    pub permissions: Vec<auth::SessionPermission>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Mints a token limited to one of the caller's sessions and the requested permissions, for
+/// handing to a bot instead of the parent token. It expires with the session.
+pub async fn issue_session_token(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    Json(req): Json<SessionTokenRequest>,
+) -> Result<Response, ApiError> {
+    if req.permissions.is_empty() {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "permissions must name at least one permission",
+        ));
+    }
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let session = owned_session(&sm, &caller, req.session_id).await?;
+    if !matches!(session.status, SessionStatus::Created | SessionStatus::Active) {
+        return Err(ApiError::new(
+            ErrorCode::InvalidSessionState,
+            format!("session is {:?}, tokens need a created or active session", session.status),
+        ));
+    }
+    let now = chrono::Utc::now();
+    if session.session_expiry <= now {
+        return Err(ErrorCode::SessionExpired.into());
+    }
+    let mut permissions = req.permissions;
+    permissions.sort();
+    permissions.dedup();
+
+    let token = auth::issue_session_token(
+        &caller.0,
+        session.id,
+        &permissions,
+        &state.cfg.security.jwt_secret,
+        now,
+        session.session_expiry,
+    )
+    .map_err(|e| internal_error(&e))?;
+    let resp = SessionTokenResponse {
+        token,
+        token_type: "Bearer",
+        scope: auth::SESSION_SCOPE,
+        session_id: session.id,
+        permissions,
+        expires_at: session.session_expiry,
+    };
+    Ok((StatusCode::OK, Json(resp)).into_response())
+}
+
+/// Operator view of sessions whose cleanup exhausted its retries. Requires the admin token.
+pub async fn admin_needs_manual(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if !is_admin(&headers, &state.cfg) {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+    let sessions = read_with_fallback(&state, |sm| async move { sm.list_needs_manual().await })
+        .await
+        .map_err(|e| internal_error(&e))?;
+    Ok((StatusCode::OK, Json(sessions)).into_response())
+}
+
+const ATTENTION_DEFAULT_LIMIT: i64 = 50;
+const ATTENTION_MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct AttentionQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttentionResponse {
+    pub items: Vec<AttentionItem>,
+    /// Offset of the next page, absent on the last page.
+    pub next_offset: Option<i64>,
+}
+
+/// Operator triage queue: dead-lettered cleanups and sessions flagged by reconciliation.
+/// Requires the admin token.
+pub async fn admin_attention(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<AttentionQuery>,
+) -> Result<Response, ApiError> {
+    if !is_admin(&headers, &state.cfg) {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+    let limit = q
+        .limit
+        .unwrap_or(ATTENTION_DEFAULT_LIMIT)
+        .clamp(1, ATTENTION_MAX_LIMIT);
+    let offset = q.offset.unwrap_or(0).max(0);
+
+    let items = read_with_fallback(&state, |sm| async move {
+        sm.list_attention(limit, offset).await
+    })
+    .await
+    .map_err(|e| internal_error(&e))?;
+    let next_offset = (items.len() as i64 == limit).then_some(offset + limit);
+    Ok((StatusCode::OK, Json(AttentionResponse { items, next_offset })).into_response())
+}
+
+/// Session counts and balances for the ops overview panel. Requires the admin token.
+pub async fn admin_sessions_health(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    if !is_admin(&headers, &state.cfg) {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+    let snapshot: HealthSnapshot =
+        read_with_fallback(&state, |sm| async move { sm.health_snapshot().await })
+            .await
+            .map_err(|e| internal_error(&e))?;
+    Ok((StatusCode::OK, Json(snapshot)).into_response())
+}
+
+const SESSION_CSV_HEADER: &str = "id,parent_wallet,ephemeral_wallet,vault_pubkey,status,\
+session_start,session_expiry,last_activity,max_deposit,total_deposited,total_spent,\
+server_managed_key\n";
+
+/// Rows buffered between the DB stream and the HTTP body; bounds memory when the client
+/// reads slower than Postgres returns rows.
+const SESSION_EXPORT_BUFFER: usize = 256;
+
+/// None of the fields can contain a comma, quote or newline, so no quoting is needed.
+fn session_csv_row(s: &Session) -> String {
+    format!(
+        "{},{},{},{},{:?},{},{},{},{},{},{},{}\n",
+        s.id,
+        s.parent_wallet,
+        s.ephemeral_wallet,
+        s.vault_pubkey.as_deref().unwrap_or(""),
+        s.status,
+        s.session_start.to_rfc3339(),
+        s.session_expiry.to_rfc3339(),
+        s.last_activity.to_rfc3339(),
+        s.max_deposit,
+        s.total_deposited,
+        s.total_spent,
+        s.server_managed_key,
+    )
+}
+
+/// CSV dump of every session matching the filter. Rows are streamed from Postgres straight
+/// into the response body, so the export never holds the full table. Requires the admin token.
+pub async fn admin_export_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(filter): Query<SessionFilter>,
+) -> Result<Response, ApiError> {
+    if !is_admin(&headers, &state.cfg) {
+        return Err(ErrorCode::Unauthorized.into());
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<String>>(SESSION_EXPORT_BUFFER);
+    tokio::spawn(async move {
+        // A stream cannot fall back mid-way, so the export simply prefers the replica.
+        let pool = state.read_db.clone().unwrap_or_else(|| state.db.clone());
+        let sm = SessionManager::new(pool, state.cfg.clone());
+        let mut rows = std::pin::pin!(sm.stream_sessions(&filter));
+        while let Some(row) = rows.next().await {
+            let line = row.map(|session| session_csv_row(&session));
+            if let Err(err) = &line {
+                tracing::error!(error = %err, "session export aborted");
+            }
+            let failed = line.is_err();
+            if tx.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let header_line = futures::stream::once(async { Ok(SESSION_CSV_HEADER.to_string()) });
+    let rows = futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    });
+    let body = Body::from_stream(header_line.chain(rows));
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"sessions.csv\""),
+        ],
+        body,
+    )
+        .into_response())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SessionStatusQuery {
     pub session_id: Uuid,
@@ -140,13 +1022,192 @@ pub struct SessionStatusQuery {
 
 pub async fn session_status(
     State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    grant: Option<Extension<auth::SessionGrant>>,
     Query(q): Query<SessionStatusQuery>,
-) -> Result<Response, StatusCode> {
-    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
-    if let Ok(Some(session)) = sm.get(q.session_id).await {
+) -> Result<Response, ApiError> {
+    let session_id = q.session_id;
+    auth::check_grant(grant.as_deref(), session_id, auth::SessionPermission::Read)?;
+    let session = read_with_fallback(&state, |sm| async move { sm.get(session_id).await }).await;
+    if let Ok(Some(session)) = session.map(|s| s.filter(|s| s.parent_wallet == caller.0)) {
         Ok((StatusCode::OK, Json(session)).into_response())
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(ErrorCode::SessionNotFound.into())
+    }
+}
+
+const SESSION_LIST_DEFAULT_LIMIT: i64 = 50;
+const SESSION_LIST_MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct SessionListQuery {
+    /// Must match the caller's token when given; sessions of other wallets are never listed.
+    pub parent_wallet: Option<String>,
+    pub status: Option<SessionStatus>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionListResponse {
+    pub sessions: Vec<Session>,
+    /// Sessions matching the filter across all pages.
+    pub total: u64,
+    /// Offset of the next page, absent on the last page.
+    pub next_offset: Option<i64>,
+}
+
+/// The caller's sessions, newest first, optionally narrowed by status.
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    Query(q): Query<SessionListQuery>,
+) -> Result<Response, ApiError> {
+    let parent_wallet: Pubkey = caller
+        .0
+        .parse()
+        .map_err(|_| ApiError::from(ErrorCode::Unauthorized))?;
+    if let Some(requested) = q.parent_wallet.as_deref() {
+        let requested: Pubkey = requested
+            .parse()
+            .map_err(|_| ApiError::new(ErrorCode::InvalidWallet, "invalid parent_wallet"))?;
+        if requested != parent_wallet {
+            return Err(ApiError::new(
+                ErrorCode::Unauthorized,
+                "token was issued to a different parent wallet",
+            ));
+        }
+    }
+    let status = q.status;
+    let limit = q
+        .limit
+        .unwrap_or(SESSION_LIST_DEFAULT_LIMIT)
+        .clamp(1, SESSION_LIST_MAX_LIMIT);
+    let offset = q.offset.unwrap_or(0).max(0);
+
+    let (sessions, total) = read_with_fallback(&state, |sm| async move {
+        sm.list_sessions(Some(parent_wallet), status, limit, offset).await
+    })
+    .await
+    .map_err(|e| internal_error(&e))?;
+    let next_offset = ((offset + sessions.len() as i64) < total as i64).then_some(offset + limit);
+    Ok((
+        StatusCode::OK,
+        Json(SessionListResponse {
+            sessions,
+            total,
+            next_offset,
+        }),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionChangesQuery {
+    pub session_id: Uuid,
+    /// `version` of the client's last-seen copy of the session.
+    pub since_version: u64,
+}
+
+/// Change feed for polling clients: only the fields that changed since `since_version`, or
+/// `304 Not Modified` when nothing did.
+pub async fn session_changes(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    grant: Option<Extension<auth::SessionGrant>>,
+    Query(q): Query<SessionChangesQuery>,
+) -> Result<Response, ApiError> {
+    let SessionChangesQuery {
+        session_id,
+        since_version,
+    } = q;
+    auth::check_grant(grant.as_deref(), session_id, auth::SessionPermission::Read)?;
+    let caller = &caller;
+    let changes = read_with_fallback(&state, |sm| async move {
+        match sm.get(session_id).await? {
+            Some(session) if session.parent_wallet == caller.0 => {
+                sm.changes_since(session_id, since_version).await
+            }
+            _ => Ok(None),
+        }
+    })
+    .await;
+    match changes {
+        Ok(Some(changes)) if changes.changes.is_empty() => {
+            Ok(StatusCode::NOT_MODIFIED.into_response())
+        }
+        Ok(Some(changes)) => Ok((StatusCode::OK, Json(changes)).into_response()),
+        Ok(None) => Err(ErrorCode::SessionNotFound.into()),
+        Err(e) => Err(internal_error(&e)),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionsBatchResponse {
+    pub sessions: Vec<Session>,
+    /// Requested ids with no matching session, or whose session belongs to another parent.
+    pub missing: Vec<Uuid>,
+}
+
+impl SessionsBatchResponse {
+    /// Keeps the `sessions` fetched for `ids` that `caller` owns. Another parent's session is
+    /// listed as missing, just as `ensure_owner` answers `404` for it.
+    fn for_caller(
+        caller: &auth::AuthenticatedParent,
+        ids: Vec<Uuid>,
+        sessions: Vec<Session>,
+    ) -> Self {
+        let sessions: Vec<Session> = sessions
+            .into_iter()
+            .filter(|session| ensure_owner(caller, session).is_ok())
+            .collect();
+        let found: HashSet<Uuid> = sessions.iter().map(|s| s.id).collect();
+        let missing = ids.into_iter().filter(|id| !found.contains(id)).collect();
+        Self { sessions, missing }
+    }
+}
+
+/// Status of many of the caller's sessions in one round trip. The body is a JSON array of
+/// session ids, capped at `EVS_MAX_BATCH_ITEMS`.
+pub async fn sessions_batch(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    BatchIds(ids): BatchIds,
+) -> Result<Response, ApiError> {
+    let lookup = ids.clone();
+    let sessions = read_with_fallback(&state, |sm| {
+        let ids = lookup.clone();
+        async move { sm.get_many(&ids).await }
+    })
+    .await
+    .map_err(|e| internal_error(&e))?;
+    let response = SessionsBatchResponse::for_caller(&caller, ids, sessions);
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionKeyMetaResponse {
+    pub session: Session,
+    pub key: KeyMeta,
+}
+
+pub async fn session_key_meta(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    Query(q): Query<SessionStatusQuery>,
+) -> Result<Response, ApiError> {
+    let session_id = q.session_id;
+    let found = read_with_fallback(&state, |sm| async move {
+        sm.get_with_key_meta(session_id).await
+    })
+    .await;
+    match found {
+        Ok(Some((session, key))) => {
+            ensure_owner(&caller, &session)?;
+            Ok((StatusCode::OK, Json(SessionKeyMetaResponse { session, key })).into_response())
+        }
+        Ok(None) => Err(ErrorCode::SessionNotFound.into()),
+        Err(e) => Err(internal_error(&e)),
     }
 }
 
@@ -155,51 +1216,599 @@ pub struct SessionDepositRequest {
     pub session_id: Uuid,
     pub min_trades_buffer: u64,
     pub priority: PriorityLevel,
+    /// Extra deposit on top of the fee estimate, in basis points.
+    #[serde(default)]
+    pub safety_margin_bps: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionDepositResponse {
+    pub session_id: Uuid,
+    #[serde(with = "crate::string_u64")]
+    pub deposit_lamports: u64,
+    /// Unsigned `auto_deposit_for_trade` transaction (bincode, base64) for the parent to sign
+    /// and submit.
+    pub transaction: String,
 }
 
+/// Prices a top-up for `min_trades_buffer` trades at `priority` and hands back the unsigned
+/// `auto_deposit_for_trade` transaction. The server never holds the parent key, so the parent
+/// signs and submits it; the amount is recorded on the session as its pending deposit.
 pub async fn session_deposit(
-    State(_state): State<AppState>,
-    Json(_req): Json<SessionDepositRequest>,
-) -> Result<Response, StatusCode> {
-    // For brevity we only accept the request and return 202. A full implementation
-    // would orchestrate auto-deposit transactions here.
-    Ok((StatusCode::ACCEPTED, "scheduled").into_response())
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::AuthenticatedParent>,
+    grant: Option<Extension<auth::SessionGrant>>,
+    Json(req): Json<SessionDepositRequest>,
+) -> Result<Response, ApiError> {
+    auth::check_grant(grant.as_deref(), req.session_id, auth::SessionPermission::Deposit)?;
+    let sm = SessionManager::new(state.db.clone(), state.cfg.clone());
+    let session = owned_session(&sm, &caller, req.session_id).await?;
+    if session.status != SessionStatus::Active {
+        return Err(ApiError::new(
+            ErrorCode::InvalidSessionState,
+            format!("session is {:?}, deposits need an active session", session.status),
+        ));
+    }
+    if session.session_expiry <= chrono::Utc::now() {
+        return Err(ErrorCode::SessionExpired.into());
+    }
+    let vault: Pubkey = session
+        .vault_pubkey
+        .as_deref()
+        .ok_or(ErrorCode::InvalidSessionState)?
+        .parse()
+        .map_err(|e| internal_error(&e))?;
+    let parent: Pubkey = session.parent_wallet.parse().map_err(|e| internal_error(&e))?;
+
+    let solana = state.cfg.solana.clone();
+    let (num_trades, priority, safety_margin_bps) =
+        (req.min_trades_buffer, req.priority, req.safety_margin_bps);
+    let deposit_lamports = state
+        .rpc_pool
+        .call_blocking(move |rpc| {
+            let oracle = fee_oracle_from_config(&solana, rpc);
+            AutoDepositCalculator::new(oracle.as_ref()).compute_deposit_for_trades(
+                num_trades,
+                priority,
+                safety_margin_bps,
+            )
+        })
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::RpcUnavailable, format!("{e:#}")))?;
+    if deposit_lamports == 0 {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            "min_trades_buffer must be at least 1",
+        ));
+    }
+    let headroom = session.max_deposit.saturating_sub(session.total_deposited);
+    if deposit_lamports > headroom {
+        return Err(ApiError::new(
+            ErrorCode::InvalidRequest,
+            format!("deposit of {deposit_lamports} lamports exceeds the {headroom} left under max_deposit"),
+        ));
+    }
+
+    let program_id = state
+        .cfg
+        .solana
+        .program_id()
+        .map_err(|e| internal_error(&e))?;
+    let dm = DelegationManager::new(state.cfg.clone());
+    let ix = dm.build_auto_deposit_ix(program_id, parent, vault, deposit_lamports);
+    let tx = dm
+        .build_unsigned_transaction(parent, vec![ix])
+        .await
+        .map_err(|e| delegation_error(&e))?;
+    let bytes = bincode::serialize(&tx).map_err(|e| internal_error(&e))?;
+
+    sm.record_pending_deposit(req.session_id, deposit_lamports)
+        .await
+        .map_err(|e| internal_error(&e))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(SessionDepositResponse {
+            session_id: req.session_id,
+            deposit_lamports,
+            transaction: general_purpose::STANDARD.encode(bytes),
+        }),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EstimateCostQuery {
+    pub num_trades: u64,
+    pub priority: PriorityLevel,
+    #[serde(default)]
+    pub safety_margin_bps: u16,
+}
+
+pub async fn estimate_session_cost(
+    State(state): State<AppState>,
+    Query(q): Query<EstimateCostQuery>,
+) -> Result<Response, ApiError> {
+    let solana = state.cfg.solana.clone();
+    let estimate = state
+        .rpc_pool
+        .call_blocking(move |rpc| {
+            let oracle = fee_oracle_from_config(&solana, rpc);
+            SessionCostEstimate::estimate(
+                rpc,
+                &AutoDepositCalculator::new(oracle.as_ref()),
+                q.num_trades,
+                q.priority,
+                q.safety_margin_bps,
+            )
+        })
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::RpcUnavailable, format!("{e:#}")))?;
+    Ok((StatusCode::OK, Json(estimate)).into_response())
+}
+
+/// Events buffered per WebSocket client before the oldest are dropped.
+const WS_CLIENT_QUEUE_CAPACITY: usize = 256;
+
+/// Per-connection queue between the shared broadcast channel and one WebSocket client.
+/// A slow client only ever loses its own oldest events; it never holds back the channel.
+#[derive(Default)]
+struct ClientQueue {
+    events: VecDeque<SessionEvent>,
+    dropped: u64,
+    closed: bool,
 }
 
+impl ClientQueue {
+    /// Queues `evt`, dropping the oldest queued event if the queue is full. Returns whether an
+    /// event was dropped.
+    fn push(&mut self, evt: SessionEvent) -> bool {
+        let overflow = self.events.len() >= WS_CLIENT_QUEUE_CAPACITY;
+        if overflow {
+            self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(evt);
+        overflow
+    }
+
+    /// Takes everything queued so far, plus a `Dropped` marker if events were lost since the
+    /// last drain. The marker is sent ahead of the events.
+    fn drain(&mut self) -> (Option<serde_json::Value>, VecDeque<SessionEvent>) {
+        let dropped = std::mem::take(&mut self.dropped);
+        let marker = (dropped > 0)
+            .then(|| serde_json::json!({ "type": "Dropped", "data": { "count": dropped } }));
+        (marker, std::mem::take(&mut self.events))
+    }
+}
+
+/// Wire encoding for one WebSocket connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WsEncoding {
+    /// UTF-8 JSON in text frames.
+    #[default]
+    Json,
+    /// MessagePack (named fields) in binary frames.
+    Msgpack,
+}
+
+impl WsEncoding {
+    const PROTOCOLS: [&'static str; 2] = ["json", "msgpack"];
+
+    /// First supported entry of a `Sec-WebSocket-Protocol` offer, in client preference order.
+    fn from_protocol_header(headers: &HeaderMap) -> Option<Self> {
+        let offered = headers.get(axum::http::header::SEC_WEBSOCKET_PROTOCOL)?.to_str().ok()?;
+        offered.split(',').find_map(|p| match p.trim() {
+            "json" => Some(Self::Json),
+            "msgpack" => Some(Self::Msgpack),
+            _ => None,
+        })
+    }
+
+    fn encode<T: Serialize>(self, value: &T) -> Option<axum::extract::ws::Message> {
+        use axum::extract::ws::Message;
+        match self {
+            Self::Json => serde_json::to_string(value).ok().map(Message::Text),
+            Self::Msgpack => rmp_serde::to_vec_named(value).ok().map(Message::Binary),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WsQuery {
+    pub encoding: Option<WsEncoding>,
+}
+
+/// The `encoding` query param wins over a `Sec-WebSocket-Protocol` of `json` or `msgpack`;
+/// without either the stream stays JSON.
 pub async fn session_ws(
     ws: WebSocketUpgrade,
     State(state): State<AppState>,
+    Query(q): Query<WsQuery>,
+    headers: HeaderMap,
 ) -> Response {
-    ws.on_upgrade(move |socket| async move {
-        use axum::extract::ws::{Message, WebSocket};
-        use tokio::select;
+    let encoding = q
+        .encoding
+        .or_else(|| WsEncoding::from_protocol_header(&headers))
+        .unwrap_or_default();
+
+    // `protocols` echoes the client's first supported subprotocol back in the handshake.
+    ws.protocols(WsEncoding::PROTOCOLS).on_upgrade(move |socket| async move {
 
+        let queue = Arc::new((Mutex::new(ClientQueue::default()), Notify::new()));
         let mut rx = state.tx_events.subscribe();
-        let (mut sender, mut _receiver) = socket.split();
+        let (mut sender, _receiver) = socket.split();
 
-        loop {
-            let evt = match rx.recv().await {
-                Ok(e) => e,
-                Err(_) => break,
-            };
+        let forwarder = {
+            let queue = queue.clone();
+            let dropped_metric = state.ws_dropped_events.clone();
+            tokio::spawn(async move {
+                let (lock, notify) = &*queue;
+                loop {
+                    let result = rx.recv().await;
+                    let mut q = lock.lock().unwrap();
+                    match result {
+                        Ok(evt) => {
+                            if q.push(evt) {
+                                dropped_metric.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            q.dropped += n;
+                            dropped_metric.fetch_add(n, Ordering::Relaxed);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            q.closed = true;
+                            notify.notify_one();
+                            break;
+                        }
+                    }
+                    drop(q);
+                    notify.notify_one();
+                }
+            })
+        };
 
-            let json = match serde_json::to_string(&evt) {
-                Ok(j) => j,
-                Err(_) => continue,
-            };
+        // Counted as in-flight so a stuck client cannot hold up shutdown past its grace period.
+        state
+            .in_flight
+            .run(async {
+                let (lock, notify) = &*queue;
+                'conn: loop {
+                    notify.notified().await;
+                    let (marker, events, closed) = {
+                        let mut q = lock.lock().unwrap();
+                        let (marker, events) = q.drain();
+                        (marker, events, q.closed)
+                    };
 
-            if sender
-                .send(Message::Text(json))
-                .await
-                .is_err()
-            {
-                break;
-            }
+                    if let Some(marker) = marker {
+                        if let Some(msg) = encoding.encode(&marker) {
+                            if sender.send(msg).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
 
-            select! {
-                // Could listen for client messages here if desired.
-                default => {}
-            }
-        }
+                    for evt in events {
+                        let Some(msg) = encoding.encode(&evt) else {
+                            continue;
+                        };
+                        if sender.send(msg).await.is_err() {
+                            break 'conn;
+                        }
+                    }
+
+                    if closed {
+                        break;
+                    }
+                }
+            })
+            .await;
+
+        forwarder.abort();
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_store::{InMemorySessionStore, KeyRecoveryAttempt};
+    use axum::http::HeaderValue;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn admin_check_ignores_bearer_tokens() {
+        let cfg = Config::for_tests();
+        let now = chrono::Utc::now();
+        let token = auth::issue_session_token(
+            &Pubkey::new_unique().to_string(),
+            Uuid::new_v4(),
+            &[auth::SessionPermission::Deposit, auth::SessionPermission::Read],
+            &cfg.security.jwt_secret,
+            now,
+            now + chrono::Duration::hours(1),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        assert!(!is_admin(&headers, &cfg));
+
+        headers.insert("x-admin-token", HeaderValue::from_static("wrong"));
+        assert!(!is_admin(&headers, &cfg));
+
+        headers.insert("x-admin-token", HeaderValue::from_static("test-admin-token"));
+        assert!(is_admin(&headers, &cfg));
+    }
+
+    fn session_of(parent_wallet: &str) -> Session {
+        let now = chrono::Utc::now();
+        Session {
+            id: Uuid::new_v4(),
+            parent_wallet: parent_wallet.to_string(),
+            ephemeral_wallet: Pubkey::new_unique().to_string(),
+            vault_pubkey: None,
+            status: SessionStatus::Active,
+            session_start: now,
+            session_expiry: now + chrono::Duration::hours(1),
+            last_activity: now,
+            max_deposit: 1_000_000,
+            total_deposited: 0,
+            total_spent: 0,
+            key_fingerprint: String::new(),
+            server_managed_key: true,
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn batch_reports_other_parents_sessions_as_missing() {
+        let caller = auth::AuthenticatedParent(Pubkey::new_unique().to_string());
+        let own = session_of(&caller.0);
+        let foreign = session_of(&Pubkey::new_unique().to_string());
+        let unknown = Uuid::new_v4();
+
+        let response = SessionsBatchResponse::for_caller(
+            &caller,
+            vec![own.id, foreign.id, unknown],
+            vec![own.clone(), foreign.clone()],
+        );
+        let returned: Vec<Uuid> = response.sessions.iter().map(|s| s.id).collect();
+        assert_eq!(returned, vec![own.id]);
+        assert_eq!(response.missing, vec![foreign.id, unknown]);
+    }
+
+    /// An in-memory manager holding one server-managed session of `parent`, with a recovery
+    /// nonce issued to it.
+    async fn recoverable_session(
+        parent: &Keypair,
+    ) -> (SessionManager<InMemorySessionStore>, Session, Keypair, String) {
+        let sm = SessionManager::with_store(InMemorySessionStore::new(), Config::for_tests());
+        let (session, ephemeral) = sm
+            .create_session(parent.pubkey(), 3600, 1_000_000, None, None, None)
+            .await
+            .unwrap();
+        let nonce = auth::new_nonce();
+        let expires_at = chrono::Utc::now() + chrono::Duration::seconds(auth::CHALLENGE_TTL_SECS);
+        sm.create_auth_challenge(&session.parent_wallet, &nonce, expires_at)
+            .await
+            .unwrap();
+        (sm, session, ephemeral.unwrap(), nonce)
+    }
+
+    fn recovery_request(session_id: Uuid, nonce: &str, signer: &Keypair) -> RecoverKeyRequest {
+        let message = recover_key_challenge(session_id, nonce);
+        RecoverKeyRequest {
+            session_id,
+            nonce: Some(nonce.to_string()),
+            signature: Some(signer.sign_message(message.as_bytes()).to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn parent_signed_recovery_returns_the_key_once() {
+        let parent = Keypair::new();
+        let (sm, session, ephemeral, nonce) = recoverable_session(&parent).await;
+        let limiter = RateLimiter::new(3, Duration::from_secs(3600));
+        let req = recovery_request(session.id, &nonce, &parent);
+
+        let resp = recover_key_with(&sm, &limiter, false, &req).await.unwrap();
+        assert_eq!(resp.ephemeral_wallet, session.ephemeral_wallet);
+        assert_eq!(resp.secret_key, ephemeral.to_bytes().to_vec());
+
+        // The nonce is spent, so replaying the signed request recovers nothing.
+        let err = recover_key_with(&sm, &limiter, false, &req).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+
+        let audit = sm.store().key_recoveries();
+        assert_eq!(audit.len(), 2);
+        assert!(audit[0].authorized);
+        assert_eq!(audit[0].requester, session.parent_wallet);
+        assert!(!audit[1].authorized);
+    }
+
+    #[tokio::test]
+    async fn recovery_signed_by_another_wallet_is_rejected_and_audited() {
+        let parent = Keypair::new();
+        let (sm, session, _, nonce) = recoverable_session(&parent).await;
+        let limiter = RateLimiter::new(1, Duration::from_secs(3600));
+
+        let forged = recovery_request(session.id, &nonce, &Keypair::new());
+        let err = recover_key_with(&sm, &limiter, false, &forged).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+        assert_eq!(
+            sm.store().key_recoveries(),
+            vec![KeyRecoveryAttempt {
+                session_id: session.id,
+                requester: session.parent_wallet.clone(),
+                authorized: false,
+                reason: "bad signature".into(),
+            }]
+        );
+
+        // Neither the parent's nonce nor its rate limit was spent by the forgery.
+        let req = recovery_request(session.id, &nonce, &parent);
+        assert!(recover_key_with(&sm, &limiter, false, &req).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn recovery_is_rate_limited_per_requester() {
+        let parent = Keypair::new();
+        let (sm, session, _, _) = recoverable_session(&parent).await;
+        let limiter = RateLimiter::new(1, Duration::from_secs(3600));
+        let req = RecoverKeyRequest {
+            session_id: session.id,
+            nonce: None,
+            signature: None,
+        };
+
+        assert!(recover_key_with(&sm, &limiter, true, &req).await.is_ok());
+        let err = recover_key_with(&sm, &limiter, true, &req).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::RateLimited);
+        let last = sm.store().key_recoveries().pop().unwrap();
+        assert_eq!((last.requester.as_str(), last.reason.as_str()), ("admin", "rate limited"));
+    }
+
+    /// An in-memory manager with a login challenge issued to `parent`, and the nonce.
+    async fn login_challenge_for(
+        parent: &Keypair,
+    ) -> (SessionManager<InMemorySessionStore>, String) {
+        let sm = SessionManager::with_store(InMemorySessionStore::new(), Config::for_tests());
+        let nonce = auth::new_nonce();
+        let expires_at = chrono::Utc::now() + chrono::Duration::seconds(auth::CHALLENGE_TTL_SECS);
+        sm.create_auth_challenge(&parent.pubkey().to_string(), &nonce, expires_at)
+            .await
+            .unwrap();
+        (sm, nonce)
+    }
+
+    fn login_request(parent_wallet: &Pubkey, nonce: &str, signer: &Keypair) -> AuthVerifyRequest {
+        let message = auth::login_challenge(&parent_wallet.to_string(), nonce);
+        AuthVerifyRequest {
+            parent_wallet: parent_wallet.to_string(),
+            nonce: nonce.to_string(),
+            signature: signer.sign_message(message.as_bytes()).to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn login_mints_a_parent_token_once_per_challenge() {
+        let cfg = Config::for_tests();
+        let parent = Keypair::new();
+        let (sm, nonce) = login_challenge_for(&parent).await;
+        let req = login_request(&parent.pubkey(), &nonce, &parent);
+
+        let (token, _) = verify_login(&sm, &cfg.security.jwt_secret, &req).await.unwrap();
+        let claims = auth::TokenVerifier::new(&cfg.security.jwt_secret)
+            .verify(&token)
+            .unwrap();
+        assert_eq!(claims.sub, parent.pubkey().to_string());
+        assert_eq!(claims.scope, auth::PARENT_SCOPE);
+
+        let err = verify_login(&sm, &cfg.security.jwt_secret, &req).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+        assert!(!sm
+            .consume_auth_challenge(&parent.pubkey().to_string(), &nonce)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn login_rejects_a_signature_from_another_wallet() {
+        let cfg = Config::for_tests();
+        let parent = Keypair::new();
+        let (sm, nonce) = login_challenge_for(&parent).await;
+
+        let forged = login_request(&parent.pubkey(), &nonce, &Keypair::new());
+        let err = verify_login(&sm, &cfg.security.jwt_secret, &forged).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+
+        // A wallet cannot redeem a challenge issued to another, even signing for itself.
+        let intruder = Keypair::new();
+        let hijack = login_request(&intruder.pubkey(), &nonce, &intruder);
+        let err = verify_login(&sm, &cfg.security.jwt_secret, &hijack).await.unwrap_err();
+        assert_eq!(err.code, ErrorCode::Unauthorized);
+
+        // Neither attempt burned the parent's challenge.
+        let req = login_request(&parent.pubkey(), &nonce, &parent);
+        assert!(verify_login(&sm, &cfg.security.jwt_secret, &req).await.is_ok());
+    }
+
+    #[test]
+    fn full_client_queue_drops_its_oldest_events_and_reports_the_count() {
+        let sessions: Vec<Session> = (0..WS_CLIENT_QUEUE_CAPACITY + 3)
+            .map(|_| session_of(&Pubkey::new_unique().to_string()))
+            .collect();
+        let mut queue = ClientQueue::default();
+        let overflowed: Vec<bool> =
+            sessions.iter().map(|s| queue.push(SessionEvent::Created(s.clone()))).collect();
+        assert!(overflowed[..WS_CLIENT_QUEUE_CAPACITY].iter().all(|o| !o));
+        assert!(overflowed[WS_CLIENT_QUEUE_CAPACITY..].iter().all(|o| *o));
+
+        let (marker, events) = queue.drain();
+        assert_eq!(marker, Some(serde_json::json!({ "type": "Dropped", "data": { "count": 3 } })));
+        let ids: Vec<Uuid> = events
+            .iter()
+            .map(|evt| match evt {
+                SessionEvent::Created(s) => s.id,
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect();
+        let newest: Vec<Uuid> = sessions[3..].iter().map(|s| s.id).collect();
+        assert_eq!(ids, newest);
+
+        // The count resets once reported.
+        queue.push(SessionEvent::Created(sessions[0].clone()));
+        let (marker, events) = queue.drain();
+        assert_eq!(marker, None);
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn missing_accounts_map_to_404_and_rpc_failures_to_502() {
+        use solana_client::client_error::{ClientError, ClientErrorKind};
+
+        let missing = DelegationError::AccountNotFound(Pubkey::new_unique());
+        let err = delegation_error(&missing);
+        assert_eq!(err.code, ErrorCode::DelegationNotFound);
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+
+        let unreachable = ClientError::from(ClientErrorKind::Custom("connection refused".into()));
+        let err = delegation_error(&DelegationError::Rpc(unreachable));
+        assert_eq!(err.code, ErrorCode::RpcUnavailable);
+        assert_eq!(err.into_response().status(), StatusCode::BAD_GATEWAY);
+
+        // The same mapping applies once the error has passed through `anyhow`.
+        let wrapped = anyhow::Error::new(missing).context("resuming bootstrap");
+        assert_eq!(bootstrap_error(&wrapped).code, ErrorCode::DelegationNotFound);
+    }
+
+    #[tokio::test]
+    async fn health_reports_a_semver_version() {
+        // Lazy pool: `/health` never touches the database.
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/evs_test")
+            .unwrap();
+        let state = AppState::new(db, None, Config::for_tests()).await.unwrap();
+
+        let response = health(State(state)).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["status"], "ok");
+        let version = body["version"].as_str().unwrap();
+        let core = version.split(['-', '+']).next().unwrap();
+        let parts: Vec<&str> = core.split('.').collect();
+        assert_eq!(parts.len(), 3, "{version} is not MAJOR.MINOR.PATCH");
+        assert!(parts.iter().all(|p| !p.is_empty() && p.parse::<u64>().is_ok()), "{version}");
+        assert!(body["git_commit"].is_string());
+        assert!(body["uptime_secs"].is_u64());
+    }
+}
+
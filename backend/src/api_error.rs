@@ -0,0 +1,210 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+/// Stable, machine-readable error codes returned in every API error body. Clients should
+/// switch on `code` rather than on the HTTP status or the free-form `message`. Codes are
+/// only ever added, never renamed or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidWallet,
+    InvalidRequest,
+    PayloadTooLarge,
+    SessionNotFound,
+    InvalidSessionState,
+    SessionExpired,
+    ClientManagedKey,
+    EphemeralWalletTaken,
+    DelegationNotFound,
+    InvalidDelegation,
+    Unauthorized,
+    Forbidden,
+    RateLimited,
+    Overloaded,
+    RpcUnavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    /// Every code, in registry order. Served by `GET /errors`.
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::InvalidWallet,
+        ErrorCode::InvalidRequest,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::SessionNotFound,
+        ErrorCode::InvalidSessionState,
+        ErrorCode::SessionExpired,
+        ErrorCode::ClientManagedKey,
+        ErrorCode::EphemeralWalletTaken,
+        ErrorCode::DelegationNotFound,
+        ErrorCode::InvalidDelegation,
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::RateLimited,
+        ErrorCode::Overloaded,
+        ErrorCode::RpcUnavailable,
+        ErrorCode::Internal,
+    ];
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidWallet | ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::SessionNotFound | ErrorCode::DelegationNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::InvalidSessionState
+            | ErrorCode::SessionExpired
+            | ErrorCode::ClientManagedKey
+            | ErrorCode::EphemeralWalletTaken
+            | ErrorCode::InvalidDelegation => StatusCode::CONFLICT,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::RpcUnavailable => StatusCode::BAD_GATEWAY,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidWallet => {
+                "A wallet or vault address is not a valid base58 pubkey, or an ephemeral wallet is a PDA."
+            }
+            ErrorCode::InvalidRequest => "The request body is not valid JSON of the expected shape.",
+            ErrorCode::PayloadTooLarge => "A batch request holds more items than the server accepts.",
+            ErrorCode::SessionNotFound => "No session exists with the given id.",
+            ErrorCode::InvalidSessionState => {
+                "The session's current status does not allow this operation."
+            }
+            ErrorCode::SessionExpired => "The session window has already lapsed.",
+            ErrorCode::ClientManagedKey => {
+                "The session's ephemeral key is held by the client; the server cannot use it."
+            }
+            ErrorCode::EphemeralWalletTaken => {
+                "The ephemeral wallet is already registered to another session."
+            }
+            ErrorCode::DelegationNotFound => "The vault or delegation account does not exist on-chain.",
+            ErrorCode::InvalidDelegation => {
+                "The on-chain delegation does not match the session or has been revoked."
+            }
+            ErrorCode::Unauthorized => "Missing, invalid or expired bearer token, admin token or parent signature.",
+            ErrorCode::Forbidden => {
+                "The session token does not grant this operation on this session."
+            }
+            ErrorCode::RateLimited => "Too many attempts; retry later.",
+            ErrorCode::Overloaded => "The server is at its write concurrency limit; retry later.",
+            ErrorCode::RpcUnavailable => "The Solana RPC node could not be reached.",
+            ErrorCode::Internal => "Unexpected server error.",
+        }
+    }
+}
+
+/// Error body for every API handler: `{ "code": "...", "message": "..." }` with the status
+/// given by `ErrorCode::status`.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<ErrorCode> for ApiError {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code, code.description())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.code.status(), Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorCodeEntry {
+    pub code: ErrorCode,
+    pub status: u16,
+    pub description: &'static str,
+}
+
+/// `GET /errors`: the registry of error codes clients may receive.
+pub async fn error_registry() -> Json<Vec<ErrorCodeEntry>> {
+    Json(
+        ErrorCode::ALL
+            .iter()
+            .map(|&code| ErrorCodeEntry {
+                code,
+                status: code.status().as_u16(),
+                description: code.description(),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn registry_maps_every_code_to_its_status() {
+        let expected = [
+            ("INVALID_WALLET", 400),
+            ("INVALID_REQUEST", 400),
+            ("PAYLOAD_TOO_LARGE", 413),
+            ("SESSION_NOT_FOUND", 404),
+            ("INVALID_SESSION_STATE", 409),
+            ("SESSION_EXPIRED", 409),
+            ("CLIENT_MANAGED_KEY", 409),
+            ("EPHEMERAL_WALLET_TAKEN", 409),
+            ("DELEGATION_NOT_FOUND", 404),
+            ("INVALID_DELEGATION", 409),
+            ("UNAUTHORIZED", 401),
+            ("FORBIDDEN", 403),
+            ("RATE_LIMITED", 429),
+            ("OVERLOADED", 503),
+            ("RPC_UNAVAILABLE", 502),
+            ("INTERNAL", 500),
+        ];
+        let actual: Vec<(String, u16)> = ErrorCode::ALL
+            .iter()
+            .map(|code| {
+                let name = serde_json::to_value(code).unwrap();
+                (name.as_str().unwrap().to_string(), code.status().as_u16())
+            })
+            .collect();
+        let expected: Vec<(String, u16)> = expected
+            .iter()
+            .map(|&(name, status)| (name.to_string(), status))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn registry_lists_each_code_once_with_a_description() {
+        let unique: HashSet<_> = ErrorCode::ALL
+            .iter()
+            .map(|code| serde_json::to_string(code).unwrap())
+            .collect();
+        assert_eq!(unique.len(), ErrorCode::ALL.len());
+        assert!(ErrorCode::ALL.iter().all(|code| !code.description().is_empty()));
+    }
+
+    #[test]
+    fn api_error_uses_the_code_status() {
+        let response = ApiError::from(ErrorCode::Forbidden).into_response();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,356 @@
+//! Wallet-native login: a parent proves ownership of its wallet by signing a single-use
+//! server nonce and receives a JWT scoped to its own sessions, which `require_parent_token`
+//! checks on every `/session/*` route. A parent can also mint narrower session tokens for
+//! one session and a few permissions, which `require_session_access` admits on the routes
+//! they can reach.
+
+use crate::api_error::{ApiError, ErrorCode};
+use anyhow::Result;
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// How long a login challenge can be redeemed after it is issued.
+pub const CHALLENGE_TTL_SECS: i64 = 120;
+
+/// Lifetime of a token minted by `POST /auth/verify`.
+pub const TOKEN_TTL_SECS: i64 = 3600;
+
+/// Scope granted to wallet-login tokens: the parent's own sessions.
+pub const PARENT_SCOPE: &str = "parent:sessions";
+
+/// Scope of tokens minted by `POST /session/token`: one session, listed permissions only.
+pub const SESSION_SCOPE: &str = "session";
+
+/// What a session token may do with its session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPermission {
+    /// Prepare top-ups with `POST /session/deposit`.
+    Deposit,
+    /// Read the session with `GET /session/status` and `GET /session/changes`.
+    Read,
+}
+
+/// JWT claims. `sub` is the parent wallet, base58. Session tokens also carry the session
+/// they are limited to and their permissions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub scope: String,
+    pub iat: i64,
+    pub exp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<SessionPermission>,
+}
+
+/// A fresh random nonce, URL-safe base64 of 32 bytes.
+pub fn new_nonce() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Message the parent must sign to redeem `nonce`. Binding the wallet in keeps a signature
+/// from being replayed as a login for another wallet.
+pub fn login_challenge(parent_wallet: &str, nonce: &str) -> String {
+    format!("evs-login:{parent_wallet}:{nonce}")
+}
+
+/// Signs an HS256 token for `parent_wallet` with `secret`, valid for `TOKEN_TTL_SECS`.
+pub fn issue_token(
+    parent_wallet: &str,
+    secret: &str,
+    now: DateTime<Utc>,
+) -> Result<(String, DateTime<Utc>)> {
+    let expires_at = now + Duration::seconds(TOKEN_TTL_SECS);
+    let claims = Claims {
+        sub: parent_wallet.to_string(),
+        scope: PARENT_SCOPE.to_string(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+        session_id: None,
+        permissions: Vec::new(),
+    };
+    Ok((encode_claims(&claims, secret)?, expires_at))
+}
+
+/// Signs an HS256 [`SESSION_SCOPE`] token for `parent_wallet`, limited to `session_id` and
+/// `permissions` and valid until `expires_at`.
+pub fn issue_session_token(
+    parent_wallet: &str,
+    session_id: Uuid,
+    permissions: &[SessionPermission],
+    secret: &str,
+    now: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+) -> Result<String> {
+    let claims = Claims {
+        sub: parent_wallet.to_string(),
+        scope: SESSION_SCOPE.to_string(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+        session_id: Some(session_id),
+        permissions: permissions.to_vec(),
+    };
+    encode_claims(&claims, secret)
+}
+
+fn encode_claims(claims: &Claims, secret: &str) -> Result<String> {
+    Ok(jsonwebtoken::encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// Checks HS256 tokens minted by [`issue_token`] with the same secret.
+pub struct TokenVerifier {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl TokenVerifier {
+    pub fn new(secret: &str) -> Self {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        Self {
+            key: DecodingKey::from_secret(secret.as_bytes()),
+            validation,
+        }
+    }
+
+    /// The token's claims, if its signature is valid and it has not expired.
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        Ok(jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)?.claims)
+    }
+}
+
+/// The parent wallet, base58, a request's bearer token was issued to. Inserted by
+/// [`require_parent_token`] and [`require_session_access`] for handlers to extract.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedParent(pub String);
+
+/// The limits of a session token. Inserted next to [`AuthenticatedParent`] by
+/// [`require_session_access`] when the caller used one; absent for parent tokens.
+#[derive(Debug, Clone)]
+pub struct SessionGrant {
+    pub session_id: Uuid,
+    pub permissions: Vec<SessionPermission>,
+}
+
+/// Passes parent callers (no grant) and session tokens that cover `permission` on
+/// `session_id`; anything else is `403`.
+pub fn check_grant(
+    grant: Option<&SessionGrant>,
+    session_id: Uuid,
+    permission: SessionPermission,
+) -> Result<(), ApiError> {
+    match grant {
+        None => Ok(()),
+        Some(grant) if grant.session_id != session_id => Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "token is limited to another session",
+        )),
+        Some(grant) if !grant.permissions.contains(&permission) => Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "token lacks the permission for this operation",
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// The verified claims of the request's `Authorization: Bearer <jwt>`, or the `401` to send.
+fn bearer_claims(verifier: &TokenVerifier, req: &Request) -> Result<Claims, Response> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let Some(token) = token else {
+        return Err(ApiError::new(ErrorCode::Unauthorized, "missing bearer token").into_response());
+    };
+    verifier.verify(token).map_err(|_| {
+        ApiError::new(ErrorCode::Unauthorized, "invalid or expired token").into_response()
+    })
+}
+
+/// Middleware requiring `Authorization: Bearer <jwt>` with [`PARENT_SCOPE`]. Missing,
+/// malformed, expired or wrongly scoped tokens, session tokens included, are rejected with
+/// `401`.
+pub async fn require_parent_token(
+    State(verifier): State<Arc<TokenVerifier>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let claims = match bearer_claims(&verifier, &req) {
+        Ok(claims) if claims.scope == PARENT_SCOPE => claims,
+        Ok(_) => {
+            return ApiError::new(ErrorCode::Unauthorized, "token lacks the parent scope")
+                .into_response()
+        }
+        Err(response) => return response,
+    };
+    req.extensions_mut().insert(AuthenticatedParent(claims.sub));
+    next.run(req).await
+}
+
+/// Like [`require_parent_token`], but also admits [`SESSION_SCOPE`] tokens, adding their
+/// [`SessionGrant`] for the handler to enforce with [`check_grant`].
+pub async fn require_session_access(
+    State(verifier): State<Arc<TokenVerifier>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let claims = match bearer_claims(&verifier, &req) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    match (claims.scope.as_str(), claims.session_id) {
+        (PARENT_SCOPE, _) => {}
+        (SESSION_SCOPE, Some(session_id)) => {
+            req.extensions_mut().insert(SessionGrant {
+                session_id,
+                permissions: claims.permissions,
+            });
+        }
+        _ => {
+            return ApiError::new(ErrorCode::Unauthorized, "token lacks a session scope")
+                .into_response()
+        }
+    }
+    req.extensions_mut().insert(AuthenticatedParent(claims.sub));
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Extension, Router};
+    use std::future::poll_fn;
+    use tower::Service;
+
+    const SECRET: &str = "test-jwt-secret";
+    const PARENT: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+
+    fn grant(session_id: Uuid, permissions: &[SessionPermission]) -> SessionGrant {
+        SessionGrant {
+            session_id,
+            permissions: permissions.to_vec(),
+        }
+    }
+
+    fn session_token(session_id: Uuid, permissions: &[SessionPermission]) -> String {
+        let now = Utc::now();
+        issue_session_token(PARENT, session_id, permissions, SECRET, now, now + Duration::hours(1))
+            .unwrap()
+    }
+
+    fn parent_token() -> String {
+        issue_token(PARENT, SECRET, Utc::now()).unwrap().0
+    }
+
+    /// Status of `GET /` on `router` with `token` as the bearer.
+    async fn status_with_token(mut router: Router, token: &str) -> StatusCode {
+        let req = axum::http::Request::builder()
+            .uri("/")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        poll_fn(|cx| Service::<Request>::poll_ready(&mut router, cx))
+            .await
+            .unwrap();
+        router.call(req).await.unwrap().status()
+    }
+
+    #[test]
+    fn check_grant_admits_parent_callers() {
+        assert!(check_grant(None, Uuid::new_v4(), SessionPermission::Deposit).is_ok());
+    }
+
+    #[test]
+    fn check_grant_admits_deposit_to_own_session() {
+        let session_id = Uuid::new_v4();
+        let grant = grant(session_id, &[SessionPermission::Deposit]);
+        assert!(check_grant(Some(&grant), session_id, SessionPermission::Deposit).is_ok());
+    }
+
+    #[test]
+    fn check_grant_rejects_other_session() {
+        let grant = grant(Uuid::new_v4(), &[SessionPermission::Deposit]);
+        let err = check_grant(Some(&grant), Uuid::new_v4(), SessionPermission::Deposit)
+            .unwrap_err();
+        assert_eq!(err.code, ErrorCode::Forbidden);
+    }
+
+    #[test]
+    fn check_grant_rejects_missing_permission() {
+        let session_id = Uuid::new_v4();
+        let grant = grant(session_id, &[SessionPermission::Read]);
+        let err = check_grant(Some(&grant), session_id, SessionPermission::Deposit).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Forbidden);
+    }
+
+    #[test]
+    fn session_token_claims_round_trip() {
+        let session_id = Uuid::new_v4();
+        let token = session_token(session_id, &[SessionPermission::Deposit]);
+        let claims = TokenVerifier::new(SECRET).verify(&token).unwrap();
+        assert_eq!(claims.sub, PARENT);
+        assert_eq!(claims.scope, SESSION_SCOPE);
+        assert_eq!(claims.session_id, Some(session_id));
+        assert_eq!(claims.permissions, vec![SessionPermission::Deposit]);
+        assert!(TokenVerifier::new("another-secret").verify(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn require_parent_token_rejects_session_tokens() {
+        let verifier = Arc::new(TokenVerifier::new(SECRET));
+        let router = Router::new()
+            .route("/", get(|| async {}))
+            .route_layer(middleware::from_fn_with_state(verifier, require_parent_token));
+
+        let token = session_token(Uuid::new_v4(), &[SessionPermission::Deposit]);
+        assert_eq!(
+            status_with_token(router.clone(), &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status_with_token(router, &parent_token()).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn require_session_access_attaches_the_grant() {
+        let verifier = Arc::new(TokenVerifier::new(SECRET));
+        let router = Router::new()
+            .route(
+                "/",
+                get(|grant: Option<Extension<SessionGrant>>| async move {
+                    if grant.is_some() {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::NO_CONTENT
+                    }
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(verifier, require_session_access));
+
+        let token = session_token(Uuid::new_v4(), &[SessionPermission::Read]);
+        assert_eq!(status_with_token(router.clone(), &token).await, StatusCode::OK);
+        assert_eq!(
+            status_with_token(router, &parent_token()).await,
+            StatusCode::NO_CONTENT
+        );
+    }
+}
@@ -1,30 +1,312 @@
+use crate::{
+    config::{FeeOracleKind, SolanaConfig},
+    program_accounts::{DELEGATION_ACCOUNT_SPACE, VAULT_ACCOUNT_SPACE},
+};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
 
+/// Serialized as the variant name (`"Low"`, ..., `"Critical"`); renaming a variant breaks
+/// the API.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum PriorityLevel {
     Low,
     Medium,
     High,
+    /// For orders that must land in the next block, e.g. MEV-sensitive ones.
+    Critical,
 }
 
-pub struct AutoDepositCalculator;
+/// Source of the lamports one trade is expected to cost at a given priority.
+pub trait FeeOracle: Send + Sync {
+    fn per_trade_fee(&self, priority: PriorityLevel) -> Result<u64>;
+}
+
+/// Fixed per-priority fees; needs no network access.
+pub struct StaticFeeOracle;
 
-impl AutoDepositCalculator {
-    pub fn estimate_fee_per_trade(priority: PriorityLevel) -> u64 {
-        // Very rough constants for demonstration; a production system would fetch
-        // recent fee parameters from the Solana RPC and add a safety margin.
-        match priority {
+impl FeeOracle for StaticFeeOracle {
+    fn per_trade_fee(&self, priority: PriorityLevel) -> Result<u64> {
+        // Very rough constants for demonstration.
+        Ok(match priority {
             PriorityLevel::Low => 5_000,      // lamports
             PriorityLevel::Medium => 10_000,  // lamports
             PriorityLevel::High => 25_000,    // lamports
-        }
+            PriorityLevel::Critical => 50_000, // lamports
+        })
+    }
+}
+
+/// Lamports per signature; every trade transaction pays it once.
+const BASE_SIGNATURE_FEE_LAMPORTS: u64 = 5_000;
+/// Compute units a trade transaction is budgeted for when pricing its priority fee.
+const TRADE_COMPUTE_UNITS: u64 = 200_000;
+
+/// Base fee plus a priority fee priced from the node's recent prioritization fees
+/// (micro-lamports per compute unit): the 25th, 50th, 90th or 99th percentile for low,
+/// medium, high or critical priority. The result is scaled by `margin_bps` (10,000 = no margin) to absorb fees
+/// rising between estimate and trade. If the RPC call fails, the `StaticFeeOracle`
+/// constants are used instead, with the same margin.
+///
+/// The client is blocking; async callers run the oracle inside `RpcPool::call_blocking`.
+pub struct RpcFeeOracle<'a> {
+    rpc: &'a RpcClient,
+    margin_bps: u32,
+}
+
+impl<'a> RpcFeeOracle<'a> {
+    pub fn new(rpc: &'a RpcClient, margin_bps: u32) -> Self {
+        Self { rpc, margin_bps }
+    }
+
+    fn live_fee(&self, priority: PriorityLevel) -> Result<u64> {
+        let mut fees: Vec<u64> = self
+            .rpc
+            .get_recent_prioritization_fees(&[])?
+            .into_iter()
+            .map(|f| f.prioritization_fee)
+            .collect();
+        fees.sort_unstable();
+
+        let percentile = match priority {
+            PriorityLevel::Low => 25,
+            PriorityLevel::Medium => 50,
+            PriorityLevel::High => 90,
+            PriorityLevel::Critical => 99,
+        };
+        let micro_lamports_per_cu = match fees.len() {
+            0 => 0,
+            n => fees[(n - 1) * percentile / 100],
+        };
+        let priority_fee = micro_lamports_per_cu
+            .checked_mul(TRADE_COMPUTE_UNITS)
+            .map(|micro| micro.div_ceil(1_000_000))
+            .ok_or_else(|| anyhow::anyhow!("priority fee overflow"))?;
+        BASE_SIGNATURE_FEE_LAMPORTS
+            .checked_add(priority_fee)
+            .ok_or_else(|| anyhow::anyhow!("fee calculation overflow"))
+    }
+}
+
+impl FeeOracle for RpcFeeOracle<'_> {
+    fn per_trade_fee(&self, priority: PriorityLevel) -> Result<u64> {
+        let fee = match self.live_fee(priority) {
+            Ok(fee) => fee,
+            Err(err) => {
+                tracing::warn!(error = %err, "prioritization fees unavailable; using static fees");
+                StaticFeeOracle.per_trade_fee(priority)?
+            }
+        };
+        fee.checked_mul(u64::from(self.margin_bps))
+            .map(|scaled| scaled.div_ceil(10_000))
+            .ok_or_else(|| anyhow::anyhow!("fee calculation overflow"))
+    }
+}
+
+/// The oracle selected by `EVS_FEE_ORACLE`, querying `rpc` if it needs the network.
+pub fn fee_oracle_from_config<'a>(
+    cfg: &SolanaConfig,
+    rpc: &'a RpcClient,
+) -> Box<dyn FeeOracle + 'a> {
+    match cfg.fee_oracle {
+        FeeOracleKind::Static => Box::new(StaticFeeOracle),
+        FeeOracleKind::Rpc => Box::new(RpcFeeOracle::new(rpc, cfg.fee_margin_bps)),
+    }
+}
+
+pub struct AutoDepositCalculator<'a> {
+    oracle: &'a dyn FeeOracle,
+}
+
+impl<'a> AutoDepositCalculator<'a> {
+    pub fn new(oracle: &'a dyn FeeOracle) -> Self {
+        Self { oracle }
     }
 
-    pub fn compute_deposit_for_trades(num_trades: u64, priority: PriorityLevel) -> Result<u64> {
-        let per_trade = Self::estimate_fee_per_trade(priority);
+    pub fn estimate_fee_per_trade(&self, priority: PriorityLevel) -> Result<u64> {
+        self.oracle.per_trade_fee(priority)
+    }
+
+    /// Fees for `num_trades` trades at `priority`, inflated by `safety_margin_bps` (e.g. 500
+    /// adds 5%) and rounded up.
+    pub fn compute_deposit_for_trades(
+        &self,
+        num_trades: u64,
+        priority: PriorityLevel,
+        safety_margin_bps: u16,
+    ) -> Result<u64> {
+        let per_trade = self.estimate_fee_per_trade(priority)?;
         num_trades
             .checked_mul(per_trade)
+            .and_then(|total| total.checked_mul(10_000 + u64::from(safety_margin_bps)))
+            .map(|scaled| scaled.div_ceil(10_000))
             .ok_or_else(|| anyhow::anyhow!("fee calculation overflow"))
     }
 }
+
+/// All-in lamport cost of opening a session, broken down by component.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionCostEstimate {
+    #[serde(with = "crate::string_u64")]
+    pub vault_rent_lamports: u64,
+    #[serde(with = "crate::string_u64")]
+    pub delegation_rent_lamports: u64,
+    #[serde(with = "crate::string_u64")]
+    pub trade_fee_deposit_lamports: u64,
+    #[serde(with = "crate::string_u64")]
+    pub total_lamports: u64,
+}
+
+impl SessionCostEstimate {
+    /// Sums the rent-exempt minimums for the vault and delegation accounts (from the RPC)
+    /// with the buffered fee deposit for `num_trades` trades at `priority`, priced by
+    /// `calculator` and inflated by `safety_margin_bps`.
+    ///
+    /// Makes blocking RPC calls; async callers run it inside `RpcPool::call_blocking`.
+    pub fn estimate(
+        rpc: &RpcClient,
+        calculator: &AutoDepositCalculator<'_>,
+        num_trades: u64,
+        priority: PriorityLevel,
+        safety_margin_bps: u16,
+    ) -> Result<Self> {
+        let vault_rent_lamports = rpc.get_minimum_balance_for_rent_exemption(VAULT_ACCOUNT_SPACE)?;
+        let delegation_rent_lamports =
+            rpc.get_minimum_balance_for_rent_exemption(DELEGATION_ACCOUNT_SPACE)?;
+        Self::with_rent(
+            vault_rent_lamports,
+            delegation_rent_lamports,
+            calculator,
+            num_trades,
+            priority,
+            safety_margin_bps,
+        )
+    }
+
+    /// [`Self::estimate`] with the rent-exempt minimums already known.
+    pub fn with_rent(
+        vault_rent_lamports: u64,
+        delegation_rent_lamports: u64,
+        calculator: &AutoDepositCalculator<'_>,
+        num_trades: u64,
+        priority: PriorityLevel,
+        safety_margin_bps: u16,
+    ) -> Result<Self> {
+        let trade_fee_deposit_lamports =
+            calculator.compute_deposit_for_trades(num_trades, priority, safety_margin_bps)?;
+
+        let total_lamports = vault_rent_lamports
+            .checked_add(delegation_rent_lamports)
+            .and_then(|t| t.checked_add(trade_fee_deposit_lamports))
+            .ok_or_else(|| anyhow::anyhow!("cost estimate overflow"))?;
+
+        Ok(Self {
+            vault_rent_lamports,
+            delegation_rent_lamports,
+            trade_fee_deposit_lamports,
+            total_lamports,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Charges the same fee at every priority.
+    struct FixedFeeOracle(u64);
+
+    impl FeeOracle for FixedFeeOracle {
+        fn per_trade_fee(&self, _priority: PriorityLevel) -> Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn deposit_covers_every_trade_plus_the_margin() {
+        let oracle = FixedFeeOracle(10_000);
+        let calculator = AutoDepositCalculator::new(&oracle);
+        let deposit = |trades, margin_bps| {
+            calculator
+                .compute_deposit_for_trades(trades, PriorityLevel::Low, margin_bps)
+                .unwrap()
+        };
+
+        assert_eq!(calculator.estimate_fee_per_trade(PriorityLevel::High).unwrap(), 10_000);
+        assert_eq!(deposit(3, 0), 30_000);
+        assert_eq!(deposit(3, 500), 31_500);
+        assert_eq!(deposit(0, 500), 0);
+    }
+
+    #[test]
+    fn deposit_margin_rounds_up() {
+        let oracle = FixedFeeOracle(3);
+        let calculator = AutoDepositCalculator::new(&oracle);
+        // 3 lamports plus 0.01% is 3.0003; the deposit never falls short of the margin.
+        let deposit = calculator.compute_deposit_for_trades(1, PriorityLevel::Medium, 1);
+        assert_eq!(deposit.unwrap(), 4);
+    }
+
+    #[test]
+    fn deposit_overflow_is_an_error() {
+        let oracle = FixedFeeOracle(u64::MAX / 2);
+        let calculator = AutoDepositCalculator::new(&oracle);
+        assert!(calculator.compute_deposit_for_trades(3, PriorityLevel::Low, 0).is_err());
+    }
+
+    #[test]
+    fn static_fees_rise_with_priority() {
+        let calculator = AutoDepositCalculator::new(&StaticFeeOracle);
+        let fees: Vec<u64> = [
+            PriorityLevel::Low,
+            PriorityLevel::Medium,
+            PriorityLevel::High,
+            PriorityLevel::Critical,
+        ]
+        .into_iter()
+        .map(|priority| calculator.estimate_fee_per_trade(priority).unwrap())
+        .collect();
+        assert_eq!(fees, vec![5_000, 10_000, 25_000, 50_000]);
+    }
+
+    #[test]
+    fn session_cost_is_the_sum_of_its_breakdown() {
+        let oracle = FixedFeeOracle(10_000);
+        let calculator = AutoDepositCalculator::new(&oracle);
+        let estimate = SessionCostEstimate::with_rent(
+            2_241_120,
+            1_517_280,
+            &calculator,
+            20,
+            PriorityLevel::Low,
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(estimate.trade_fee_deposit_lamports, 200_000);
+        assert_eq!(
+            estimate.total_lamports,
+            estimate.vault_rent_lamports
+                + estimate.delegation_rent_lamports
+                + estimate.trade_fee_deposit_lamports
+        );
+        assert_eq!(
+            serde_json::to_value(&estimate).unwrap(),
+            serde_json::json!({
+                "vault_rent_lamports": "2241120",
+                "delegation_rent_lamports": "1517280",
+                "trade_fee_deposit_lamports": "200000",
+                "total_lamports": "3958400",
+            })
+        );
+    }
+
+    #[test]
+    fn session_cost_overflow_is_an_error() {
+        let oracle = FixedFeeOracle(1);
+        let calculator = AutoDepositCalculator::new(&oracle);
+        let estimate =
+            SessionCostEstimate::with_rent(u64::MAX, 1, &calculator, 0, PriorityLevel::Low, 0);
+        assert!(estimate.is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    api::AppState,
+    api_error::{ApiError, ErrorCode},
+};
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{FromRequest, Request},
+};
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use std::{cell::Cell, fmt};
+use uuid::Uuid;
+
+/// Upper bound on the encoded size of one array element: a quoted hyphenated UUID, a comma
+/// and some whitespace.
+const BYTES_PER_ITEM: usize = 64;
+/// Room for the brackets and surrounding whitespace.
+const BYTES_OVERHEAD: usize = 64;
+
+/// JSON array of session ids, e.g. `["<uuid>", ...]`, capped at `Config::max_batch_items`.
+///
+/// Oversized requests are rejected with `413` early: the body is read only up to the byte
+/// budget implied by the item cap, and parsing stops at the first element past the cap.
+pub struct BatchIds(pub Vec<Uuid>);
+
+#[async_trait]
+impl FromRequest<AppState> for BatchIds {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request<Body>, state: &AppState) -> Result<Self, Self::Rejection> {
+        Self::read(req.into_body(), state.cfg.max_batch_items).await
+    }
+}
+
+impl BatchIds {
+    async fn read(body: Body, max_items: usize) -> Result<Self, ApiError> {
+        let max_bytes = max_items
+            .saturating_mul(BYTES_PER_ITEM)
+            .saturating_add(BYTES_OVERHEAD);
+        let too_large = || {
+            ApiError::new(
+                ErrorCode::PayloadTooLarge,
+                format!("batch exceeds {max_items} items"),
+            )
+        };
+
+        let bytes = to_bytes(body, max_bytes).await.map_err(|_| too_large())?;
+
+        let exceeded = Cell::new(false);
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let parsed = BoundedSeq {
+            max_items,
+            exceeded: &exceeded,
+        }
+        .deserialize(&mut deserializer)
+        .and_then(|ids| deserializer.end().map(|()| ids));
+        match parsed {
+            Ok(ids) => Ok(BatchIds(ids)),
+            Err(_) if exceeded.get() => Err(too_large()),
+            Err(err) => Err(ApiError::new(ErrorCode::InvalidRequest, err.to_string())),
+        }
+    }
+}
+
+/// Deserializes a sequence of UUIDs, failing as soon as it holds more than `max_items`.
+struct BoundedSeq<'a> {
+    max_items: usize,
+    exceeded: &'a Cell<bool>,
+}
+
+impl<'de> DeserializeSeed<'de> for BoundedSeq<'_> {
+    type Value = Vec<Uuid>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BoundedSeq<'_> {
+    type Value = Vec<Uuid>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an array of at most {} session ids", self.max_items)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut ids = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(self.max_items));
+        while let Some(id) = seq.next_element()? {
+            if ids.len() == self.max_items {
+                self.exceeded.set(true);
+                return Err(de::Error::custom("too many items"));
+            }
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids_json(count: usize) -> String {
+        let ids: Vec<Uuid> = (0..count).map(|_| Uuid::new_v4()).collect();
+        serde_json::to_string(&ids).unwrap()
+    }
+
+    async fn read(body: impl Into<Body>, max_items: usize) -> Result<Vec<Uuid>, ErrorCode> {
+        BatchIds::read(body.into(), max_items)
+            .await
+            .map(|BatchIds(ids)| ids)
+            .map_err(|err| err.code)
+    }
+
+    #[tokio::test]
+    async fn accepts_up_to_the_cap() {
+        assert_eq!(read(ids_json(3), 3).await.unwrap().len(), 3);
+        assert!(read("[]", 3).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_one_item_over_the_cap() {
+        assert_eq!(read(ids_json(4), 3).await, Err(ErrorCode::PayloadTooLarge));
+    }
+
+    #[tokio::test]
+    async fn stops_parsing_at_the_first_item_past_the_cap() {
+        // Everything after the fourth id is garbage the parser must never reach.
+        let ids = ids_json(4);
+        let body = format!("{},!!!]", &ids[..ids.len() - 1]);
+        assert_eq!(read(body, 3).await, Err(ErrorCode::PayloadTooLarge));
+    }
+
+    #[tokio::test]
+    async fn rejects_bodies_over_the_byte_budget_unread() {
+        let body = " ".repeat(3 * BYTES_PER_ITEM + BYTES_OVERHEAD + 1) + "[]";
+        assert_eq!(read(body, 3).await, Err(ErrorCode::PayloadTooLarge));
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_are_invalid_requests() {
+        assert_eq!(read("[\"not-a-uuid\"]", 3).await, Err(ErrorCode::InvalidRequest));
+        assert_eq!(read("{}", 3).await, Err(ErrorCode::InvalidRequest));
+        assert_eq!(read("[] []", 3).await, Err(ErrorCode::InvalidRequest));
+    }
+}
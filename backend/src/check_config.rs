@@ -0,0 +1,86 @@
+use crate::config::Config;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use sqlx::postgres::PgPoolOptions;
+use std::{process::ExitCode, time::Duration};
+
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `--check-config`: validates configuration and connectivity, prints a pass/fail report and
+/// returns the process exit code. Never starts the HTTP server.
+pub async fn run() -> ExitCode {
+    // `from_env` already runs `Config::validate`; a load failure covers both.
+    if check(Config::from_env()).await {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Prints the report for a loaded (or failed) configuration; returns whether every check passed.
+async fn check(loaded: anyhow::Result<Config>) -> bool {
+    let mut failed = false;
+    let mut report = |name: &str, result: anyhow::Result<()>| match result {
+        Ok(()) => println!("[PASS] {name}"),
+        Err(err) => {
+            failed = true;
+            println!("[FAIL] {name}: {err:#}");
+        }
+    };
+
+    let cfg = match loaded {
+        Ok(cfg) => {
+            report("config", Ok(()));
+            cfg
+        }
+        Err(err) => {
+            report("config", Err(err));
+            return false;
+        }
+    };
+
+    report("database", ping_database(&cfg.database.url).await);
+    if let Some(replica_url) = &cfg.database.read_replica_url {
+        report("database read replica", ping_database(replica_url).await);
+    }
+    report("solana rpc", ping_rpc(&cfg).await);
+
+    !failed
+}
+
+async fn ping_database(url: &str) -> anyhow::Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(PING_TIMEOUT)
+        .connect(url)
+        .await?;
+    sqlx::query("SELECT 1").execute(&pool).await?;
+    pool.close().await;
+    Ok(())
+}
+
+async fn ping_rpc(cfg: &Config) -> anyhow::Result<()> {
+    let rpc = RpcClient::new_with_timeout(cfg.solana.rpc_url.clone(), PING_TIMEOUT);
+    rpc.get_version().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn invalid_config_fails_the_check() {
+        let mut cfg = Config::for_tests();
+        cfg.max_batch_items = 0;
+        assert!(!check(cfg.validate().map(|()| cfg)).await);
+    }
+
+    #[tokio::test]
+    async fn unreachable_dependencies_fail_the_check() {
+        // Nothing listens on port 1, so both pings are refused straight away.
+        let mut cfg = Config::for_tests();
+        cfg.database.url = "postgres://127.0.0.1:1/evs_test".into();
+        cfg.solana.rpc_url = "http://127.0.0.1:1".into();
+        assert!(!check(Ok(cfg)).await);
+    }
+}
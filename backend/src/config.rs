@@ -1,49 +1,269 @@
+use crate::program_accounts::MAX_ALLOWED_PROGRAMS;
 use anyhow::{Context, Result};
 use serde::Deserialize;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    pubkey::Pubkey,
+};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
+    /// Optional streaming replica for read-only endpoints. Writes always go to `url`.
+    pub read_replica_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct SolanaConfig {
     pub rpc_url: String,
     pub ws_url: String,
+    /// Default commitment for RPC reads and transaction confirmation.
     pub commitment: String,
+    pub operation_commitments: OperationCommitments,
+    /// Deployed `ephemeral_vault` program id, base58.
+    pub program_id: String,
+    /// DEX programs, base58, that vaults created by the backend allow trades to target.
+    pub dex_programs: Vec<String>,
+    /// Where per-trade fee estimates come from.
+    pub fee_oracle: FeeOracleKind,
+    /// Multiplier on `rpc` fee estimates, in basis points; 10,000 adds no margin.
+    pub fee_margin_bps: u32,
+    pub rpc_pool: RpcPoolConfig,
+}
+
+/// Sizing and recycling policy for `rpc_pool::RpcPool`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcPoolConfig {
+    /// Further RPC endpoints pooled alongside `rpc_url`.
+    pub extra_urls: Vec<String>,
+    /// Clients opened per endpoint.
+    pub clients_per_endpoint: usize,
+    /// Consecutive errors after which a client is taken out of rotation.
+    pub max_consecutive_errors: u32,
+    /// Seconds a benched client sits out before it is handed out again.
+    pub cooldown_secs: u64,
+}
+
+/// Per-trade fee source for `auto_deposit`, selected with `EVS_FEE_ORACLE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeOracleKind {
+    /// Built-in per-priority constants.
+    #[default]
+    Static,
+    /// Recent prioritization fees from the Solana RPC node.
+    Rpc,
+}
+
+/// Transaction submission paths that can wait for their own confirmation level.
+#[derive(Debug, Clone, Copy)]
+pub enum TxOperation {
+    CreateVault,
+    ApproveDelegate,
+    Deposit,
+    Trade,
+    Cleanup,
+}
+
+/// Per-operation commitment overrides; `None` falls back to `SolanaConfig::commitment`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OperationCommitments {
+    pub create_vault: Option<String>,
+    pub approve_delegate: Option<String>,
+    pub deposit: Option<String>,
+    pub trade: Option<String>,
+    pub cleanup: Option<String>,
+}
+
+impl SolanaConfig {
+    pub fn default_commitment(&self) -> Result<CommitmentConfig> {
+        parse_commitment(&self.commitment)
+    }
+
+    pub fn program_id(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.program_id)
+            .map_err(|_| anyhow::anyhow!("invalid program id: {}", self.program_id))
+    }
+
+    /// `dex_programs` as a vault's `allowed_programs`, padded with the default pubkey.
+    pub fn dex_programs(&self) -> Result<[Pubkey; MAX_ALLOWED_PROGRAMS]> {
+        anyhow::ensure!(
+            self.dex_programs.len() <= MAX_ALLOWED_PROGRAMS,
+            "at most {MAX_ALLOWED_PROGRAMS} DEX programs are supported, got {}",
+            self.dex_programs.len()
+        );
+        let mut programs = [Pubkey::default(); MAX_ALLOWED_PROGRAMS];
+        for (slot, program) in programs.iter_mut().zip(&self.dex_programs) {
+            *slot = Pubkey::from_str(program)
+                .map_err(|_| anyhow::anyhow!("invalid DEX program id: {program}"))?;
+        }
+        Ok(programs)
+    }
+
+    pub fn commitment_for(&self, op: TxOperation) -> Result<CommitmentConfig> {
+        let overrides = &self.operation_commitments;
+        let level = match op {
+            TxOperation::CreateVault => &overrides.create_vault,
+            TxOperation::ApproveDelegate => &overrides.approve_delegate,
+            TxOperation::Deposit => &overrides.deposit,
+            TxOperation::Trade => &overrides.trade,
+            TxOperation::Cleanup => &overrides.cleanup,
+        };
+        parse_commitment(level.as_deref().unwrap_or(&self.commitment))
+    }
+}
+
+fn parse_commitment(level: &str) -> Result<CommitmentConfig> {
+    let commitment = CommitmentLevel::from_str(level)
+        .map_err(|_| anyhow::anyhow!("invalid commitment level: {level}"))?;
+    Ok(CommitmentConfig { commitment })
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct SecurityConfig {
     pub key_encryption_key: String,
     pub jwt_secret: String,
+    /// Session creations per minute for one parent wallet, across all client IPs.
     pub rate_limit_sessions_per_minute: u32,
+    /// Session creations per minute from one client IP, across all wallets.
+    pub rate_limit_ip_sessions_per_minute: u32,
+    /// Bearer secret for operator-only endpoints. Admin access is disabled when unset.
+    pub admin_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonitorConfig {
+    /// Seconds between monitor ticks.
+    pub interval_secs: u64,
+    /// Failed cleanup submissions before a session is parked in `NEEDS_MANUAL`.
+    pub cleanup_max_attempts: u32,
+    /// Delay after the first failed cleanup; doubles per further failure, capped at one hour.
+    pub cleanup_backoff_base_secs: u64,
+    /// Upper bound on per-session checks running concurrently within one tick.
+    pub max_concurrent_checks: usize,
+}
+
+/// The KEK is stretched with PBKDF2, but a short passphrase still caps its entropy.
+pub const MIN_KEY_ENCRYPTION_KEY_LEN: usize = 32;
+
+const DEFAULT_PROGRAM_ID: &str = "EpheVau1t1111111111111111111111111111111111";
+
+fn default_webhook_timeout_secs() -> u64 {
+    5
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub listen_addr: String,
+    /// Session write requests served at once; further writes are shed with `503`.
+    pub max_concurrent_writes: usize,
+    /// Most ids accepted in one batch request; larger batches are rejected with `413`.
+    pub max_batch_items: usize,
+    /// How long shutdown waits for open connections before aborting them.
+    pub shutdown_grace_secs: u64,
+    /// Compress HTTP responses (gzip or brotli, per `Accept-Encoding`). WebSockets are exempt.
+    pub compress_responses: bool,
     pub database: DatabaseConfig,
     pub solana: SolanaConfig,
     pub security: SecurityConfig,
+    pub webhooks: Vec<WebhookConfig>,
+    pub monitor: MonitorConfig,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
         let listen_addr = std::env::var("EVS_LISTEN_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".into());
+        let max_concurrent_writes: usize = std::env::var("EVS_MAX_CONCURRENT_WRITES")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(64);
+        let max_batch_items: usize = std::env::var("EVS_MAX_BATCH_ITEMS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1000);
+        let shutdown_grace_secs: u64 = std::env::var("EVS_SHUTDOWN_GRACE_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        let compress_responses = match std::env::var("EVS_COMPRESS_RESPONSES").as_deref() {
+            Err(_) | Ok("true") | Ok("1") => true,
+            Ok("false") | Ok("0") => false,
+            Ok(other) => {
+                anyhow::bail!("EVS_COMPRESS_RESPONSES must be `true` or `false`, got `{other}`")
+            }
+        };
         let database_url = std::env::var("EVS_DATABASE_URL")
             .context("EVS_DATABASE_URL must be set for PostgreSQL connection")?;
         let max_connections: u32 = std::env::var("EVS_DATABASE_MAX_CONNECTIONS")
             .ok()
             .and_then(|s| s.parse().ok())
             .unwrap_or(10);
+        let read_replica_url = std::env::var("EVS_DATABASE_READ_REPLICA_URL")
+            .ok()
+            .filter(|url| !url.is_empty());
 
         let rpc_url = std::env::var("EVS_SOLANA_RPC_URL")
             .unwrap_or_else(|_| "http://localhost:8899".into());
         let ws_url = std::env::var("EVS_SOLANA_WS_URL")
             .unwrap_or_else(|_| "ws://localhost:8900".into());
         let commitment = std::env::var("EVS_SOLANA_COMMITMENT").unwrap_or_else(|_| "confirmed".into());
+        let operation_commitments = OperationCommitments {
+            create_vault: std::env::var("EVS_SOLANA_COMMITMENT_CREATE_VAULT").ok(),
+            approve_delegate: std::env::var("EVS_SOLANA_COMMITMENT_APPROVE_DELEGATE").ok(),
+            deposit: std::env::var("EVS_SOLANA_COMMITMENT_DEPOSIT").ok(),
+            trade: std::env::var("EVS_SOLANA_COMMITMENT_TRADE").ok(),
+            cleanup: std::env::var("EVS_SOLANA_COMMITMENT_CLEANUP").ok(),
+        };
+        let program_id =
+            std::env::var("EVS_PROGRAM_ID").unwrap_or_else(|_| DEFAULT_PROGRAM_ID.into());
+        let dex_programs: Vec<String> = std::env::var("EVS_DEX_PROGRAMS")
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let rpc_extra_urls: Vec<String> = std::env::var("EVS_SOLANA_RPC_EXTRA_URLS")
+            .map(|raw| {
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let rpc_clients_per_endpoint: usize = std::env::var("EVS_RPC_POOL_CLIENTS_PER_ENDPOINT")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1);
+        let rpc_max_consecutive_errors: u32 = std::env::var("EVS_RPC_POOL_MAX_ERRORS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(3);
+        let rpc_cooldown_secs: u64 = std::env::var("EVS_RPC_POOL_COOLDOWN_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        let fee_oracle = match std::env::var("EVS_FEE_ORACLE").as_deref() {
+            Err(_) | Ok("static") => FeeOracleKind::Static,
+            Ok("rpc") => FeeOracleKind::Rpc,
+            Ok(other) => anyhow::bail!("EVS_FEE_ORACLE must be `static` or `rpc`, got `{other}`"),
+        };
+        let fee_margin_bps: u32 = std::env::var("EVS_FEE_MARGIN_BPS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(10_000);
 
         let key_encryption_key = std::env::var("EVS_KEY_ENCRYPTION_KEY")
             .context("EVS_KEY_ENCRYPTION_KEY must be set for encrypting ephemeral keys")?;
@@ -53,23 +273,143 @@ impl Config {
             .ok()
             .and_then(|s| s.parse().ok())
             .unwrap_or(60);
+        let rate_limit_ip_sessions_per_minute: u32 =
+            std::env::var("EVS_RATE_LIMIT_IP_SESSIONS_PER_MINUTE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(120);
+        let admin_token = std::env::var("EVS_ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+
+        // JSON array, e.g. `[{"url":"https://example.com/hook","timeout_secs":5}]`.
+        let webhooks: Vec<WebhookConfig> = match std::env::var("EVS_WEBHOOKS") {
+            Ok(raw) => serde_json::from_str(&raw).context("EVS_WEBHOOKS must be a JSON array")?,
+            Err(_) => Vec::new(),
+        };
+
+        let monitor_interval_secs: u64 = std::env::var("EVS_MONITOR_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        let cleanup_max_attempts: u32 = std::env::var("EVS_CLEANUP_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5);
+        let cleanup_backoff_base_secs: u64 = std::env::var("EVS_CLEANUP_BACKOFF_BASE_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        let max_concurrent_checks: usize = std::env::var("EVS_MONITOR_MAX_CONCURRENCY")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(32);
 
-        Ok(Self {
+        let cfg = Self {
             listen_addr,
+            max_concurrent_writes,
+            max_batch_items,
+            shutdown_grace_secs,
+            compress_responses,
             database: DatabaseConfig {
                 url: database_url,
                 max_connections,
+                read_replica_url,
             },
             solana: SolanaConfig {
                 rpc_url,
                 ws_url,
                 commitment,
+                operation_commitments,
+                program_id,
+                dex_programs,
+                fee_oracle,
+                fee_margin_bps,
+                rpc_pool: RpcPoolConfig {
+                    extra_urls: rpc_extra_urls,
+                    clients_per_endpoint: rpc_clients_per_endpoint,
+                    max_consecutive_errors: rpc_max_consecutive_errors,
+                    cooldown_secs: rpc_cooldown_secs,
+                },
             },
             security: SecurityConfig {
                 key_encryption_key,
                 jwt_secret,
                 rate_limit_sessions_per_minute,
+                rate_limit_ip_sessions_per_minute,
+                admin_token,
             },
-        })
+            webhooks,
+            monitor: MonitorConfig {
+                interval_secs: monitor_interval_secs,
+                cleanup_max_attempts,
+                cleanup_backoff_base_secs,
+                max_concurrent_checks,
+            },
+        };
+
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    /// Static checks that need no network access. Surfaces typos at startup rather than on
+    /// first use.
+    pub fn validate(&self) -> Result<()> {
+        self.listen_addr
+            .parse::<std::net::SocketAddr>()
+            .with_context(|| format!("invalid EVS_LISTEN_ADDR: {}", self.listen_addr))?;
+        if self.max_concurrent_writes == 0 {
+            anyhow::bail!("EVS_MAX_CONCURRENT_WRITES must be at least 1");
+        }
+        if self.max_batch_items == 0 {
+            anyhow::bail!("EVS_MAX_BATCH_ITEMS must be at least 1");
+        }
+        if self.security.rate_limit_sessions_per_minute == 0
+            || self.security.rate_limit_ip_sessions_per_minute == 0
+        {
+            anyhow::bail!(
+                "EVS_RATE_LIMIT_SESSIONS_PER_MINUTE and EVS_RATE_LIMIT_IP_SESSIONS_PER_MINUTE must be at least 1"
+            );
+        }
+        if self.security.key_encryption_key.len() < MIN_KEY_ENCRYPTION_KEY_LEN {
+            anyhow::bail!(
+                "EVS_KEY_ENCRYPTION_KEY must be at least {MIN_KEY_ENCRYPTION_KEY_LEN} bytes"
+            );
+        }
+        self.solana.program_id()?;
+        self.solana.dex_programs()?;
+        if self.solana.fee_margin_bps < 10_000 {
+            anyhow::bail!("EVS_FEE_MARGIN_BPS must be at least 10000 (no margin)");
+        }
+        if self.solana.rpc_pool.clients_per_endpoint == 0
+            || self.solana.rpc_pool.max_consecutive_errors == 0
+        {
+            anyhow::bail!(
+                "EVS_RPC_POOL_CLIENTS_PER_ENDPOINT and EVS_RPC_POOL_MAX_ERRORS must be at least 1"
+            );
+        }
+        for op in [
+            TxOperation::CreateVault,
+            TxOperation::ApproveDelegate,
+            TxOperation::Deposit,
+            TxOperation::Trade,
+            TxOperation::Cleanup,
+        ] {
+            self.solana.commitment_for(op)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// `from_env` with the required variables set to test values; everything else defaults.
+    pub(crate) fn for_tests() -> Self {
+        static ENV: std::sync::Once = std::sync::Once::new();
+        ENV.call_once(|| {
+            std::env::set_var("EVS_DATABASE_URL", "postgres://localhost/evs_test");
+            std::env::set_var("EVS_KEY_ENCRYPTION_KEY", "k".repeat(32));
+            std::env::set_var("EVS_JWT_SECRET", "test-jwt-secret");
+            std::env::set_var("EVS_ADMIN_TOKEN", "test-admin-token");
+        });
+        Self::from_env().expect("test config is valid")
     }
 }
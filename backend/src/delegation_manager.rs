@@ -1,15 +1,112 @@
-use crate::{config::Config, session_manager::Session};
-use anyhow::Result;
-use solana_client::rpc_client::RpcClient;
+use crate::{
+    config::Config,
+    program_accounts::{
+        EphemeralVault, VaultDelegation, DELEGATION_PURPOSE_LEN, DISCRIMINATOR_LEN,
+        MAX_ALLOWED_PROGRAMS,
+    },
+    session_manager::Session,
+    transaction_signer::RemoteSigner,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     instruction::Instruction,
     pubkey::Pubkey,
-    signature::{Keypair, Signer},
+    signature::Keypair,
     system_program,
     transaction::Transaction,
 };
 
+#[derive(Debug, thiserror::Error)]
+pub enum DelegationError {
+    #[error("solana rpc error: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+    #[error("failed to deserialize account {account}: {reason}")]
+    Deserialize { account: Pubkey, reason: String },
+    #[error("invalid delegation: {0}")]
+    InvalidDelegation(String),
+    #[error("failed to sign transaction: {0}")]
+    Signing(String),
+}
+
+pub type Result<T, E = DelegationError> = std::result::Result<T, E>;
+
+/// Vault PDA for a parent/ephemeral pair, matching the program's `[b"vault", parent, ephemeral]`.
+pub fn vault_pda(program_id: &Pubkey, parent_wallet: &Pubkey, ephemeral_wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"vault", parent_wallet.as_ref(), ephemeral_wallet.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+/// Delegation PDA for a vault/delegate pair, matching the program's
+/// `[b"delegation", vault, delegate]`.
+pub fn delegation_pda(program_id: &Pubkey, vault_pda: &Pubkey, delegate: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"delegation", vault_pda.as_ref(), delegate.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+/// Anchor's instruction discriminator: the first 8 bytes of `sha256("global:<name>")`.
+pub fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let digest = ring::digest::digest(&ring::digest::SHA256, format!("global:{name}").as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&digest.as_ref()[..8]);
+    discriminator
+}
+
+/// Anchor instruction data: the discriminator of `name` followed by the Borsh-encoded `args`.
+fn anchor_ix_data(name: &str, args: &impl BorshSerialize) -> Vec<u8> {
+    let mut data = anchor_discriminator(name).to_vec();
+    borsh::to_writer(&mut data, args).expect("writing to a Vec cannot fail");
+    data
+}
+
+/// Arguments of the program's `create_vault`, in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct CreateVaultArgs {
+    pub session_duration: i64,
+    pub max_deposit: u64,
+    pub ephemeral_wallet: Pubkey,
+    pub parent_topup_allowance: u64,
+    pub single_use: bool,
+    pub grace_trades: u8,
+    pub grace_period: i64,
+    pub slot_time_ms: u32,
+    pub per_trade_limit: u64,
+    pub min_trade_interval_secs: i64,
+    pub allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+}
+
+/// Arguments of the program's `execute_trade`, in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ExecuteTradeArgs {
+    pub fee_paid: u64,
+    pub venue: Pubkey,
+    pub min_output: u64,
+}
+
+/// Arguments of the program's `auto_deposit_for_trade`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct AutoDepositArgs {
+    pub trade_fee_estimate: u64,
+}
+
+/// Arguments of the program's `approve_delegate`, in declaration order.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ApproveDelegateArgs {
+    pub delegate: Pubkey,
+    pub delegate_expiry_slot: u64,
+    pub delegation_ttl_secs: i64,
+    pub purpose: [u8; DELEGATION_PURPOSE_LEN],
+}
+
 pub struct DelegationManager {
     rpc: RpcClient,
     cfg: Config,
@@ -17,13 +114,17 @@ pub struct DelegationManager {
 
 impl DelegationManager {
     pub fn new(cfg: Config) -> Self {
-        let rpc = RpcClient::new_with_commitment(
-            cfg.solana.rpc_url.clone(),
-            CommitmentConfig::confirmed(),
-        );
+        let commitment = cfg
+            .solana
+            .default_commitment()
+            .unwrap_or_else(|_| CommitmentConfig::confirmed());
+        let rpc = RpcClient::new_with_commitment(cfg.solana.rpc_url.clone(), commitment);
         Self { rpc, cfg }
     }
 
+    /// `create_vault` with the program's defaults for every optional policy: no top-up
+    /// allowance, grace window, slot bound, per-trade limit or trade cooldown. Trades may
+    /// target `allowed_programs` (see `SolanaConfig::dex_programs`).
     pub fn build_create_vault_ix(
         &self,
         program_id: Pubkey,
@@ -31,14 +132,23 @@ impl DelegationManager {
         ephemeral_wallet: Pubkey,
         session_duration_secs: i64,
         max_deposit: u64,
+        allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
     ) -> Instruction {
-        let (vault_pda, _bump) = Pubkey::find_program_address(
-            &[b"vault", parent_wallet.as_ref(), ephemeral_wallet.as_ref()],
-            &program_id,
-        );
+        let vault_pda = vault_pda(&program_id, &parent_wallet, &ephemeral_wallet);
+        let args = CreateVaultArgs {
+            session_duration: session_duration_secs,
+            max_deposit,
+            ephemeral_wallet,
+            parent_topup_allowance: 0,
+            single_use: false,
+            grace_trades: 0,
+            grace_period: 0,
+            slot_time_ms: 0,
+            per_trade_limit: 0,
+            min_trade_interval_secs: 0,
+            allowed_programs,
+        };
 
-        // Anchor instruction layout is normally generated via IDL. For this assessment we
-        // treat this as a placeholder; the frontend would usually use the IDL to build this.
         Instruction {
             program_id,
             accounts: vec![
@@ -48,10 +158,12 @@ impl DelegationManager {
                 solana_sdk::instruction::AccountMeta::new(vault_pda, false),
                 solana_sdk::instruction::AccountMeta::new_readonly(system_program::id(), false),
             ],
-            data: vec![],
+            data: anchor_ix_data("create_vault", &args),
         }
     }
 
+    /// `approve_delegate` with no slot bound, TTL or purpose label, so the delegation lasts
+    /// as long as the session.
     pub fn build_approve_delegate_ix(
         &self,
         program_id: Pubkey,
@@ -59,8 +171,13 @@ impl DelegationManager {
         vault_pda: Pubkey,
         delegate: Pubkey,
     ) -> Instruction {
-        let (delegation_pda, _bump) =
-            Pubkey::find_program_address(&[b"delegation", vault_pda.as_ref()], &program_id);
+        let delegation_pda = delegation_pda(&program_id, &vault_pda, &delegate);
+        let args = ApproveDelegateArgs {
+            delegate,
+            delegate_expiry_slot: 0,
+            delegation_ttl_secs: 0,
+            purpose: [0; DELEGATION_PURPOSE_LEN],
+        };
 
         Instruction {
             program_id,
@@ -70,33 +187,391 @@ impl DelegationManager {
                 solana_sdk::instruction::AccountMeta::new(delegation_pda, false),
                 solana_sdk::instruction::AccountMeta::new_readonly(system_program::id(), false),
             ],
-            data: vec![],
+            data: anchor_ix_data("approve_delegate", &args),
         }
     }
 
+    /// `execute_trade` signed by the session's `ephemeral` wallet, routed through
+    /// `target_program` (one of the vault's `allowed_programs`). `min_output` is zero and
+    /// `trade_output` omitted: the program rejects a slippage guard until the DEX CPI lands.
+    pub fn build_execute_trade_ix(
+        &self,
+        program_id: Pubkey,
+        vault_pda: Pubkey,
+        ephemeral: Pubkey,
+        parent_wallet: Pubkey,
+        fee_paid: u64,
+        venue: Pubkey,
+        target_program: Pubkey,
+    ) -> Instruction {
+        let delegation_pda = delegation_pda(&program_id, &vault_pda, &ephemeral);
+        let args = ExecuteTradeArgs {
+            fee_paid,
+            venue,
+            min_output: 0,
+        };
+
+        Instruction {
+            program_id,
+            accounts: vec![
+                solana_sdk::instruction::AccountMeta::new(vault_pda, false),
+                solana_sdk::instruction::AccountMeta::new_readonly(ephemeral, true),
+                solana_sdk::instruction::AccountMeta::new(delegation_pda, false),
+                solana_sdk::instruction::AccountMeta::new_readonly(parent_wallet, false),
+                // `trade_output` is optional; the program id in its slot reads as `None`.
+                solana_sdk::instruction::AccountMeta::new_readonly(program_id, false),
+                solana_sdk::instruction::AccountMeta::new_readonly(target_program, false),
+            ],
+            data: anchor_ix_data("execute_trade", &args),
+        }
+    }
+
+    /// `cleanup_vault` for an expired or revoked vault, paid and signed by `cleaner`. Pass the
+    /// vault's `delegation` when one was approved so its rent is returned to the parent too.
+    /// `refund_recipient` must match the vault's field (see [`Self::fetch_vault`]).
+    pub fn build_cleanup_vault_ix(
+        &self,
+        program_id: Pubkey,
+        parent_wallet: Pubkey,
+        vault_pda: Pubkey,
+        cleaner: Pubkey,
+        delegation: Option<Pubkey>,
+        refund_recipient: Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id,
+            accounts: vec![
+                solana_sdk::instruction::AccountMeta::new(vault_pda, false),
+                solana_sdk::instruction::AccountMeta::new(parent_wallet, false),
+                solana_sdk::instruction::AccountMeta::new(cleaner, true),
+                solana_sdk::instruction::AccountMeta::new_readonly(parent_wallet, false),
+                // Anchor reads the program id in an optional account's slot as `None`.
+                match delegation {
+                    Some(delegation) => solana_sdk::instruction::AccountMeta::new(delegation, false),
+                    None => solana_sdk::instruction::AccountMeta::new_readonly(program_id, false),
+                },
+                solana_sdk::instruction::AccountMeta::new(refund_recipient, false),
+            ],
+            data: anchor_discriminator("cleanup_vault").to_vec(),
+        }
+    }
+
+    /// Checks that `vault_pubkey` carries a live delegation to the session's ephemeral wallet,
+    /// so a missing or revoked delegation is caught here rather than by a failed `execute_trade`.
     pub async fn verify_delegation_onchain(
         &self,
-        _session: &Session,
-        _program_id: Pubkey,
-    ) -> Result<bool> {
-        // For the purposes of this assessment we keep this light-weight and rely on
-        // application-layer guarantees. A production implementation would fetch the
-        // VaultDelegation account and validate its fields.
-        Ok(true)
+        session: &Session,
+        vault_pubkey: Pubkey,
+        program_id: Pubkey,
+    ) -> Result<()> {
+        let ephemeral: Pubkey = session.ephemeral_wallet.parse().map_err(|_| {
+            DelegationError::InvalidDelegation("session ephemeral wallet is not a pubkey".into())
+        })?;
+        let pda = delegation_pda(&program_id, &vault_pubkey, &ephemeral);
+        let delegation: VaultDelegation = self.fetch_account(pda)?;
+
+        if delegation.vault != vault_pubkey {
+            return Err(DelegationError::InvalidDelegation(format!(
+                "delegation {pda} belongs to vault {}",
+                delegation.vault
+            )));
+        }
+        if delegation.delegate != ephemeral {
+            return Err(DelegationError::InvalidDelegation(format!(
+                "delegation {pda} is for {}, not the session's ephemeral wallet",
+                delegation.delegate
+            )));
+        }
+        if let Some(revoked_at) = delegation.revoked_at {
+            return Err(DelegationError::InvalidDelegation(format!(
+                "delegation {pda} was revoked at {revoked_at}"
+            )));
+        }
+        Ok(())
     }
 
+    /// Fetches and decodes the `EphemeralVault` account at `vault_pda`.
+    pub async fn fetch_vault(&self, vault_pda: Pubkey) -> Result<EphemeralVault> {
+        self.fetch_account(vault_pda)
+    }
+
+    /// Fetches the program account at `address` and Borsh-decodes it past the discriminator.
+    fn fetch_account<T: BorshDeserialize>(&self, address: Pubkey) -> Result<T> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(&address, self.rpc.commitment())?
+            .value
+            .ok_or(DelegationError::AccountNotFound(address))?;
+        let data = account
+            .data
+            .get(DISCRIMINATOR_LEN..)
+            .ok_or_else(|| DelegationError::Deserialize {
+                account: address,
+                reason: "account shorter than its discriminator".into(),
+            })?;
+        T::deserialize(&mut &data[..]).map_err(|err| DelegationError::Deserialize {
+            account: address,
+            reason: err.to_string(),
+        })
+    }
+
+    /// Whether `pubkey` currently holds an account at the configured commitment.
+    pub async fn account_exists(&self, pubkey: Pubkey) -> Result<bool> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(&pubkey, self.rpc.commitment())?;
+        Ok(account.value.is_some())
+    }
+
+    /// Builds a transaction with a recent blockhash but no signatures, for a wallet that holds
+    /// the required keys (e.g. the parent) to sign and submit.
+    pub async fn build_unsigned_transaction(
+        &self,
+        fee_payer: Pubkey,
+        instructions: Vec<Instruction>,
+    ) -> Result<Transaction> {
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&fee_payer));
+        tx.message.recent_blockhash = self.rpc.get_latest_blockhash()?;
+        Ok(tx)
+    }
+
+    /// `auto_deposit_for_trade`: moves `amount` lamports from the parent into the vault.
+    pub fn build_auto_deposit_ix(
+        &self,
+        program_id: Pubkey,
+        parent_wallet: Pubkey,
+        vault_pda: Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let args = AutoDepositArgs {
+            trade_fee_estimate: amount,
+        };
+
+        Instruction {
+            program_id,
+            accounts: vec![
+                solana_sdk::instruction::AccountMeta::new(vault_pda, false),
+                solana_sdk::instruction::AccountMeta::new(parent_wallet, true),
+                solana_sdk::instruction::AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: anchor_ix_data("auto_deposit_for_trade", &args),
+        }
+    }
+
+    /// Builds a transaction paid for and signed by `payer`, which may be a hardware wallet or
+    /// remote signing service; `local_signers` (e.g. the ephemeral key) sign in-process.
     pub async fn build_and_sign_transactions(
         &self,
-        payer: &Keypair,
+        payer: &dyn RemoteSigner,
+        local_signers: &[&Keypair],
         instructions: Vec<Instruction>,
     ) -> Result<Transaction> {
-        let latest_blockhash = self.rpc.get_latest_blockhash()?;
-        let tx = Transaction::new_signed_with_payer(
-            &instructions,
-            Some(&payer.pubkey()),
-            &[payer],
-            latest_blockhash,
-        );
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        tx.message.recent_blockhash = self.rpc.get_latest_blockhash()?;
+        crate::transaction_signer::sign_transaction(&mut tx, payer, local_signers)
+            .await
+            .map_err(|err| DelegationError::Signing(format!("{err:#}")))?;
         Ok(tx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> DelegationManager {
+        DelegationManager::new(Config::for_tests())
+    }
+
+    /// Splits instruction data into its discriminator and the decoded args.
+    fn decode<T: BorshDeserialize>(data: &[u8]) -> ([u8; 8], T) {
+        let (discriminator, args) = data.split_at(8);
+        (
+            discriminator.try_into().unwrap(),
+            T::try_from_slice(args).unwrap(),
+        )
+    }
+
+    #[test]
+    fn discriminator_is_anchor_sighash() {
+        assert_eq!(
+            anchor_discriminator("create_vault"),
+            [29, 237, 247, 208, 193, 82, 54, 135]
+        );
+        assert_eq!(
+            anchor_discriminator("approve_delegate"),
+            [68, 6, 248, 64, 195, 222, 182, 223]
+        );
+    }
+
+    #[test]
+    fn create_vault_data_decodes_back() {
+        let program_id = Pubkey::new_unique();
+        let parent = Pubkey::new_unique();
+        let ephemeral = Pubkey::new_unique();
+        let mut allowed_programs = [Pubkey::default(); MAX_ALLOWED_PROGRAMS];
+        allowed_programs[0] = Pubkey::new_unique();
+
+        let ix = manager().build_create_vault_ix(
+            program_id,
+            parent,
+            ephemeral,
+            3600,
+            5_000_000,
+            allowed_programs,
+        );
+        let (discriminator, args) = decode::<CreateVaultArgs>(&ix.data);
+        assert_eq!(discriminator, anchor_discriminator("create_vault"));
+        assert_eq!(args.session_duration, 3600);
+        assert_eq!(args.max_deposit, 5_000_000);
+        assert_eq!(args.ephemeral_wallet, ephemeral);
+        assert_eq!(args.allowed_programs, allowed_programs);
+        assert_eq!(ix.accounts[2].pubkey, vault_pda(&program_id, &parent, &ephemeral));
+    }
+
+    #[test]
+    fn approve_delegate_data_decodes_back() {
+        let program_id = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+
+        let ix =
+            manager().build_approve_delegate_ix(program_id, Pubkey::new_unique(), vault, delegate);
+        let (discriminator, args) = decode::<ApproveDelegateArgs>(&ix.data);
+        assert_eq!(discriminator, anchor_discriminator("approve_delegate"));
+        assert_eq!(
+            args,
+            ApproveDelegateArgs {
+                delegate,
+                delegate_expiry_slot: 0,
+                delegation_ttl_secs: 0,
+                purpose: [0; DELEGATION_PURPOSE_LEN],
+            }
+        );
+        assert_eq!(ix.accounts[2].pubkey, delegation_pda(&program_id, &vault, &delegate));
+    }
+
+    #[test]
+    fn execute_trade_data_and_accounts() {
+        let program_id = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let ephemeral = Pubkey::new_unique();
+        let parent = Pubkey::new_unique();
+        let venue = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+
+        let ix = manager()
+            .build_execute_trade_ix(program_id, vault, ephemeral, parent, 5_000, venue, target);
+        let (discriminator, args) = decode::<ExecuteTradeArgs>(&ix.data);
+        assert_eq!(discriminator, [77, 16, 192, 135, 13, 0, 106, 97]);
+        assert_eq!(
+            args,
+            ExecuteTradeArgs {
+                fee_paid: 5_000,
+                venue,
+                min_output: 0,
+            }
+        );
+
+        let accounts: Vec<(Pubkey, bool, bool)> = ix
+            .accounts
+            .iter()
+            .map(|meta| (meta.pubkey, meta.is_signer, meta.is_writable))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (vault, false, true),
+                (ephemeral, true, false),
+                (delegation_pda(&program_id, &vault, &ephemeral), false, true),
+                (parent, false, false),
+                (program_id, false, false),
+                (target, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn cleanup_vault_data_and_accounts() {
+        let program_id = Pubkey::new_unique();
+        let parent = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let cleaner = Pubkey::new_unique();
+        let refund_recipient = Pubkey::new_unique();
+
+        let ix = manager().build_cleanup_vault_ix(
+            program_id,
+            parent,
+            vault,
+            cleaner,
+            None,
+            refund_recipient,
+        );
+        assert_eq!(ix.data, [101, 166, 114, 78, 21, 159, 130, 253]);
+        assert_eq!(ix.accounts[1].pubkey, parent);
+        assert!(ix.accounts[1].is_writable, "parent receives the closed vault's rent");
+        assert!(ix.accounts[2].is_signer);
+        assert_eq!(ix.accounts[4].pubkey, program_id, "no delegation reads as None");
+        assert_eq!(ix.accounts[5].pubkey, refund_recipient);
+
+        let delegation = Pubkey::new_unique();
+        let ix = manager().build_cleanup_vault_ix(
+            program_id,
+            parent,
+            vault,
+            cleaner,
+            Some(delegation),
+            refund_recipient,
+        );
+        assert_eq!(ix.accounts[4].pubkey, delegation);
+        assert!(ix.accounts[4].is_writable);
+    }
+
+    /// JSON-RPC stub that records the commitment of every `getAccountInfo` and reports the
+    /// account as missing.
+    async fn account_info_stub() -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        use axum::{routing::post, Json, Router};
+        use serde_json::{json, Value};
+
+        let commitments = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = commitments.clone();
+        let router = Router::new().route(
+            "/",
+            post(move |Json(req): Json<Value>| async move {
+                let result = match req["method"].as_str() {
+                    Some("getVersion") => json!({ "solana-core": "1.18.0", "feature-set": 0 }),
+                    Some("getAccountInfo") => {
+                        let commitment = req["params"][1]["commitment"].as_str().unwrap_or("");
+                        seen.lock().unwrap().push(commitment.to_string());
+                        json!({ "context": { "slot": 1 }, "value": null })
+                    }
+                    other => panic!("unexpected rpc method {other:?}"),
+                };
+                Json(json!({ "jsonrpc": "2.0", "id": req["id"], "result": result }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (url, commitments)
+    }
+
+    // Multi-threaded: the blocking `RpcClient` parks its worker with `block_in_place`.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn account_reads_use_the_configured_finalized_commitment() {
+        let (url, commitments) = account_info_stub().await;
+        let mut cfg = Config::for_tests();
+        cfg.solana.rpc_url = url;
+        cfg.solana.commitment = "finalized".into();
+        let manager = DelegationManager::new(cfg);
+
+        let vault = Pubkey::new_unique();
+        assert!(!manager.account_exists(vault).await.unwrap());
+        assert!(matches!(
+            manager.fetch_vault(vault).await,
+            Err(DelegationError::AccountNotFound(missing)) if missing == vault
+        ));
+        assert_eq!(*commitments.lock().unwrap(), ["finalized", "finalized"]);
+    }
+}
+
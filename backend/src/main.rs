@@ -1,20 +1,38 @@
 mod config;
 mod session_manager;
+mod session_store;
 mod delegation_manager;
 mod auto_deposit;
 mod vault_monitor;
 mod transaction_signer;
 mod api;
+mod api_error;
+mod auth;
+mod batch;
+mod check_config;
+mod rate_limit;
+mod rpc_pool;
+mod webhook;
+mod program_accounts;
+mod pubsub;
+mod shutdown;
+mod string_u64;
 
 use anyhow::Result;
-use axum::{routing::{get, post, delete}, Router};
+use axum::{
+    error_handling::HandleErrorLayer,
+    routing::{get, post, delete},
+    BoxError, Router,
+};
 use sqlx::postgres::PgPoolOptions;
-use std::net::SocketAddr;
-use tokio::signal;
+use std::{net::SocketAddr, process::ExitCode};
+use tokio::{signal, sync::oneshot};
+use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "backend=info,axum=info".into()),
@@ -22,6 +40,10 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    if std::env::args().any(|arg| arg == "--check-config") {
+        return Ok(check_config::run().await);
+    }
+
     let cfg = config::Config::from_env()?;
 
     let pool = PgPoolOptions::new()
@@ -29,27 +51,124 @@ async fn main() -> Result<()> {
         .connect(&cfg.database.url)
         .await?;
 
-    let shared_state = api::AppState::new(pool, cfg.clone()).await?;
+    // Connected lazily so a replica that is down at startup only costs a fallback per read.
+    let read_pool = cfg
+        .database
+        .read_replica_url
+        .as_deref()
+        .map(|url| {
+            PgPoolOptions::new()
+                .max_connections(cfg.database.max_connections)
+                .acquire_timeout(std::time::Duration::from_secs(2))
+                .connect_lazy(url)
+        })
+        .transpose()?;
 
-    let app = Router::new()
-        .route("/health", get(api::health))
+    let shared_state = api::AppState::new(pool.clone(), read_pool, cfg.clone()).await?;
+
+    let monitor = vault_monitor::VaultMonitor::new(
+        pool.clone(),
+        cfg.clone(),
+        shared_state.tx_events.clone(),
+    );
+    let cleanup = std::sync::Arc::new(vault_monitor::CleanupSubmitter::new(pool, cfg.clone()));
+    tokio::spawn(monitor.run(move |candidate| cleanup.clone().submit(candidate)));
+
+    if !cfg.webhooks.is_empty() {
+        let dispatcher = webhook::WebhookDispatcher::new(cfg.webhooks.clone());
+        tokio::spawn(dispatcher.run(shared_state.tx_events.subscribe()));
+    }
+
+    // `/session/*` routes and `/sessions/batch` need a parent bearer token from `/auth/verify`.
+    // `recover-key` is exempt: it carries its own admin token or parent signature. `deposit`,
+    // `status` and `changes` also take a session token from `/session/token`, within its grant.
+    let verifier = std::sync::Arc::new(auth::TokenVerifier::new(&cfg.security.jwt_secret));
+    let require_token =
+        axum::middleware::from_fn_with_state(verifier.clone(), auth::require_parent_token);
+    let session_access =
+        axum::middleware::from_fn_with_state(verifier, auth::require_session_access);
+
+    // Session writes share one concurrency budget so a burst cannot exhaust the DB pool or
+    // flood the RPC node; excess requests are shed with 503 instead of queueing. Reads and
+    // `/health` stay outside the limit.
+    let writes = Router::new()
         .route("/session/create", post(api::create_session))
         .route("/session/approve", post(api::approve_session))
+        .route("/session/resume-bootstrap", post(api::resume_bootstrap))
         .route("/session/revoke", delete(api::revoke_session))
-        .route("/session/status", get(api::session_status))
-        .route("/session/deposit", post(api::session_deposit))
+        .route("/session/rotate-key", post(api::rotate_key))
+        .route("/session/token", post(api::issue_session_token))
+        .route_layer(require_token.clone())
+        .merge(
+            Router::new()
+                .route("/session/deposit", post(api::session_deposit))
+                .route_layer(session_access.clone()),
+        )
+        .route("/session/recover-key", post(api::recover_key))
+        .route("/auth/challenge", post(api::auth_challenge))
+        .route("/auth/verify", post(api::auth_verify))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async {
+                    api_error::ApiError::from(api_error::ErrorCode::Overloaded)
+                }))
+                .load_shed()
+                .concurrency_limit(cfg.max_concurrent_writes),
+        );
+
+    let mut app = Router::new()
+        .route("/health", get(api::health))
+        .route("/errors", get(api_error::error_registry))
+        .merge(
+            Router::new()
+                .route("/session/list", get(api::list_sessions))
+                .route("/session/key-meta", get(api::session_key_meta))
+                .route("/session/estimate-cost", get(api::estimate_session_cost))
+                .route("/sessions/batch", post(api::sessions_batch))
+                .route_layer(require_token),
+        )
+        .merge(
+            Router::new()
+                .route("/session/status", get(api::session_status))
+                .route("/session/changes", get(api::session_changes))
+                .route_layer(session_access),
+        )
+        .route("/admin/needs-manual", get(api::admin_needs_manual))
+        .route("/admin/sessions/attention", get(api::admin_attention))
+        .route("/admin/sessions/health", get(api::admin_sessions_health))
+        .route("/admin/sessions/export", get(api::admin_export_sessions))
+        .merge(writes);
+    if cfg.compress_responses {
+        app = app.layer(CompressionLayer::new());
+    }
+    // Added after the compression layer so the WebSocket upgrade bypasses it.
+    let app = app
         .route("/ws/session", get(api::session_ws))
-        .with_state(shared_state);
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.in_flight.clone(),
+            shutdown::track_request,
+        ))
+        .with_state(shared_state.clone());
 
     let addr: SocketAddr = cfg.listen_addr.parse()?;
     tracing::info!("listening on {}", addr);
 
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let (stop, stopped) = oneshot::channel();
+    let server = axum::Server::bind(&addr)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+    shutdown::serve_with_grace(
+        server,
+        stop,
+        shutdown_signal(),
+        std::time::Duration::from_secs(cfg.shutdown_grace_secs),
+        &shared_state.in_flight,
+    )
+    .await?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 async fn shutdown_signal() {
@@ -0,0 +1,427 @@
+//! Off-chain mirror of the `ephemeral_vault` program's account layouts.
+//!
+//! Keep in sync with `programs/ephemeral_vault/src/lib.rs`.
+
+use crate::session_manager::{Session, SessionStatus};
+use anyhow::{anyhow, Context, Result};
+use borsh::{BorshDeserialize, BorshSerialize};
+use chrono::{DateTime, Utc};
+use solana_sdk::pubkey::Pubkey;
+
+/// Anchor account discriminator length prepended to every account.
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// Mirrors the program's `MAX_DELEGATES`.
+pub const MAX_DELEGATES: usize = 4;
+
+/// Mirrors the program's `MAX_ALLOWED_PROGRAMS`.
+pub const MAX_ALLOWED_PROGRAMS: usize = 4;
+
+/// Mirrors the program's `DELEGATION_PURPOSE_LEN`.
+pub const DELEGATION_PURPOSE_LEN: usize = 16;
+
+/// Mirrors the program's `TRADE_HISTOGRAM_BUCKETS`.
+pub const TRADE_HISTOGRAM_BUCKETS: usize = 3;
+
+/// Mirrors the program's `MIN_CLEANER_BALANCE_LAMPORTS`, a new vault's `min_cleaner_balance`.
+pub const MIN_CLEANER_BALANCE_LAMPORTS: u64 = 10_000_000;
+
+/// Serialized size of `EphemeralVault`, excluding the discriminator.
+pub const EPHEMERAL_VAULT_LEN: usize = 32 + 32 + 8 + 8 + 1 + 8 + 8 + 8 + 8
+    + 8 + 8 + 8 + 8 // risk policy
+    + 8 + 8 + 8 + 8 + 8 // risk state
+    + 8 + 8 // delegate top-up
+    + 1 // single_use
+    + 1 + 8 // grace_trades, grace_period
+    + 8 + 8 // event_seq, last_acked_seq
+    + 8 // expiry_slot
+    + 1 + 32 // preauthorized_trade
+    + 32 * MAX_DELEGATES // allowed_delegates
+    + 1 // cleaner_reward_enabled
+    + 8 + 8 + 8 // trade cooldown
+    + 32 + 32 + 8 + 8 + 8 // token vault
+    + 8 * TRADE_HISTOGRAM_BUCKETS // trade_histogram
+    + 32 * MAX_ALLOWED_PROGRAMS // allowed_programs
+    + 32 // refund_recipient
+    + 32 // seed_ephemeral_wallet
+    + 1 // is_settled
+    + 8 // min_cleaner_balance
+    + 1;
+
+/// Serialized size of `VaultDelegation`, excluding the discriminator.
+pub const VAULT_DELEGATION_LEN: usize =
+    32 + 32 + 8 + 1 + 8 + 8 + 8 + 8 + DELEGATION_PURPOSE_LEN + 4 + 1;
+
+/// Total on-chain size of a vault account.
+pub const VAULT_ACCOUNT_SPACE: usize = DISCRIMINATOR_LEN + EPHEMERAL_VAULT_LEN;
+
+/// Total on-chain size of a delegation account.
+pub const DELEGATION_ACCOUNT_SPACE: usize = DISCRIMINATOR_LEN + VAULT_DELEGATION_LEN;
+
+/// Mirror of the on-chain `EphemeralVault` account (without the discriminator).
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct EphemeralVault {
+    pub parent_wallet: Pubkey,
+    pub ephemeral_wallet: Pubkey,
+    pub session_start: i64,
+    pub session_expiry: i64,
+    pub is_active: bool,
+    pub total_deposited: u64,
+    pub total_spent: u64,
+    pub max_deposit: u64,
+    pub created_slot: u64,
+    pub per_trade_limit: u64,
+    pub daily_limit: u64,
+    pub idle_timeout: i64,
+    pub max_velocity: u64,
+    pub daily_window_start: i64,
+    pub daily_spent: u64,
+    pub velocity_window_start: i64,
+    pub velocity_window_trades: u64,
+    pub last_activity: i64,
+    pub parent_topup_allowance: u64,
+    pub topped_up_by_delegate: u64,
+    pub single_use: bool,
+    pub grace_trades: u8,
+    pub grace_period: i64,
+    pub event_seq: u64,
+    pub last_acked_seq: u64,
+    pub expiry_slot: u64,
+    pub preauthorized_trade: Option<[u8; 32]>,
+    pub allowed_delegates: [Pubkey; MAX_DELEGATES],
+    pub cleaner_reward_enabled: bool,
+    pub trade_count: u64,
+    pub last_trade_ts: i64,
+    pub min_trade_interval_secs: i64,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub max_token_deposit: u64,
+    pub token_deposited: u64,
+    pub token_spent: u64,
+    pub trade_histogram: [u64; TRADE_HISTOGRAM_BUCKETS],
+    pub allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+    pub refund_recipient: Pubkey,
+    pub seed_ephemeral_wallet: Pubkey,
+    pub is_settled: bool,
+    pub min_cleaner_balance: u64,
+    pub bump: u8,
+}
+
+/// Mirror of the on-chain `VaultDelegation` account (without the discriminator).
+#[derive(Debug, Clone, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct VaultDelegation {
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub approved_at: i64,
+    pub revoked_at: Option<i64>,
+    pub spent: u64,
+    pub expiry_slot: u64,
+    pub expires_at: i64,
+    pub purpose: [u8; DELEGATION_PURPOSE_LEN],
+    pub approval_count: u32,
+    pub bump: u8,
+}
+
+/// The fields shared by the on-chain `EphemeralVault` and the backend `Session`, in backend
+/// types. Used by reconciliation code to compare the two sides without hand-mapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSnapshot {
+    pub parent_wallet: Pubkey,
+    pub ephemeral_wallet: Pubkey,
+    pub vault_pubkey: Option<Pubkey>,
+    pub is_active: bool,
+    pub session_start: DateTime<Utc>,
+    pub session_expiry: DateTime<Utc>,
+    pub max_deposit: u64,
+    pub total_deposited: u64,
+    pub total_spent: u64,
+}
+
+impl SessionSnapshot {
+    pub fn with_vault(mut self, vault_pubkey: Pubkey) -> Self {
+        self.vault_pubkey = Some(vault_pubkey);
+        self
+    }
+}
+
+fn from_unix(ts: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp(ts, 0).ok_or_else(|| anyhow!("timestamp {ts} out of range"))
+}
+
+impl TryFrom<EphemeralVault> for SessionSnapshot {
+    type Error = anyhow::Error;
+
+    /// The vault account does not know its own address, so `vault_pubkey` is left unset;
+    /// use [`SessionSnapshot::with_vault`] to attach it.
+    fn try_from(vault: EphemeralVault) -> Result<Self> {
+        Ok(Self {
+            parent_wallet: vault.parent_wallet,
+            ephemeral_wallet: vault.ephemeral_wallet,
+            vault_pubkey: None,
+            is_active: vault.is_active,
+            session_start: from_unix(vault.session_start)?,
+            session_expiry: from_unix(vault.session_expiry)?,
+            max_deposit: vault.max_deposit,
+            total_deposited: vault.total_deposited,
+            total_spent: vault.total_spent,
+        })
+    }
+}
+
+impl TryFrom<SessionSnapshot> for EphemeralVault {
+    type Error = anyhow::Error;
+
+    /// Fields with no backend counterpart (risk policy, rolling windows, slot, bump) are zeroed;
+    /// the cleaner-reward settings take their creation defaults.
+    fn try_from(snapshot: SessionSnapshot) -> Result<Self> {
+        let session_start = snapshot.session_start.timestamp();
+        let mut allowed_delegates = [Pubkey::default(); MAX_DELEGATES];
+        allowed_delegates[0] = snapshot.ephemeral_wallet;
+        Ok(Self {
+            parent_wallet: snapshot.parent_wallet,
+            ephemeral_wallet: snapshot.ephemeral_wallet,
+            session_start,
+            session_expiry: snapshot.session_expiry.timestamp(),
+            is_active: snapshot.is_active,
+            total_deposited: snapshot.total_deposited,
+            total_spent: snapshot.total_spent,
+            max_deposit: snapshot.max_deposit,
+            daily_window_start: session_start,
+            velocity_window_start: session_start,
+            last_activity: session_start,
+            allowed_delegates,
+            cleaner_reward_enabled: true,
+            min_cleaner_balance: MIN_CLEANER_BALANCE_LAMPORTS,
+            ..Default::default()
+        })
+    }
+}
+
+impl TryFrom<&Session> for SessionSnapshot {
+    type Error = anyhow::Error;
+
+    fn try_from(session: &Session) -> Result<Self> {
+        let vault_pubkey = session
+            .vault_pubkey
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .context("invalid vault_pubkey")?;
+
+        Ok(Self {
+            parent_wallet: session.parent_wallet.parse().context("invalid parent_wallet")?,
+            ephemeral_wallet: session
+                .ephemeral_wallet
+                .parse()
+                .context("invalid ephemeral_wallet")?,
+            vault_pubkey,
+            is_active: matches!(session.status, SessionStatus::Active),
+            session_start: session.session_start,
+            session_expiry: session.session_expiry,
+            max_deposit: session.max_deposit,
+            total_deposited: session.total_deposited,
+            total_spent: session.total_spent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vault with every field set to a distinct non-default value.
+    fn sample_vault() -> EphemeralVault {
+        EphemeralVault {
+            parent_wallet: Pubkey::new_unique(),
+            ephemeral_wallet: Pubkey::new_unique(),
+            session_start: 1_700_000_000,
+            session_expiry: 1_700_003_600,
+            is_active: true,
+            total_deposited: 5_000_000,
+            total_spent: 12_000,
+            max_deposit: 9_000_000,
+            created_slot: 101,
+            per_trade_limit: 102,
+            daily_limit: 103,
+            idle_timeout: 104,
+            max_velocity: 105,
+            daily_window_start: 106,
+            daily_spent: 107,
+            velocity_window_start: 108,
+            velocity_window_trades: 109,
+            last_activity: 110,
+            parent_topup_allowance: 111,
+            topped_up_by_delegate: 112,
+            single_use: true,
+            grace_trades: 2,
+            grace_period: 113,
+            event_seq: 114,
+            last_acked_seq: 115,
+            expiry_slot: 116,
+            preauthorized_trade: Some([7; 32]),
+            allowed_delegates: [(); MAX_DELEGATES].map(|_| Pubkey::new_unique()),
+            cleaner_reward_enabled: true,
+            trade_count: 117,
+            last_trade_ts: 118,
+            min_trade_interval_secs: 119,
+            token_mint: Pubkey::new_unique(),
+            token_account: Pubkey::new_unique(),
+            max_token_deposit: 120,
+            token_deposited: 121,
+            token_spent: 122,
+            trade_histogram: [123, 124, 125],
+            allowed_programs: [(); MAX_ALLOWED_PROGRAMS].map(|_| Pubkey::new_unique()),
+            refund_recipient: Pubkey::new_unique(),
+            seed_ephemeral_wallet: Pubkey::new_unique(),
+            is_settled: true,
+            min_cleaner_balance: 126,
+            bump: 254,
+        }
+    }
+
+    /// `v` laid out by hand in the field order of the program's `EphemeralVault`, as the
+    /// account data reads on-chain after the discriminator.
+    fn onchain_vault_bytes(v: &EphemeralVault) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend(v.parent_wallet.to_bytes());
+        b.extend(v.ephemeral_wallet.to_bytes());
+        b.extend(v.session_start.to_le_bytes());
+        b.extend(v.session_expiry.to_le_bytes());
+        b.push(v.is_active as u8);
+        for n in [v.total_deposited, v.total_spent, v.max_deposit, v.created_slot] {
+            b.extend(n.to_le_bytes());
+        }
+        b.extend(v.per_trade_limit.to_le_bytes());
+        b.extend(v.daily_limit.to_le_bytes());
+        b.extend(v.idle_timeout.to_le_bytes());
+        b.extend(v.max_velocity.to_le_bytes());
+        b.extend(v.daily_window_start.to_le_bytes());
+        b.extend(v.daily_spent.to_le_bytes());
+        b.extend(v.velocity_window_start.to_le_bytes());
+        b.extend(v.velocity_window_trades.to_le_bytes());
+        b.extend(v.last_activity.to_le_bytes());
+        b.extend(v.parent_topup_allowance.to_le_bytes());
+        b.extend(v.topped_up_by_delegate.to_le_bytes());
+        b.push(v.single_use as u8);
+        b.push(v.grace_trades);
+        b.extend(v.grace_period.to_le_bytes());
+        b.extend(v.event_seq.to_le_bytes());
+        b.extend(v.last_acked_seq.to_le_bytes());
+        b.extend(v.expiry_slot.to_le_bytes());
+        match v.preauthorized_trade {
+            Some(hash) => {
+                b.push(1);
+                b.extend(hash);
+            }
+            None => b.push(0),
+        }
+        for delegate in &v.allowed_delegates {
+            b.extend(delegate.to_bytes());
+        }
+        b.push(v.cleaner_reward_enabled as u8);
+        b.extend(v.trade_count.to_le_bytes());
+        b.extend(v.last_trade_ts.to_le_bytes());
+        b.extend(v.min_trade_interval_secs.to_le_bytes());
+        b.extend(v.token_mint.to_bytes());
+        b.extend(v.token_account.to_bytes());
+        for n in [v.max_token_deposit, v.token_deposited, v.token_spent] {
+            b.extend(n.to_le_bytes());
+        }
+        for bucket in v.trade_histogram {
+            b.extend(bucket.to_le_bytes());
+        }
+        for program in &v.allowed_programs {
+            b.extend(program.to_bytes());
+        }
+        b.extend(v.refund_recipient.to_bytes());
+        b.extend(v.seed_ephemeral_wallet.to_bytes());
+        b.push(v.is_settled as u8);
+        b.extend(v.min_cleaner_balance.to_le_bytes());
+        b.push(v.bump);
+        b
+    }
+
+    #[test]
+    fn vault_mirror_matches_the_onchain_layout() {
+        let vault = sample_vault();
+        let onchain = onchain_vault_bytes(&vault);
+        assert_eq!(borsh::to_vec(&vault).unwrap(), onchain);
+        assert_eq!(EphemeralVault::try_from_slice(&onchain).unwrap(), vault);
+
+        // With `preauthorized_trade` set, the account is exactly as large as the program
+        // allocates; `VAULT_ACCOUNT_SPACE` backs rent quotes, so it must not drift.
+        assert_eq!(onchain.len(), EPHEMERAL_VAULT_LEN);
+        assert_eq!(VAULT_ACCOUNT_SPACE, 743, "8 + EphemeralVault::LEN in the program");
+    }
+
+    #[test]
+    fn delegation_mirror_matches_the_onchain_layout() {
+        let mut purpose = [0; DELEGATION_PURPOSE_LEN];
+        purpose[..6].copy_from_slice(b"market");
+        let delegation = VaultDelegation {
+            vault: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            approved_at: 1_700_000_000,
+            revoked_at: Some(1_700_000_500),
+            spent: 42,
+            expiry_slot: 900,
+            expires_at: 1_700_001_000,
+            purpose,
+            approval_count: 3,
+            bump: 253,
+        };
+
+        let mut onchain = Vec::new();
+        onchain.extend(delegation.vault.to_bytes());
+        onchain.extend(delegation.delegate.to_bytes());
+        onchain.extend(delegation.approved_at.to_le_bytes());
+        onchain.push(1);
+        onchain.extend(1_700_000_500i64.to_le_bytes());
+        onchain.extend(delegation.spent.to_le_bytes());
+        onchain.extend(delegation.expiry_slot.to_le_bytes());
+        onchain.extend(delegation.expires_at.to_le_bytes());
+        onchain.extend(delegation.purpose);
+        onchain.extend(delegation.approval_count.to_le_bytes());
+        onchain.push(delegation.bump);
+
+        assert_eq!(borsh::to_vec(&delegation).unwrap(), onchain);
+        assert_eq!(VaultDelegation::try_from_slice(&onchain).unwrap(), delegation);
+        assert_eq!(onchain.len(), VAULT_DELEGATION_LEN);
+        assert_eq!(DELEGATION_ACCOUNT_SPACE, 134, "8 + VaultDelegation::LEN in the program");
+    }
+
+    #[test]
+    fn vault_round_trips_through_a_session_snapshot() {
+        let vault = sample_vault();
+        let vault_pubkey = Pubkey::new_unique();
+        let snapshot = SessionSnapshot::try_from(vault.clone()).unwrap().with_vault(vault_pubkey);
+        assert_eq!(snapshot.vault_pubkey, Some(vault_pubkey));
+        assert_eq!(snapshot.session_start.timestamp(), vault.session_start);
+        assert_eq!(snapshot.session_expiry.timestamp(), vault.session_expiry);
+
+        let back = EphemeralVault::try_from(snapshot.clone()).unwrap();
+        assert_eq!(
+            (back.parent_wallet, back.ephemeral_wallet, back.is_active),
+            (vault.parent_wallet, vault.ephemeral_wallet, vault.is_active)
+        );
+        assert_eq!(
+            (back.session_start, back.session_expiry),
+            (vault.session_start, vault.session_expiry)
+        );
+        assert_eq!(
+            (back.max_deposit, back.total_deposited, back.total_spent),
+            (vault.max_deposit, vault.total_deposited, vault.total_spent)
+        );
+        assert_eq!(back.allowed_delegates[0], vault.ephemeral_wallet);
+        assert_eq!(SessionSnapshot::try_from(back).unwrap().with_vault(vault_pubkey), snapshot);
+    }
+
+    #[test]
+    fn out_of_range_timestamps_are_rejected() {
+        let vault = EphemeralVault {
+            session_expiry: i64::MAX,
+            ..sample_vault()
+        };
+        assert!(SessionSnapshot::try_from(vault).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::Result;
+use futures::StreamExt;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcSignatureSubscribeConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+    rpc_response::{Response, RpcLogsResponse, RpcSignatureResult},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{broadcast, oneshot, OnceCell};
+use tracing::warn;
+
+const LOG_FANOUT_CAPACITY: usize = 256;
+
+/// Single WebSocket connection to the validator shared by every subscriber in the process.
+///
+/// `PubsubClient` already multiplexes subscriptions over one socket; this wrapper makes sure
+/// only one client exists and deduplicates identical log subscriptions. Each distinct
+/// `mentions` filter is subscribed once and fanned out to all holders of a [`LogsHandle`];
+/// the validator-side subscription is dropped when the last handle goes away.
+pub struct SharedPubsub {
+    ws_url: String,
+    commitment: CommitmentConfig,
+    client: OnceCell<Arc<PubsubClient>>,
+    log_subs: Mutex<HashMap<Pubkey, LogSubscription>>,
+}
+
+struct LogSubscription {
+    refs: usize,
+    tx: broadcast::Sender<Response<RpcLogsResponse>>,
+    stop: Option<oneshot::Sender<()>>,
+}
+
+/// A logical log subscription. Dropping it releases its reference on the shared subscription.
+pub struct LogsHandle {
+    pub rx: broadcast::Receiver<Response<RpcLogsResponse>>,
+    mentions: Pubkey,
+    owner: Arc<SharedPubsub>,
+}
+
+impl Drop for LogsHandle {
+    fn drop(&mut self) {
+        self.owner.release_logs(&self.mentions);
+    }
+}
+
+impl SharedPubsub {
+    pub fn new(ws_url: String, commitment: CommitmentConfig) -> Arc<Self> {
+        Arc::new(Self {
+            ws_url,
+            commitment,
+            client: OnceCell::new(),
+            log_subs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Connects lazily so the backend can start while the validator is unreachable.
+    async fn client(&self) -> Result<Arc<PubsubClient>> {
+        let client = self
+            .client
+            .get_or_try_init(|| async { PubsubClient::new(&self.ws_url).await.map(Arc::new) })
+            .await?;
+        Ok(client.clone())
+    }
+
+    /// Number of distinct validator-side log subscriptions currently open.
+    pub fn active_log_subscriptions(&self) -> usize {
+        self.log_subs.lock().unwrap().len()
+    }
+
+    pub async fn subscribe_logs(self: &Arc<Self>, mentions: Pubkey) -> Result<LogsHandle> {
+        if let Some(sub) = self.log_subs.lock().unwrap().get_mut(&mentions) {
+            sub.refs += 1;
+            return Ok(LogsHandle {
+                rx: sub.tx.subscribe(),
+                mentions,
+                owner: self.clone(),
+            });
+        }
+
+        let client = self.client().await?;
+        let (tx, rx) = broadcast::channel(LOG_FANOUT_CAPACITY);
+        let (stop_tx, stop_rx) = oneshot::channel();
+
+        {
+            let mut subs = self.log_subs.lock().unwrap();
+            // Another caller may have subscribed while we were connecting.
+            if let Some(sub) = subs.get_mut(&mentions) {
+                sub.refs += 1;
+                return Ok(LogsHandle {
+                    rx: sub.tx.subscribe(),
+                    mentions,
+                    owner: self.clone(),
+                });
+            }
+            subs.insert(
+                mentions,
+                LogSubscription {
+                    refs: 1,
+                    tx: tx.clone(),
+                    stop: Some(stop_tx),
+                },
+            );
+        }
+
+        let commitment = self.commitment;
+        tokio::spawn(async move {
+            let config = RpcTransactionLogsConfig {
+                commitment: Some(commitment),
+            };
+            let filter = RpcTransactionLogsFilter::Mentions(vec![mentions.to_string()]);
+            let (mut stream, unsubscribe) = match client.logs_subscribe(filter, config).await {
+                Ok(sub) => sub,
+                Err(err) => {
+                    warn!(%mentions, error = %err, "logs_subscribe_failed");
+                    return;
+                }
+            };
+
+            tokio::pin!(stop_rx);
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    msg = stream.next() => match msg {
+                        Some(msg) => {
+                            let _ = tx.send(msg);
+                        }
+                        None => break,
+                    },
+                }
+            }
+            unsubscribe().await;
+        });
+
+        Ok(LogsHandle {
+            rx,
+            mentions,
+            owner: self.clone(),
+        })
+    }
+
+    fn release_logs(&self, mentions: &Pubkey) {
+        let mut subs = self.log_subs.lock().unwrap();
+        let Some(sub) = subs.get_mut(mentions) else { return };
+        sub.refs -= 1;
+        if sub.refs == 0 {
+            if let Some(stop) = sub.stop.take() {
+                let _ = stop.send(());
+            }
+            subs.remove(mentions);
+        }
+    }
+
+    /// Waits for a single signature notification over the shared connection.
+    pub async fn wait_for_signature(&self, signature: &Signature) -> Result<RpcSignatureResult> {
+        let client = self.client().await?;
+        let config = RpcSignatureSubscribeConfig {
+            commitment: Some(self.commitment),
+            enable_received_notification: Some(false),
+        };
+        let (mut stream, unsubscribe) = client.signature_subscribe(signature, Some(config)).await?;
+        let result = stream.next().await;
+        unsubscribe().await;
+        result
+            .map(|r| r.value)
+            .ok_or_else(|| anyhow::anyhow!("signature subscription closed for {signature}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::ws::{Message, WebSocket, WebSocketUpgrade},
+        routing::get,
+        Router,
+    };
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    /// Minimal validator pubsub endpoint: answers `logsSubscribe` with a fresh subscription id
+    /// followed by one notification naming the filtered key, and counts connections and calls.
+    #[derive(Default)]
+    struct StubValidator {
+        connections: AtomicUsize,
+        next_sub: AtomicU64,
+        methods: Mutex<Vec<String>>,
+    }
+
+    impl StubValidator {
+        async fn serve(self: Arc<Self>, mut socket: WebSocket) {
+            self.connections.fetch_add(1, Ordering::SeqCst);
+            while let Some(Ok(Message::Text(text))) = socket.recv().await {
+                let req: Value = serde_json::from_str(&text).unwrap();
+                let method = req["method"].as_str().unwrap().to_string();
+                self.methods.lock().unwrap().push(method.clone());
+                let mut replies = Vec::new();
+                if method == "logsSubscribe" {
+                    let sub = self.next_sub.fetch_add(1, Ordering::SeqCst);
+                    let mentions = req["params"][0]["mentions"][0].clone();
+                    replies.push(json!({ "jsonrpc": "2.0", "result": sub, "id": req["id"] }));
+                    replies.push(json!({
+                        "jsonrpc": "2.0",
+                        "method": "logsNotification",
+                        "params": {
+                            "subscription": sub,
+                            "result": {
+                                "context": { "slot": 1 },
+                                "value": { "signature": "", "err": null, "logs": [mentions] },
+                            },
+                        },
+                    }));
+                } else {
+                    replies.push(json!({ "jsonrpc": "2.0", "result": true, "id": req["id"] }));
+                }
+                for reply in replies {
+                    if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+
+        fn calls(&self, method: &str) -> usize {
+            self.methods.lock().unwrap().iter().filter(|m| *m == method).count()
+        }
+    }
+
+    async fn stub_validator() -> (Arc<StubValidator>, String) {
+        let stub = Arc::new(StubValidator::default());
+        let router = Router::new().route(
+            "/",
+            get({
+                let stub = stub.clone();
+                move |ws: WebSocketUpgrade| async move { ws.on_upgrade(move |s| stub.serve(s)) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (stub, url)
+    }
+
+    async fn next_logs(handle: &mut LogsHandle) -> Vec<String> {
+        let msg = tokio::time::timeout(std::time::Duration::from_secs(5), handle.rx.recv())
+            .await
+            .expect("no log notification")
+            .unwrap();
+        msg.value.logs
+    }
+
+    #[tokio::test]
+    async fn subscribers_share_one_connection_and_one_subscription_per_filter() {
+        let (stub, url) = stub_validator().await;
+        let pubsub = SharedPubsub::new(url, CommitmentConfig::confirmed());
+        let (vault, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let mut first = pubsub.subscribe_logs(vault).await.unwrap();
+        let mut second = pubsub.subscribe_logs(vault).await.unwrap();
+        let mut third = pubsub.subscribe_logs(other).await.unwrap();
+        assert_eq!(pubsub.active_log_subscriptions(), 2);
+
+        // Both holders of the `vault` filter see its single notification.
+        assert_eq!(next_logs(&mut first).await, vec![vault.to_string()]);
+        assert_eq!(next_logs(&mut second).await, vec![vault.to_string()]);
+        assert_eq!(next_logs(&mut third).await, vec![other.to_string()]);
+        assert_eq!(stub.connections.load(Ordering::SeqCst), 1);
+        assert_eq!(stub.calls("logsSubscribe"), 2);
+
+        drop(first);
+        assert_eq!(pubsub.active_log_subscriptions(), 2);
+        drop(second);
+        drop(third);
+        assert_eq!(pubsub.active_log_subscriptions(), 0);
+
+        // Resubscribing reuses the open connection.
+        let mut again = pubsub.subscribe_logs(vault).await.unwrap();
+        assert_eq!(next_logs(&mut again).await, vec![vault.to_string()]);
+        assert_eq!(stub.connections.load(Ordering::SeqCst), 1);
+        assert_eq!(stub.calls("logsSubscribe"), 3);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Number of tracked keys at which a limiter first drops keys with no hits left in the window.
+const MIN_SWEEP_KEYS: usize = 1024;
+
+/// In-memory sliding-window rate limiter keyed by `K`.
+pub struct RateLimiter<K> {
+    limit: usize,
+    window: Duration,
+    hits: Mutex<HashMap<K, Vec<Instant>>>,
+    /// Key count that triggers the next sweep of idle keys; doubles with the live key count so
+    /// sweeping stays amortised O(1) per hit.
+    sweep_at: AtomicUsize,
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            hits: Mutex::new(HashMap::new()),
+            sweep_at: AtomicUsize::new(MIN_SWEEP_KEYS),
+        }
+    }
+
+    /// Records a hit for `key` and returns whether it is within the limit. Rejected hits are
+    /// not recorded, so a client that backs off regains capacity as the window slides.
+    pub fn check(&self, key: &K) -> bool {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap();
+        let entry = self.live_hits(&mut hits, key, now);
+        if entry.len() >= self.limit {
+            return false;
+        }
+        entry.push(now);
+        true
+    }
+
+    /// `key`'s hits still inside the window at `now`. Keys whose window has emptied are
+    /// dropped once the map grows past `sweep_at`, so callers that come and go do not
+    /// accumulate forever.
+    fn live_hits<'a>(
+        &self,
+        hits: &'a mut HashMap<K, Vec<Instant>>,
+        key: &K,
+        now: Instant,
+    ) -> &'a mut Vec<Instant> {
+        if hits.len() >= self.sweep_at.load(Ordering::Relaxed) {
+            hits.retain(|_, ts| ts.last().is_some_and(|t| now.duration_since(*t) < self.window));
+            self.sweep_at
+                .store((hits.len() * 2).max(MIN_SWEEP_KEYS), Ordering::Relaxed);
+        }
+        let entry = hits.entry(key.clone()).or_default();
+        entry.retain(|t| now.duration_since(*t) < self.window);
+        entry
+    }
+}
+
+/// Limits callers on two independent dimensions: the client IP and, when the request names
+/// one, the wallet it acts for. A hit is admitted only if both are under their limits, so one
+/// wallet cannot spread load across many IPs and one IP cannot fan out across many wallets.
+/// Requests without a wallet fall back to the IP limit alone.
+pub struct CompositeRateLimiter {
+    by_ip: RateLimiter<IpAddr>,
+    by_wallet: RateLimiter<String>,
+}
+
+impl CompositeRateLimiter {
+    pub fn new(ip_limit: usize, wallet_limit: usize, window: Duration) -> Self {
+        Self {
+            by_ip: RateLimiter::new(ip_limit, window),
+            by_wallet: RateLimiter::new(wallet_limit, window),
+        }
+    }
+
+    /// Like [`RateLimiter::check`], across both dimensions. A hit rejected by either limit is
+    /// recorded against neither.
+    pub fn check(&self, ip: IpAddr, wallet: Option<&str>) -> bool {
+        let now = Instant::now();
+        // Always lock the IP map first so concurrent checks cannot deadlock.
+        let mut ip_hits = self.by_ip.hits.lock().unwrap();
+        let ip_entry = self.by_ip.live_hits(&mut ip_hits, &ip, now);
+        if ip_entry.len() >= self.by_ip.limit {
+            return false;
+        }
+        if let Some(wallet) = wallet {
+            let mut wallet_hits = self.by_wallet.hits.lock().unwrap();
+            let wallet_entry = self
+                .by_wallet
+                .live_hits(&mut wallet_hits, &wallet.to_string(), now);
+            if wallet_entry.len() >= self.by_wallet.limit {
+                return false;
+            }
+            wallet_entry.push(now);
+        }
+        ip_entry.push(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[test]
+    fn ip_limit_trips_despite_distinct_wallets() {
+        let limiter = CompositeRateLimiter::new(2, 10, WINDOW);
+        assert!(limiter.check(ip(1), Some("wallet-a")));
+        assert!(limiter.check(ip(1), Some("wallet-b")));
+        assert!(!limiter.check(ip(1), Some("wallet-c")));
+        assert!(!limiter.check(ip(1), None));
+        assert!(limiter.check(ip(2), Some("wallet-c")));
+    }
+
+    #[test]
+    fn wallet_limit_trips_across_ips() {
+        let limiter = CompositeRateLimiter::new(10, 2, WINDOW);
+        assert!(limiter.check(ip(1), Some("wallet-a")));
+        assert!(limiter.check(ip(2), Some("wallet-a")));
+        assert!(!limiter.check(ip(3), Some("wallet-a")));
+        assert!(limiter.check(ip(3), Some("wallet-b")));
+    }
+
+    #[test]
+    fn rejected_hits_count_against_neither_dimension() {
+        let limiter = CompositeRateLimiter::new(10, 1, WINDOW);
+        assert!(limiter.check(ip(1), Some("wallet-a")));
+        for _ in 0..5 {
+            assert!(!limiter.check(ip(1), Some("wallet-a")));
+        }
+        // Only the admitted hit was recorded against ip(1).
+        assert_eq!(limiter.by_ip.hits.lock().unwrap()[&ip(1)].len(), 1);
+    }
+
+    #[test]
+    fn window_slides_and_rejected_hits_are_not_recorded() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(50));
+        assert!(limiter.check(&"wallet"));
+        assert!(limiter.check(&"wallet"));
+        assert!(!limiter.check(&"wallet"));
+        assert!(limiter.check(&"other"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.check(&"wallet"));
+        assert!(limiter.check(&"wallet"));
+        assert!(!limiter.check(&"wallet"));
+    }
+
+    #[test]
+    fn idle_keys_are_swept_once_the_map_is_large() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+        for key in 0..MIN_SWEEP_KEYS {
+            assert!(limiter.check(&key));
+        }
+        assert_eq!(limiter.hits.lock().unwrap().len(), MIN_SWEEP_KEYS);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check(&MIN_SWEEP_KEYS));
+        let hits = limiter.hits.lock().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits.contains_key(&MIN_SWEEP_KEYS));
+        assert_eq!(limiter.sweep_at.load(Ordering::Relaxed), MIN_SWEEP_KEYS);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::config::SolanaConfig;
+use anyhow::Result;
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
+use std::{
+    ops::Deref,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A fixed set of RPC clients, possibly spread over several endpoints, handed out healthiest
+/// first. A client that fails `max_consecutive_errors` calls in a row is benched for
+/// `cooldown`, then re-admitted with a clean record; one success resets its count.
+pub struct RpcPool {
+    clients: Vec<PooledClient>,
+    health: Mutex<Vec<Health>>,
+    max_consecutive_errors: u32,
+    cooldown: Duration,
+}
+
+struct PooledClient {
+    url: String,
+    rpc: Arc<RpcClient>,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_errors: u32,
+    benched_until: Option<Instant>,
+    /// Leases handed out and not yet reported back.
+    in_flight: u32,
+}
+
+/// A client borrowed from the pool. Report how the call went with [`RpcPool::report`] (or use
+/// [`RpcPool::call`], which does it for you) so the pool can steer away from failing clients.
+pub struct RpcLease {
+    rpc: Arc<RpcClient>,
+    slot: usize,
+}
+
+impl Deref for RpcLease {
+    type Target = RpcClient;
+
+    fn deref(&self) -> &RpcClient {
+        &self.rpc
+    }
+}
+
+impl RpcPool {
+    /// Opens `clients_per_endpoint` clients for each of `urls`, in order.
+    pub fn new(
+        urls: &[String],
+        clients_per_endpoint: usize,
+        commitment: solana_sdk::commitment_config::CommitmentConfig,
+        max_consecutive_errors: u32,
+        cooldown: Duration,
+    ) -> Self {
+        let clients: Vec<PooledClient> = urls
+            .iter()
+            .flat_map(|url| std::iter::repeat(url).take(clients_per_endpoint))
+            .map(|url| PooledClient {
+                url: url.clone(),
+                rpc: Arc::new(RpcClient::new_with_commitment(url.clone(), commitment)),
+            })
+            .collect();
+        let health = clients.iter().map(|_| Health::default()).collect();
+        Self {
+            clients,
+            health: Mutex::new(health),
+            max_consecutive_errors,
+            cooldown,
+        }
+    }
+
+    /// Pool over `rpc_url` and `rpc_pool.extra_urls`, sized by `rpc_pool`.
+    pub fn from_config(cfg: &SolanaConfig) -> Result<Self> {
+        let urls: Vec<String> = std::iter::once(cfg.rpc_url.clone())
+            .chain(cfg.rpc_pool.extra_urls.iter().cloned())
+            .collect();
+        Ok(Self::new(
+            &urls,
+            cfg.rpc_pool.clients_per_endpoint,
+            cfg.default_commitment()?,
+            cfg.rpc_pool.max_consecutive_errors,
+            Duration::from_secs(cfg.rpc_pool.cooldown_secs),
+        ))
+    }
+
+    /// The healthiest client: fewest consecutive errors, then fewest leases in flight. Benched
+    /// clients whose cool-down has run out are re-admitted first. If every client is benched,
+    /// the one due back soonest is handed out anyway rather than failing the caller.
+    pub fn get(&self) -> RpcLease {
+        let now = Instant::now();
+        let mut health = self.health.lock().unwrap();
+        for h in health.iter_mut() {
+            if h.benched_until.is_some_and(|until| until <= now) {
+                h.benched_until = None;
+                h.consecutive_errors = 0;
+            }
+        }
+        let slot = (0..health.len())
+            .filter(|&i| health[i].benched_until.is_none())
+            .min_by_key(|&i| (health[i].consecutive_errors, health[i].in_flight))
+            .or_else(|| (0..health.len()).min_by_key(|&i| health[i].benched_until))
+            .expect("rpc pool has at least one client");
+        health[slot].in_flight += 1;
+        RpcLease {
+            rpc: self.clients[slot].rpc.clone(),
+            slot,
+        }
+    }
+
+    /// Records the outcome of a call made with `lease`, benching its client once it has
+    /// failed `max_consecutive_errors` times in a row.
+    pub fn report(&self, lease: RpcLease, ok: bool) {
+        let mut health = self.health.lock().unwrap();
+        let h = &mut health[lease.slot];
+        h.in_flight = h.in_flight.saturating_sub(1);
+        if ok {
+            h.consecutive_errors = 0;
+            return;
+        }
+        h.consecutive_errors += 1;
+        if h.consecutive_errors >= self.max_consecutive_errors && h.benched_until.is_none() {
+            h.benched_until = Some(Instant::now() + self.cooldown);
+            tracing::warn!(
+                url = %self.clients[lease.slot].url,
+                errors = h.consecutive_errors,
+                cooldown_secs = self.cooldown.as_secs(),
+                "rpc client taken out of rotation"
+            );
+        }
+    }
+
+    /// Runs `f` on the healthiest client and reports the result.
+    pub fn call<T>(
+        &self,
+        f: impl FnOnce(&RpcClient) -> Result<T, ClientError>,
+    ) -> Result<T, ClientError> {
+        let lease = self.get();
+        let result = f(&lease);
+        self.report(lease, result.is_ok());
+        result
+    }
+
+    /// Like [`Self::call`], for async callers: `f` runs on the blocking thread pool, so a slow
+    /// node ties up a blocking thread instead of a runtime worker. Any error from `f` counts
+    /// against the client.
+    pub async fn call_blocking<T, F>(self: &Arc<Self>, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&RpcClient) -> Result<T> + Send + 'static,
+    {
+        let pool = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let lease = pool.get();
+            let result = f(&lease);
+            pool.report(lease, result.is_ok());
+            result
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::commitment_config::CommitmentConfig;
+
+    const A: &str = "http://rpc-a.invalid";
+    const B: &str = "http://rpc-b.invalid";
+
+    #[test]
+    fn failing_endpoint_is_benched_then_readmitted_after_cooldown() {
+        let cooldown = Duration::from_millis(50);
+        let pool = RpcPool::new(
+            &[A.to_string(), B.to_string()],
+            1,
+            CommitmentConfig::confirmed(),
+            2,
+            cooldown,
+        );
+
+        // Ties go to the first client, then the one with fewer leases in flight.
+        let (a1, b, a2) = (pool.get(), pool.get(), pool.get());
+        assert_eq!((a1.url(), b.url(), a2.url()), (A.into(), B.into(), A.into()));
+        pool.report(a1, false);
+        pool.report(a2, false);
+        pool.report(b, true);
+
+        // Benched: `B` is handed out even as its own leases pile up.
+        let held: Vec<RpcLease> = (0..3).map(|_| pool.get()).collect();
+        assert!(held.iter().all(|lease| lease.url() == B));
+
+        std::thread::sleep(cooldown + Duration::from_millis(10));
+        let readmitted = pool.get();
+        assert_eq!(readmitted.url(), A);
+
+        // Re-admitted with a clean record: one more failure does not bench it again.
+        pool.report(readmitted, false);
+        for lease in held {
+            pool.report(lease, true);
+        }
+        let health = pool.health.lock().unwrap();
+        assert_eq!(health[0].consecutive_errors, 1);
+        assert!(health[0].benched_until.is_none());
+    }
+}
@@ -1,21 +1,80 @@
-use crate::config::Config;
+use crate::{
+    config::Config,
+    delegation_manager::{self, DelegationError, DelegationManager},
+    session_store::{
+        session_columns, KeyRotation, PgSessionStore, SessionRow, SessionStore,
+        StagedKeyRotation,
+    },
+};
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
+use futures::{Stream, StreamExt};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
 use sqlx::{Pool, Postgres};
+use std::future::Future;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Stored in the `TEXT` column `sessions.status` as `CREATED`, `NEEDS_MANUAL`, etc. Decoding
+/// any other value fails instead of guessing a status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SessionStatus {
     Created,
     Active,
     Revoked,
     Expired,
     Cleaned,
+    Archived,
+    /// Cleanup kept failing; parked for an operator instead of being retried forever.
+    NeedsManual,
+}
+
+impl SessionStatus {
+    /// Statuses from which a session may legally move to `self`:
+    /// Created → Active → {Revoked, Expired} → Cleaned → Archived, plus revoking or expiring
+    /// a session that never became active. A session whose cleanup keeps failing detours
+    /// through NeedsManual before an operator cleans it.
+    pub fn predecessors(self) -> &'static [SessionStatus] {
+        use SessionStatus::*;
+        match self {
+            Created => &[],
+            Active => &[Created],
+            Revoked | Expired => &[Created, Active],
+            NeedsManual => &[Revoked, Expired],
+            Cleaned => &[Revoked, Expired, NeedsManual],
+            Archived => &[Cleaned],
+        }
+    }
+
+    pub fn can_transition_to(self, next: SessionStatus) -> bool {
+        next.predecessors().contains(&self)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("session {0} not found")]
+    NotFound(Uuid),
+    #[error("illegal session transition from {from:?} to {to:?}")]
+    InvalidTransition {
+        from: SessionStatus,
+        to: SessionStatus,
+    },
+    #[error("session {0} uses a client-managed key; server-side signing is unavailable")]
+    ClientManagedKey(Uuid),
+    #[error("ephemeral wallet {0} is already registered to another session")]
+    EphemeralWalletTaken(String),
 }
 
+/// Server-generated ephemeral keys are regenerated this many times on a pubkey collision
+/// before `create_session` gives up.
+const MAX_EPHEMERAL_KEYGEN_ATTEMPTS: u32 = 3;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub id: Uuid,
@@ -26,166 +85,1076 @@ pub struct Session {
     pub session_start: DateTime<Utc>,
     pub session_expiry: DateTime<Utc>,
     pub last_activity: DateTime<Utc>,
+    #[serde(with = "crate::string_u64")]
     pub max_deposit: u64,
+    #[serde(with = "crate::string_u64")]
     pub total_deposited: u64,
+    #[serde(with = "crate::string_u64")]
+    pub total_spent: u64,
+    pub key_fingerprint: String,
+    /// False when the client generated the ephemeral key and registered only its pubkey; the
+    /// server then holds no secret and cannot sign for this session.
+    pub server_managed_key: bool,
+    /// Starts at 1 and increases with every change to the session. Pass it back to
+    /// `GET /session/changes` to fetch only what changed since.
+    #[serde(with = "crate::string_u64")]
+    pub version: u64,
+}
+
+/// Non-sensitive metadata about a session's current ephemeral key. Never carries key material.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyMeta {
+    pub created_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub key_fingerprint: String,
+    pub server_managed_key: bool,
+}
+
+/// A session awaiting on-chain cleanup, with its retry bookkeeping.
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupRetry {
+    pub session_id: Uuid,
+    pub parent_wallet: String,
+    pub vault_pubkey: String,
+    pub status: SessionStatus,
+    pub cleanup_attempts: u32,
+    pub last_cleanup_attempt_at: Option<DateTime<Utc>>,
+    pub cleanup_last_error: Option<String>,
+}
+
+/// A session an operator needs to look at: dead-lettered cleanup or DB/chain divergence.
+#[derive(Debug, Clone, Serialize)]
+pub struct AttentionItem {
+    pub session_id: Uuid,
+    pub parent_wallet: String,
+    pub vault_pubkey: Option<String>,
+    pub status: SessionStatus,
+    pub needs_reconciliation: bool,
+    /// Reconciliation reason if flagged, otherwise the last cleanup error.
+    pub reason: Option<String>,
+    pub cleanup_attempts: u32,
+    pub last_cleanup_attempt_at: Option<DateTime<Utc>>,
+    pub last_activity: DateTime<Utc>,
+}
+
+/// Payload of `SessionEvent::SpendAlert`: the session's spend crossed its alert threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendAlert {
+    pub session_id: Uuid,
+    pub parent_wallet: String,
+    pub vault_pubkey: Option<String>,
+    pub spend_alert_bps: u16,
+    #[serde(with = "crate::string_u64")]
     pub total_spent: u64,
+    #[serde(with = "crate::string_u64")]
+    pub max_deposit: u64,
+}
+
+/// An active session with an armed spend alert, whose vault the monitor polls.
+#[derive(Debug, Clone)]
+pub struct SpendWatch {
+    pub session_id: Uuid,
+    pub vault_pubkey: String,
+    pub max_deposit: u64,
+    pub spend_alert_bps: u16,
+}
+
+impl SpendWatch {
+    /// Whether `total_spent` has reached `spend_alert_bps` of `max_deposit`. Never true when
+    /// `max_deposit` is zero, since there is no cap to measure spend against.
+    pub fn is_due(&self, total_spent: u64) -> bool {
+        self.max_deposit > 0
+            && u128::from(total_spent) * 10_000
+                >= u128::from(self.max_deposit) * u128::from(self.spend_alert_bps)
+    }
+}
+
+/// Aggregate session figures for the ops overview, taken in one query.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// Sessions per status; statuses with no sessions are omitted.
+    pub counts: Vec<StatusCount>,
+    /// Seconds since the oldest `ACTIVE` session started; absent when none is active.
+    pub oldest_active_age_secs: Option<i64>,
+    /// `total_deposited - total_spent` summed over `ACTIVE` sessions.
+    #[serde(with = "crate::string_u64")]
+    pub active_outstanding_lamports: u64,
+    /// Sessions past `session_expiry` that are neither `CLEANED` nor `ARCHIVED`.
+    pub expired_uncleaned: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusCount {
+    pub status: SessionStatus,
+    pub count: u64,
+}
+
+/// What changed in a session after a version the client already holds.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionChanges {
+    /// Current version; the client's next `since_version`.
+    #[serde(with = "crate::string_u64")]
+    pub version: u64,
+    /// Set when the client's version has no recorded snapshot, in which case `changes` holds
+    /// every field.
+    pub full: bool,
+    /// Changed fields with their current values, encoded exactly as in `Session`.
+    pub changes: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Narrows `stream_sessions`; unset fields match every session.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionFilter {
+    pub parent_wallet: Option<String>,
+    pub status: Option<SessionStatus>,
+}
+
+/// Where `resume_bootstrap` left a session that was still `CREATED`.
+#[derive(Debug)]
+pub enum BootstrapOutcome {
+    /// Vault and delegation are live on-chain and the session is `ACTIVE`.
+    Activated(Session),
+    /// The vault was never created; the parent must sign and submit `create_vault` again.
+    AwaitingVault { session: Session, vault_pubkey: Pubkey },
+    /// The vault exists but carries no live delegation yet; the parent must approve it.
+    AwaitingApproval { session: Session, vault_pubkey: Pubkey },
 }
 
-pub struct SessionManager {
-    pool: Pool<Postgres>,
+pub struct SessionManager<S = PgSessionStore> {
+    store: S,
     cfg: Config,
 }
 
-impl SessionManager {
-    pub fn new(pool: Pool<Postgres>, cfg: Config) -> Self {
-        Self { pool, cfg }
+impl<S: SessionStore> SessionManager<S> {
+    pub fn with_store(store: S, cfg: Config) -> Self {
+        Self { store, cfg }
     }
 
+    /// Creates a session. With `client_ephemeral_wallet` set, the client keeps the private key
+    /// and only its pubkey is registered; otherwise the server generates and stores one.
+    /// The generated keypair is returned only in the server-managed case.
+    ///
+    /// An ephemeral pubkey already owned by another session fails with
+    /// `SessionError::EphemeralWalletTaken` when client-provided; a server-generated one is
+    /// regenerated up to `MAX_EPHEMERAL_KEYGEN_ATTEMPTS` times.
+    ///
+    /// `activation_webhook_url`, if set, receives a one-time callback on activation.
+    /// `spend_alert_bps`, if set, arms a one-time `SpendAlert`; see [`Self::record_spend`].
     pub async fn create_session(
         &self,
         parent_wallet: Pubkey,
         session_duration_secs: i64,
         max_deposit: u64,
-    ) -> Result<(Session, Keypair)> {
+        client_ephemeral_wallet: Option<Pubkey>,
+        activation_webhook_url: Option<&str>,
+        spend_alert_bps: Option<u16>,
+    ) -> Result<(Session, Option<Keypair>)> {
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .try_create_session(
+                    parent_wallet,
+                    session_duration_secs,
+                    max_deposit,
+                    client_ephemeral_wallet,
+                    activation_webhook_url,
+                    spend_alert_bps,
+                )
+                .await;
+            match result {
+                Err(err)
+                    if client_ephemeral_wallet.is_none()
+                        && attempt < MAX_EPHEMERAL_KEYGEN_ATTEMPTS
+                        && matches!(
+                            err.downcast_ref::<SessionError>(),
+                            Some(SessionError::EphemeralWalletTaken(_))
+                        ) =>
+                {
+                    tracing::warn!(attempt, error = %err, "ephemeral_key_collision_regenerating");
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_create_session(
+        &self,
+        parent_wallet: Pubkey,
+        session_duration_secs: i64,
+        max_deposit: u64,
+        client_ephemeral_wallet: Option<Pubkey>,
+        activation_webhook_url: Option<&str>,
+        spend_alert_bps: Option<u16>,
+    ) -> Result<(Session, Option<Keypair>)> {
         let now = Utc::now();
         let expiry = now + Duration::seconds(session_duration_secs);
 
+        let session_id = Uuid::new_v4();
+
+        let (ephemeral_wallet, ephemeral, encrypted_key) = match client_ephemeral_wallet {
+            Some(pubkey) => (pubkey, None, None),
+            None => {
+                let mut rng = OsRng;
+                let ephemeral = Keypair::generate(&mut rng);
+                // For this assessment, we store the ephemeral key encrypted using a simple
+                // symmetric scheme (ring AES-GCM). In a production setup this would be an HSM or KMS.
+                let encrypted_key = crate::transaction_signer::encrypt_keypair_blocking(
+                    &ephemeral,
+                    &self.cfg.security.key_encryption_key,
+                )
+                .await?;
+                (ephemeral.pubkey(), Some(ephemeral), Some(encrypted_key))
+            }
+        };
+        let key_fingerprint = crate::transaction_signer::key_fingerprint(&ephemeral_wallet);
+
+        let session = Session {
+            id: session_id,
+            parent_wallet: parent_wallet.to_string(),
+            ephemeral_wallet: ephemeral_wallet.to_string(),
+            vault_pubkey: None,
+            status: SessionStatus::Created,
+            session_start: now,
+            session_expiry: expiry,
+            last_activity: now,
+            max_deposit,
+            total_deposited: 0,
+            total_spent: 0,
+            key_fingerprint,
+            server_managed_key: encrypted_key.is_some(),
+            version: 1,
+        };
+        self.store
+            .insert(
+                &session,
+                encrypted_key.as_deref(),
+                activation_webhook_url,
+                spend_alert_bps,
+            )
+            .await?;
+
+        Ok((session, ephemeral))
+    }
+
+    pub async fn mark_active(&self, session_id: Uuid, vault_pubkey: Pubkey) -> Result<()> {
+        self.store
+            .mark_active(session_id, &vault_pubkey.to_string(), Utc::now())
+            .await
+    }
+
+    pub async fn revoke(&self, session_id: Uuid) -> Result<()> {
+        self.store.revoke(session_id, Utc::now()).await
+    }
+
+    pub async fn get(&self, session_id: Uuid) -> Result<Option<Session>> {
+        self.store.get(session_id).await
+    }
+
+    /// Decrypts the session's stored ephemeral keypair. Callers are responsible for
+    /// authorizing and auditing the access.
+    pub async fn load_ephemeral_keypair(&self, session_id: Uuid) -> Result<Option<Keypair>> {
+        let Some(encrypted_key) = self.store.encrypted_key(session_id).await? else {
+            return Ok(None);
+        };
+        let encrypted_key = encrypted_key.ok_or(SessionError::ClientManagedKey(session_id))?;
+        let keypair = crate::transaction_signer::decrypt_keypair_blocking(
+            encrypted_key,
+            &self.cfg.security.key_encryption_key,
+        )
+        .await?;
+        Ok(Some(keypair))
+    }
+
+    pub async fn record_key_recovery(
+        &self,
+        session_id: Uuid,
+        requester: &str,
+        authorized: bool,
+        reason: &str,
+    ) -> Result<()> {
+        self.store
+            .record_key_recovery(session_id, requester, authorized, reason)
+            .await
+    }
+
+    /// Stores a challenge nonce for `parent_wallet`, pruning ones that expired unredeemed.
+    pub async fn create_auth_challenge(
+        &self,
+        parent_wallet: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.store
+            .create_auth_challenge(parent_wallet, nonce, expires_at)
+            .await
+    }
+
+    /// Redeems a challenge nonce. Returns `false` if `nonce` was never issued to
+    /// `parent_wallet`, has expired, or was already redeemed; the store makes it single-use
+    /// even under concurrent requests.
+    pub async fn consume_auth_challenge(&self, parent_wallet: &str, nonce: &str) -> Result<bool> {
+        self.store.consume_auth_challenge(parent_wallet, nonce).await
+    }
+
+    /// Replaces the session's ephemeral keypair with a freshly generated one. The session id
+    /// is unchanged; the new key's fingerprint is stored so rotations can be audited.
+    pub async fn rotate_key(&self, session_id: Uuid) -> Result<Option<(Session, Keypair)>> {
+        self.rotate_key_with(session_id, |_, _| async { Ok(()) }).await
+    }
+
+    /// Rotates the session key, running `submit_onchain(old_wallet, new_wallet)` between the
+    /// staged store writes and their commit.
+    ///
+    /// All writes are staged together (one transaction in Postgres). If the on-chain step
+    /// fails they are rolled back and nothing changes. If the on-chain step succeeds but the
+    /// commit fails, the chain has already moved on, so the session is flagged
+    /// `needs_reconciliation`.
+    pub async fn rotate_key_with<F, Fut>(
+        &self,
+        session_id: Uuid,
+        submit_onchain: F,
+    ) -> Result<Option<(Session, Keypair)>>
+    where
+        F: FnOnce(Pubkey, Pubkey) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
         let mut rng = OsRng;
         let ephemeral = Keypair::generate(&mut rng);
 
-        let session_id = Uuid::new_v4();
-
-        // For this assessment, we store the ephemeral key encrypted using a simple symmetric
-        // scheme (ring AES-GCM). In a production setup this would be an HSM or KMS.
-        let encrypted_key = crate::transaction_signer::encrypt_keypair(
+        let encrypted_key = crate::transaction_signer::encrypt_keypair_blocking(
             &ephemeral,
             &self.cfg.security.key_encryption_key,
-        )?;
-
-        sqlx::query!(
-            r#"
-            INSERT INTO sessions (
-                id,
-                parent_wallet,
-                ephemeral_wallet,
-                vault_pubkey,
-                status,
-                session_start,
-                session_expiry,
-                last_activity,
-                max_deposit,
-                total_deposited,
-                total_spent,
-                encrypted_ephemeral_key
-            ) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12)
-            "#,
-            session_id,
-            parent_wallet.to_string(),
-            ephemeral.pubkey().to_string(),
-            Option::<String>::None,
-            "CREATED",
-            now,
-            expiry,
-            now,
-            max_deposit as i64,
-            0_i64,
-            0_i64,
+        )
+        .await?;
+        let rotation = KeyRotation {
+            ephemeral_wallet: ephemeral.pubkey().to_string(),
             encrypted_key,
+            key_fingerprint: crate::transaction_signer::key_fingerprint(&ephemeral.pubkey()),
+            now: Utc::now(),
+        };
+
+        let Some(staged) = self.store.stage_key_rotation(session_id, &rotation).await? else {
+            return Ok(None);
+        };
+        let old_wallet: Pubkey = match staged.old_wallet().parse() {
+            Ok(wallet) => wallet,
+            Err(err) => {
+                staged.rollback().await?;
+                return Err(anyhow::Error::from(err));
+            }
+        };
+
+        // Nothing irreversible has happened yet: a chain failure simply rolls the store back.
+        if let Err(err) = submit_onchain(old_wallet, ephemeral.pubkey()).await {
+            staged.rollback().await?;
+            return Err(err);
+        }
+
+        if let Err(err) = staged.commit().await {
+            let reason = format!("key rotation committed on-chain but not in DB: {err}");
+            self.mark_needs_reconciliation(session_id, &reason).await?;
+            return Err(err);
+        }
+
+        Ok(self.get(session_id).await?.map(|session| (session, ephemeral)))
+    }
+
+    /// Flags a session whose on-chain state diverged from the DB so an operator can reconcile it.
+    pub async fn mark_needs_reconciliation(&self, session_id: Uuid, reason: &str) -> Result<()> {
+        tracing::warn!(%session_id, reason, "session needs reconciliation");
+        self.store.mark_needs_reconciliation(session_id, reason).await
+    }
+
+    /// Revoked or expired sessions whose vault is past expiry and so can be cleaned on-chain.
+    /// Backoff between attempts is applied by the caller.
+    pub async fn cleanup_candidates(&self, now: DateTime<Utc>) -> Result<Vec<CleanupRetry>> {
+        self.store.cleanup_candidates(now).await
+    }
+
+    pub async fn record_cleanup_success(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        self.store.record_cleanup_success(session_id, now).await
+    }
+
+    /// Counts a failed cleanup. Once `max_attempts` is reached the session moves to
+    /// `NEEDS_MANUAL` and drops out of `cleanup_candidates`. Returns the resulting status.
+    ///
+    /// Only sessions that may still move to `NEEDS_MANUAL` (those awaiting cleanup) count
+    /// failures; any other status fails with `SessionError::InvalidTransition` and nothing
+    /// is recorded.
+    pub async fn record_cleanup_failure(
+        &self,
+        session_id: Uuid,
+        error: &str,
+        max_attempts: u32,
+        now: DateTime<Utc>,
+    ) -> Result<SessionStatus> {
+        self.store
+            .record_cleanup_failure(session_id, error, max_attempts, now)
+            .await
+    }
+
+    /// The underlying store, so tests can inspect what the manager wrote.
+    #[cfg(any(test, feature = "test-store"))]
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Recovers a session whose bootstrap was interrupted between inserting the row and
+    /// confirming the vault. Derives the vault PDA and activates the session if the vault and
+    /// its delegation are live; otherwise reports which parent-signed step is still missing.
+    /// Already-active sessions are returned unchanged, so retries are safe.
+    pub async fn resume_bootstrap(
+        &self,
+        session_id: Uuid,
+        delegations: &DelegationManager,
+    ) -> Result<BootstrapOutcome> {
+        let session = self
+            .get(session_id)
+            .await?
+            .ok_or(SessionError::NotFound(session_id))?;
+        match session.status {
+            SessionStatus::Created => {}
+            SessionStatus::Active => return Ok(BootstrapOutcome::Activated(session)),
+            from => {
+                return Err(SessionError::InvalidTransition {
+                    from,
+                    to: SessionStatus::Active,
+                }
+                .into())
+            }
+        }
+
+        let program_id = self.cfg.solana.program_id()?;
+        let parent_wallet: Pubkey = session.parent_wallet.parse()?;
+        let ephemeral_wallet: Pubkey = session.ephemeral_wallet.parse()?;
+        let vault_pubkey = delegation_manager::vault_pda(&program_id, &parent_wallet, &ephemeral_wallet);
+
+        if !delegations.account_exists(vault_pubkey).await? {
+            return Ok(BootstrapOutcome::AwaitingVault { session, vault_pubkey });
+        }
+        match delegations
+            .verify_delegation_onchain(&session, vault_pubkey, program_id)
+            .await
+        {
+            Ok(()) => {}
+            Err(DelegationError::AccountNotFound(_) | DelegationError::InvalidDelegation(_)) => {
+                return Ok(BootstrapOutcome::AwaitingApproval { session, vault_pubkey });
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        self.mark_active(session_id, vault_pubkey).await?;
+        let session = self
+            .get(session_id)
+            .await?
+            .ok_or(SessionError::NotFound(session_id))?;
+        Ok(BootstrapOutcome::Activated(session))
+    }
+}
+
+impl SessionManager<PgSessionStore> {
+    pub fn new(pool: Pool<Postgres>, cfg: Config) -> Self {
+        Self::with_store(PgSessionStore::new(pool), cfg)
+    }
+
+    fn pool(&self) -> &Pool<Postgres> {
+        self.store.pool()
+    }
+
+    /// The sessions among `ids` that exist, in no particular order.
+    pub async fn get_many(&self, ids: &[Uuid]) -> Result<Vec<Session>> {
+        let rows = sqlx::query_as::<_, SessionRow>(concat!(
+            "SELECT ",
+            session_columns!(),
+            " FROM sessions WHERE id = ANY($1)"
+        ))
+        .bind(ids)
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows.into_iter().map(Session::from).collect())
+    }
+
+    /// One page of sessions, newest first, optionally narrowed to one parent wallet and/or
+    /// status, together with the number of sessions matching the filter across all pages.
+    pub async fn list_sessions(
+        &self,
+        parent_wallet: Option<Pubkey>,
+        status: Option<SessionStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Session>, u64)> {
+        let parent_wallet = parent_wallet.map(|p| p.to_string());
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!"
+               FROM sessions
+               WHERE ($1::text IS NULL OR parent_wallet = $1)
+                 AND ($2::text IS NULL OR status = $2)"#,
+            parent_wallet,
+            status as Option<SessionStatus>,
         )
-        .execute(&self.pool)
-        .await?;
-
-        Ok((
-            Session {
-                id: session_id,
-                parent_wallet: parent_wallet.to_string(),
-                ephemeral_wallet: ephemeral.pubkey().to_string(),
-                vault_pubkey: None,
-                status: SessionStatus::Created,
-                session_start: now,
-                session_expiry: expiry,
-                last_activity: now,
-                max_deposit,
-                total_deposited: 0,
-                total_spent: 0,
-            },
-            ephemeral,
+        .fetch_one(self.pool())
+        .await?;
+        let rows = sqlx::query_as::<_, SessionRow>(concat!(
+            "SELECT ",
+            session_columns!(),
+            " FROM sessions
+               WHERE ($1::text IS NULL OR parent_wallet = $1)
+                 AND ($2::text IS NULL OR status = $2)
+               ORDER BY session_start DESC, id
+               LIMIT $3 OFFSET $4"
         ))
+        .bind(parent_wallet.as_deref())
+        .bind(status)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool())
+        .await?;
+        let sessions = rows.into_iter().map(Session::from).collect();
+        Ok((sessions, total as u64))
     }
 
-    pub async fn mark_active(&self, session_id: Uuid, vault_pubkey: Pubkey) -> Result<()> {
-        let now = Utc::now();
-        sqlx::query!(
+    /// Yields matching sessions oldest first, decoding rows as Postgres returns them instead of
+    /// collecting the whole result set, so exports and scans stay flat in memory.
+    pub fn stream_sessions<'a>(
+        &'a self,
+        filter: &'a SessionFilter,
+    ) -> impl Stream<Item = Result<Session>> + Send + 'a {
+        sqlx::query_as::<_, SessionRow>(concat!(
+            "SELECT ",
+            session_columns!(),
+            " FROM sessions
+               WHERE ($1::text IS NULL OR parent_wallet = $1)
+                 AND ($2::text IS NULL OR status = $2)
+               ORDER BY session_start, id"
+        ))
+        .bind(filter.parent_wallet.as_deref())
+        .bind(filter.status)
+        .fetch(self.pool())
+        .map(|row| Ok(Session::from(row?)))
+    }
+
+    /// Returns the session together with metadata about its ephemeral key. The key was issued
+    /// at `session_start`; `rotated_at` is set once `rotate_key` has replaced it.
+    pub async fn get_with_key_meta(&self, session_id: Uuid) -> Result<Option<(Session, KeyMeta)>> {
+        let Some(session) = self.get(session_id).await? else { return Ok(None) };
+        let row = sqlx::query!(
+            r#"SELECT key_rotated_at FROM sessions WHERE id = $1"#,
+            session_id,
+        )
+        .fetch_one(self.pool())
+        .await?;
+
+        let meta = KeyMeta {
+            created_at: session.session_start,
+            rotated_at: row.key_rotated_at,
+            key_fingerprint: session.key_fingerprint.clone(),
+            server_managed_key: session.server_managed_key,
+        };
+        Ok(Some((session, meta)))
+    }
+
+    /// Fields of the session that differ from version `since`, found by comparing the
+    /// snapshots migration 0009 records on every write. A `since` at or past the current
+    /// version yields no changes: a client ahead of this read is talking to a lagging replica
+    /// and already has newer data.
+    pub async fn changes_since(&self, session_id: Uuid, since: u64) -> Result<Option<SessionChanges>> {
+        let Some(session) = self.get(session_id).await? else { return Ok(None) };
+        let version = session.version;
+        let mut current = match serde_json::to_value(&session)? {
+            serde_json::Value::Object(fields) => fields,
+            _ => unreachable!("Session serializes to an object"),
+        };
+        current.remove("version");
+
+        if since >= version {
+            return Ok(Some(SessionChanges {
+                version,
+                full: false,
+                changes: serde_json::Map::new(),
+            }));
+        }
+
+        let rows = sqlx::query!(
+            r#"SELECT version, snapshot
+               FROM session_versions
+               WHERE session_id = $1 AND version = ANY($2)"#,
+            session_id,
+            &[since as i64, version as i64][..],
+        )
+        .fetch_all(self.pool())
+        .await?;
+        let snapshot_at = |v: u64| {
+            rows.iter()
+                .find(|row| row.version as u64 == v)
+                .map(|row| &row.snapshot)
+        };
+
+        let (full, changes) = match (snapshot_at(since), snapshot_at(version)) {
+            (Some(old), Some(new)) => (
+                false,
+                current
+                    .into_iter()
+                    .filter(|(field, _)| old.get(field) != new.get(field))
+                    .collect(),
+            ),
+            _ => (true, current),
+        };
+        Ok(Some(SessionChanges {
+            version,
+            full,
+            changes,
+        }))
+    }
+
+    /// Claims the session's activation callback for sending, returning its URL. Succeeds at
+    /// most once per session, so concurrent or repeated activations cannot fire it twice.
+    pub async fn claim_activation_webhook(&self, session_id: Uuid) -> Result<Option<String>> {
+        let row = sqlx::query!(
             r#"UPDATE sessions
-               SET status = 'ACTIVE', vault_pubkey = $2, last_activity = $3
-               WHERE id = $1"#,
+               SET activation_webhook_claimed_at = now()
+               WHERE id = $1
+                 AND activation_webhook_url IS NOT NULL
+                 AND activation_webhook_claimed_at IS NULL
+               RETURNING activation_webhook_url AS "activation_webhook_url!""#,
             session_id,
-            vault_pubkey.to_string(),
-            now,
         )
-        .execute(&self.pool)
+        .fetch_optional(self.pool())
         .await?;
-        Ok(())
+        Ok(row.map(|row| row.activation_webhook_url))
     }
 
-    pub async fn revoke(&self, session_id: Uuid) -> Result<()> {
-        let now = Utc::now();
+    pub async fn record_activation_webhook_delivered(&self, session_id: Uuid) -> Result<()> {
         sqlx::query!(
-            r#"UPDATE sessions
-               SET status = 'REVOKED', last_activity = $2
-               WHERE id = $1"#,
+            r#"UPDATE sessions SET activation_webhook_delivered_at = now() WHERE id = $1"#,
             session_id,
-            now,
         )
-        .execute(&self.pool)
+        .execute(self.pool())
         .await?;
         Ok(())
     }
 
-    pub async fn get(&self, session_id: Uuid) -> Result<Option<Session>> {
-        let row = sqlx::query!(
-            r#"SELECT
-                   id,
-                   parent_wallet,
-                   ephemeral_wallet,
-                   vault_pubkey,
-                   status,
-                   session_start,
-                   session_expiry,
-                   last_activity,
-                   max_deposit,
-                   total_deposited,
-                   total_spent
-               FROM sessions
+    /// Records the deposit `/session/deposit` last prepared for the parent to sign.
+    pub async fn record_pending_deposit(&self, session_id: Uuid, lamports: u64) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE sessions
+               SET pending_deposit_lamports = $2, pending_deposit_requested_at = now()
                WHERE id = $1"#,
             session_id,
+            lamports as i64,
         )
-        .fetch_optional(&self.pool)
+        .execute(self.pool())
         .await?;
+        Ok(())
+    }
 
-        let Some(row) = row else { return Ok(None) };
+    /// Moves every `CREATED`/`ACTIVE` session past `session_expiry` to `EXPIRED` and returns
+    /// them as updated. Sessions with a vault then show up in `cleanup_candidates`.
+    pub async fn mark_expired(&self, now: DateTime<Utc>) -> Result<Vec<Session>> {
+        let to = SessionStatus::Expired;
+        let rows = sqlx::query_as::<_, SessionRow>(concat!(
+            "UPDATE sessions
+               SET status = $2, last_activity = $1
+               WHERE session_expiry < $1 AND status = ANY($3)
+               RETURNING ",
+            session_columns!()
+        ))
+        .bind(now)
+        .bind(to)
+        .bind(to.predecessors())
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows.into_iter().map(Session::from).collect())
+    }
 
-        let status = match row.status.as_str() {
-            "CREATED" => SessionStatus::Created,
-            "ACTIVE" => SessionStatus::Active,
-            "REVOKED" => SessionStatus::Revoked,
-            "EXPIRED" => SessionStatus::Expired,
-            "CLEANED" => SessionStatus::Cleaned,
-            _ => SessionStatus::Created,
-        };
+    /// Active sessions whose spend alert is armed and has not fired yet.
+    pub async fn spend_watches(&self) -> Result<Vec<SpendWatch>> {
+        let rows = sqlx::query!(
+            r#"SELECT id, vault_pubkey AS "vault_pubkey!", max_deposit,
+                      spend_alert_bps AS "spend_alert_bps!"
+               FROM sessions
+               WHERE status = $1
+                 AND vault_pubkey IS NOT NULL
+                 AND spend_alert_bps IS NOT NULL
+                 AND spend_alert_fired_at IS NULL"#,
+            SessionStatus::Active as SessionStatus,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| SpendWatch {
+                session_id: row.id,
+                vault_pubkey: row.vault_pubkey,
+                max_deposit: row.max_deposit as u64,
+                spend_alert_bps: row.spend_alert_bps as u16,
+            })
+            .collect())
+    }
 
-        Ok(Some(Session {
-            id: row.id,
+    /// Records the vault's confirmed on-chain totals. Both only ever grow, so a stale read
+    /// cannot roll them back. `alert_due` comes from [`SpendWatch::is_due`]; returns the
+    /// session's `SpendAlert` if this is the first update with it set. The alert is claimed in
+    /// the same statement, so it fires once however many updates follow.
+    pub async fn record_spend(
+        &self,
+        session_id: Uuid,
+        total_deposited: u64,
+        total_spent: u64,
+        alert_due: bool,
+    ) -> Result<Option<SpendAlert>> {
+        let row = sqlx::query!(
+            r#"WITH prev AS (
+                   SELECT id, total_spent, spend_alert_fired_at
+                   FROM sessions WHERE id = $1 FOR UPDATE
+               )
+               UPDATE sessions s
+               SET total_deposited = GREATEST(s.total_deposited, $2),
+                   total_spent = GREATEST(s.total_spent, $3),
+                   last_activity = CASE WHEN $3 > s.total_spent THEN now()
+                                        ELSE s.last_activity END,
+                   spend_alert_fired_at = CASE
+                       WHEN s.spend_alert_fired_at IS NULL
+                        AND s.spend_alert_bps IS NOT NULL
+                        AND $4
+                       THEN now()
+                       ELSE s.spend_alert_fired_at END
+               FROM prev
+               WHERE s.id = prev.id
+               RETURNING s.parent_wallet, s.vault_pubkey, s.spend_alert_bps,
+                         s.total_spent, s.max_deposit,
+                         (prev.spend_alert_fired_at IS NULL
+                          AND s.spend_alert_fired_at IS NOT NULL) AS "fired!""#,
+            session_id,
+            total_deposited as i64,
+            total_spent as i64,
+            alert_due,
+        )
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(row.filter(|row| row.fired).map(|row| SpendAlert {
+            session_id,
             parent_wallet: row.parent_wallet,
-            ephemeral_wallet: row.ephemeral_wallet,
             vault_pubkey: row.vault_pubkey,
-            status,
-            session_start: row.session_start,
-            session_expiry: row.session_expiry,
-            last_activity: row.last_activity,
-            max_deposit: row.max_deposit as u64,
-            total_deposited: row.total_deposited as u64,
+            spend_alert_bps: row.spend_alert_bps.unwrap_or_default() as u16,
             total_spent: row.total_spent as u64,
+            max_deposit: row.max_deposit as u64,
         }))
     }
+
+    pub async fn list_needs_manual(&self) -> Result<Vec<CleanupRetry>> {
+        let rows = sqlx::query!(
+            r#"SELECT id, parent_wallet, vault_pubkey AS "vault_pubkey!",
+                      status AS "status: SessionStatus",
+                      cleanup_attempts, last_cleanup_attempt_at, cleanup_last_error
+               FROM sessions
+               WHERE status = $1
+               ORDER BY last_cleanup_attempt_at"#,
+            SessionStatus::NeedsManual as SessionStatus,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| CleanupRetry {
+                session_id: row.id,
+                parent_wallet: row.parent_wallet,
+                vault_pubkey: row.vault_pubkey,
+                status: row.status,
+                cleanup_attempts: row.cleanup_attempts as u32,
+                last_cleanup_attempt_at: row.last_cleanup_attempt_at,
+                cleanup_last_error: row.cleanup_last_error,
+            })
+            .collect())
+    }
+
+    /// Counts and balances across all sessions, grouped by status in a single query.
+    pub async fn health_snapshot(&self) -> Result<HealthSnapshot> {
+        let now = Utc::now();
+        let rows = sqlx::query!(
+            r#"SELECT status AS "status: SessionStatus",
+                      COUNT(*) AS "count!",
+                      MIN(session_start) AS oldest_start,
+                      COALESCE(SUM(total_deposited - total_spent), 0)::BIGINT AS "outstanding!",
+                      COUNT(*) FILTER (WHERE session_expiry <= $1) AS "past_expiry!"
+               FROM sessions
+               GROUP BY status
+               ORDER BY status"#,
+            now,
+        )
+        .fetch_all(self.pool())
+        .await?;
+
+        let mut snapshot = HealthSnapshot {
+            taken_at: now,
+            counts: Vec::with_capacity(rows.len()),
+            oldest_active_age_secs: None,
+            active_outstanding_lamports: 0,
+            expired_uncleaned: 0,
+        };
+        for row in rows {
+            match row.status {
+                SessionStatus::Active => {
+                    snapshot.oldest_active_age_secs =
+                        row.oldest_start.map(|start| (now - start).num_seconds());
+                    snapshot.active_outstanding_lamports = row.outstanding.max(0) as u64;
+                }
+                SessionStatus::Cleaned | SessionStatus::Archived => {}
+                _ => snapshot.expired_uncleaned += row.past_expiry as u64,
+            }
+            snapshot.counts.push(StatusCount {
+                status: row.status,
+                count: row.count as u64,
+            });
+        }
+        Ok(snapshot)
+    }
+
+    /// Operator worklist: sessions in `NEEDS_MANUAL` or flagged `needs_reconciliation`,
+    /// oldest activity first.
+    pub async fn list_attention(&self, limit: i64, offset: i64) -> Result<Vec<AttentionItem>> {
+        let rows = sqlx::query!(
+            r#"SELECT id, parent_wallet, vault_pubkey, status AS "status: SessionStatus",
+                      needs_reconciliation,
+                      COALESCE(reconciliation_reason, cleanup_last_error) AS reason,
+                      cleanup_attempts, last_cleanup_attempt_at, last_activity
+               FROM sessions
+               WHERE status = $1 OR needs_reconciliation
+               ORDER BY last_activity, id
+               LIMIT $2 OFFSET $3"#,
+            SessionStatus::NeedsManual as SessionStatus,
+            limit,
+            offset,
+        )
+        .fetch_all(self.pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| AttentionItem {
+                session_id: row.id,
+                parent_wallet: row.parent_wallet,
+                vault_pubkey: row.vault_pubkey,
+                status: row.status,
+                needs_reconciliation: row.needs_reconciliation,
+                reason: row.reason,
+                cleanup_attempts: row.cleanup_attempts as u32,
+                last_cleanup_attempt_at: row.last_cleanup_attempt_at,
+                last_activity: row.last_activity,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_store::InMemorySessionStore;
+
+    fn watch(max_deposit: u64, spend_alert_bps: u16) -> SpendWatch {
+        SpendWatch {
+            session_id: Uuid::new_v4(),
+            vault_pubkey: Pubkey::new_unique().to_string(),
+            max_deposit,
+            spend_alert_bps,
+        }
+    }
+
+    #[test]
+    fn spend_alert_fires_once_when_threshold_is_crossed() {
+        let watch = watch(1_000, 8_000);
+        assert!(!watch.is_due(799));
+        assert!(watch.is_due(800));
+
+        // `spend_watches` stops returning a session once its alert is claimed.
+        let mut armed = true;
+        let mut alerts = 0;
+        for total_spent in [100, 500, 799, 800, 950, 1_000] {
+            if armed && watch.is_due(total_spent) {
+                alerts += 1;
+                armed = false;
+            }
+        }
+        assert_eq!(alerts, 1);
+    }
+
+    #[test]
+    fn spend_alert_never_fires_without_max_deposit() {
+        let watch = watch(0, 1);
+        assert!(!watch.is_due(0));
+        assert!(!watch.is_due(u64::MAX));
+    }
+
+    #[test]
+    fn spend_alert_threshold_does_not_overflow() {
+        assert!(watch(u64::MAX, 10_000).is_due(u64::MAX));
+        assert!(!watch(u64::MAX, 10_000).is_due(u64::MAX - 1));
+    }
+
+    const ALL_STATUSES: [SessionStatus; 7] = [
+        SessionStatus::Created,
+        SessionStatus::Active,
+        SessionStatus::Revoked,
+        SessionStatus::Expired,
+        SessionStatus::Cleaned,
+        SessionStatus::Archived,
+        SessionStatus::NeedsManual,
+    ];
+
+    fn in_memory_manager() -> SessionManager<InMemorySessionStore> {
+        SessionManager::with_store(InMemorySessionStore::new(), Config::for_tests())
+    }
+
+    fn assert_invalid_transition(err: anyhow::Error, from: SessionStatus, to: SessionStatus) {
+        match err.downcast_ref::<SessionError>() {
+            Some(SessionError::InvalidTransition { from: f, to: t }) => {
+                assert_eq!((*f, *t), (from, to));
+            }
+            other => panic!("expected InvalidTransition, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn can_transition_to_allows_only_lifecycle_edges() {
+        use SessionStatus::*;
+        let allowed = [
+            (Created, Active),
+            (Created, Revoked),
+            (Created, Expired),
+            (Active, Revoked),
+            (Active, Expired),
+            (Revoked, NeedsManual),
+            (Expired, NeedsManual),
+            (Revoked, Cleaned),
+            (Expired, Cleaned),
+            (NeedsManual, Cleaned),
+            (Cleaned, Archived),
+        ];
+        for from in ALL_STATUSES {
+            for to in ALL_STATUSES {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{from:?} -> {to:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn lifecycle_runs_through_the_session_manager() {
+        let sm = in_memory_manager();
+        let client_wallet = Pubkey::new_unique();
+        let (session, keypair) = sm
+            .create_session(Pubkey::new_unique(), 3600, 1_000_000, Some(client_wallet), None, None)
+            .await
+            .unwrap();
+        assert!(keypair.is_none());
+        assert_eq!(session.ephemeral_wallet, client_wallet.to_string());
+        assert_eq!(session.status, SessionStatus::Created);
+
+        let vault = Pubkey::new_unique();
+        sm.mark_active(session.id, vault).await.unwrap();
+        let active = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(active.status, SessionStatus::Active);
+        assert_eq!(active.vault_pubkey, Some(vault.to_string()));
+
+        sm.revoke(session.id).await.unwrap();
+        let revoked = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(revoked.status, SessionStatus::Revoked);
+        assert_eq!(revoked.version, 3);
+
+        let err = sm.mark_active(session.id, Pubkey::new_unique()).await.unwrap_err();
+        assert_invalid_transition(err, SessionStatus::Revoked, SessionStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn expired_session_cannot_be_reactivated() {
+        let (created, _) = in_memory_manager()
+            .create_session(Pubkey::new_unique(), 3600, 1_000_000, None, None, None)
+            .await
+            .unwrap();
+        let expired = Session {
+            status: SessionStatus::Expired,
+            ..created
+        };
+        let store = InMemorySessionStore::new();
+        store.insert(&expired, None, None, None).await.unwrap();
+        let sm = SessionManager::with_store(store, Config::for_tests());
+
+        let err = sm.mark_active(expired.id, Pubkey::new_unique()).await.unwrap_err();
+        assert_invalid_transition(err, SessionStatus::Expired, SessionStatus::Active);
+        let unchanged = sm.get(expired.id).await.unwrap().unwrap();
+        assert_eq!(unchanged.status, SessionStatus::Expired);
+        assert_eq!(unchanged.vault_pubkey, None);
+        assert_eq!(unchanged.version, expired.version);
+    }
+
+    async fn server_managed_session(sm: &SessionManager<InMemorySessionStore>) -> Session {
+        let (session, keypair) = sm
+            .create_session(Pubkey::new_unique(), 3600, 1_000_000, None, None, None)
+            .await
+            .unwrap();
+        assert!(keypair.is_some());
+        session
+    }
+
+    #[tokio::test]
+    async fn rotation_rolls_back_when_the_chain_step_fails() {
+        let sm = in_memory_manager();
+        let session = server_managed_session(&sm).await;
+
+        let err = sm
+            .rotate_key_with(session.id, |_, _| async {
+                Err(anyhow::anyhow!("rotate_ephemeral was not confirmed"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "rotate_ephemeral was not confirmed");
+
+        let unchanged = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(unchanged.ephemeral_wallet, session.ephemeral_wallet);
+        assert_eq!(unchanged.key_fingerprint, session.key_fingerprint);
+        assert_eq!(unchanged.version, session.version);
+        let key = sm.load_ephemeral_keypair(session.id).await.unwrap().unwrap();
+        assert_eq!(key.pubkey().to_string(), session.ephemeral_wallet);
+        assert_eq!(sm.store().reconciliation_reason(session.id), None);
+    }
+
+    #[tokio::test]
+    async fn rotation_that_fails_to_commit_needs_reconciliation() {
+        let sm = in_memory_manager();
+        let session = server_managed_session(&sm).await;
+        sm.store().fail_next_commit();
+
+        assert!(sm
+            .rotate_key_with(session.id, |_, _| async { Ok(()) })
+            .await
+            .is_err());
+
+        let unchanged = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(unchanged.ephemeral_wallet, session.ephemeral_wallet);
+        let reason = sm.store().reconciliation_reason(session.id).unwrap();
+        assert!(reason.starts_with("key rotation committed on-chain but not in DB"));
+    }
+
+    #[tokio::test]
+    async fn rotation_keeps_the_id_and_changes_the_fingerprint() {
+        let sm = in_memory_manager();
+        let session = server_managed_session(&sm).await;
+
+        let (rotated, keypair) = sm.rotate_key(session.id).await.unwrap().unwrap();
+        assert_eq!(rotated.id, session.id);
+        assert_eq!(rotated.ephemeral_wallet, keypair.pubkey().to_string());
+        assert_ne!(rotated.ephemeral_wallet, session.ephemeral_wallet);
+        assert_ne!(rotated.key_fingerprint, session.key_fingerprint);
+        assert_eq!(
+            rotated.key_fingerprint,
+            crate::transaction_signer::key_fingerprint(&keypair.pubkey())
+        );
+        assert_eq!(rotated.version, session.version + 1);
+
+        let stored = sm.load_ephemeral_keypair(session.id).await.unwrap().unwrap();
+        assert_eq!(stored.pubkey(), keypair.pubkey());
+    }
 }
@@ -0,0 +1,901 @@
+use crate::session_manager::{CleanupRetry, Session, SessionError, SessionStatus};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Persistence backend for sessions. `SessionManager` is generic over this so the lifecycle
+/// logic can run against Postgres in production or an in-memory map in tests and local dev.
+///
+/// Status-changing methods must only apply when the current status is a legal predecessor
+/// (see `SessionStatus::predecessors`), failing with `SessionError::InvalidTransition`.
+pub trait SessionStore: Send + Sync {
+    type StagedKeyRotation: StagedKeyRotation;
+
+    /// `encrypted_ephemeral_key` is `None` for sessions with a client-managed key.
+    /// `activation_webhook_url` is called once when the session becomes active.
+    /// `spend_alert_bps` arms a one-time `SpendAlert` at that fraction of `max_deposit`.
+    async fn insert(
+        &self,
+        session: &Session,
+        encrypted_ephemeral_key: Option<&str>,
+        activation_webhook_url: Option<&str>,
+        spend_alert_bps: Option<u16>,
+    ) -> Result<()>;
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<Session>>;
+
+    async fn mark_active(
+        &self,
+        session_id: Uuid,
+        vault_pubkey: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()>;
+
+    async fn revoke(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()>;
+
+    /// The session's encrypted ephemeral key: `None` if the session does not exist,
+    /// `Some(None)` if its key is client-managed.
+    async fn encrypted_key(&self, session_id: Uuid) -> Result<Option<Option<String>>>;
+
+    /// Appends an attempt to the key recovery audit trail.
+    async fn record_key_recovery(
+        &self,
+        session_id: Uuid,
+        requester: &str,
+        authorized: bool,
+        reason: &str,
+    ) -> Result<()>;
+
+    /// Stores a challenge nonce issued to `parent_wallet`, pruning ones that expired
+    /// unredeemed.
+    async fn create_auth_challenge(
+        &self,
+        parent_wallet: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()>;
+
+    /// Deletes `nonce` if it was issued to `parent_wallet` and has not expired, returning
+    /// whether it did. Must be atomic, so a nonce is redeemed at most once.
+    async fn consume_auth_challenge(&self, parent_wallet: &str, nonce: &str) -> Result<bool>;
+
+    /// Stages replacing the session's server-managed key with `rotation`, locking the
+    /// session until the returned rotation is committed or rolled back. `None` if the session
+    /// does not exist; fails with `SessionError::ClientManagedKey` if the client holds its key.
+    async fn stage_key_rotation(
+        &self,
+        session_id: Uuid,
+        rotation: &KeyRotation,
+    ) -> Result<Option<Self::StagedKeyRotation>>;
+
+    /// Flags a session whose on-chain state diverged from the store.
+    async fn mark_needs_reconciliation(&self, session_id: Uuid, reason: &str) -> Result<()>;
+
+    /// `REVOKED` or `EXPIRED` sessions with a vault and `session_expiry <= now`, soonest
+    /// expiry first.
+    async fn cleanup_candidates(&self, now: DateTime<Utc>) -> Result<Vec<CleanupRetry>>;
+
+    /// Moves the session to `CLEANED` and clears its last cleanup error.
+    async fn record_cleanup_success(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()>;
+
+    /// Counts a failed cleanup, moving the session to `NEEDS_MANUAL` on the `max_attempts`th.
+    /// Returns the resulting status.
+    async fn record_cleanup_failure(
+        &self,
+        session_id: Uuid,
+        error: &str,
+        max_attempts: u32,
+        now: DateTime<Utc>,
+    ) -> Result<SessionStatus>;
+}
+
+/// A replacement server-managed ephemeral key.
+pub struct KeyRotation {
+    pub ephemeral_wallet: String,
+    pub encrypted_key: String,
+    pub key_fingerprint: String,
+    pub now: DateTime<Utc>,
+}
+
+/// Key rotation writes that are staged but not yet visible to other readers.
+pub trait StagedKeyRotation: Send {
+    /// The ephemeral wallet being replaced.
+    fn old_wallet(&self) -> &str;
+
+    async fn commit(self) -> Result<()>;
+
+    async fn rollback(self) -> Result<()>;
+}
+
+/// Postgres SQLSTATE `unique_violation`.
+const UNIQUE_VIOLATION: &str = "23505";
+/// Unique index on `sessions.ephemeral_wallet` (migration 0008).
+const EPHEMERAL_WALLET_UNIQUE: &str = "idx_sessions_ephemeral_wallet_unique";
+
+/// The `sessions` columns making up a `Session`, in `SessionRow` order, as a literal for
+/// `concat!`. Usable after both `SELECT` and `RETURNING`.
+macro_rules! session_columns {
+    () => {
+        "id, parent_wallet, ephemeral_wallet, vault_pubkey, status, session_start, \
+         session_expiry, last_activity, max_deposit, total_deposited, total_spent, \
+         key_fingerprint, encrypted_ephemeral_key IS NOT NULL AS server_managed_key, version"
+    };
+}
+pub(crate) use session_columns;
+
+/// A `sessions` row as selected by `session_columns!`.
+#[derive(sqlx::FromRow)]
+pub(crate) struct SessionRow {
+    id: Uuid,
+    parent_wallet: String,
+    ephemeral_wallet: String,
+    vault_pubkey: Option<String>,
+    status: SessionStatus,
+    session_start: DateTime<Utc>,
+    session_expiry: DateTime<Utc>,
+    last_activity: DateTime<Utc>,
+    max_deposit: i64,
+    total_deposited: i64,
+    total_spent: i64,
+    key_fingerprint: String,
+    server_managed_key: bool,
+    version: i64,
+}
+
+impl From<SessionRow> for Session {
+    fn from(row: SessionRow) -> Self {
+        Session {
+            id: row.id,
+            parent_wallet: row.parent_wallet,
+            ephemeral_wallet: row.ephemeral_wallet,
+            vault_pubkey: row.vault_pubkey,
+            status: row.status,
+            session_start: row.session_start,
+            session_expiry: row.session_expiry,
+            last_activity: row.last_activity,
+            max_deposit: row.max_deposit as u64,
+            total_deposited: row.total_deposited as u64,
+            total_spent: row.total_spent as u64,
+            key_fingerprint: row.key_fingerprint,
+            server_managed_key: row.server_managed_key,
+            version: row.version as u64,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PgSessionStore {
+    pool: Pool<Postgres>,
+}
+
+impl PgSessionStore {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub fn pool(&self) -> &Pool<Postgres> {
+        &self.pool
+    }
+}
+
+impl SessionStore for PgSessionStore {
+    type StagedKeyRotation = PgStagedKeyRotation;
+
+    async fn insert(
+        &self,
+        session: &Session,
+        encrypted_ephemeral_key: Option<&str>,
+        activation_webhook_url: Option<&str>,
+        spend_alert_bps: Option<u16>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (
+                id,
+                parent_wallet,
+                ephemeral_wallet,
+                vault_pubkey,
+                status,
+                session_start,
+                session_expiry,
+                last_activity,
+                max_deposit,
+                total_deposited,
+                total_spent,
+                encrypted_ephemeral_key,
+                key_fingerprint,
+                activation_webhook_url,
+                spend_alert_bps
+            ) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15)
+            "#,
+            session.id,
+            session.parent_wallet,
+            session.ephemeral_wallet,
+            session.vault_pubkey,
+            SessionStatus::Created as SessionStatus,
+            session.session_start,
+            session.session_expiry,
+            session.last_activity,
+            session.max_deposit as i64,
+            session.total_deposited as i64,
+            session.total_spent as i64,
+            encrypted_ephemeral_key,
+            session.key_fingerprint,
+            activation_webhook_url,
+            spend_alert_bps.map(i32::from),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|err| {
+            if is_ephemeral_wallet_conflict(&err) {
+                SessionError::EphemeralWalletTaken(session.ephemeral_wallet.clone()).into()
+            } else {
+                anyhow::Error::from(err)
+            }
+        })?;
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<Session>> {
+        let row = sqlx::query_as::<_, SessionRow>(concat!(
+            "SELECT ",
+            session_columns!(),
+            " FROM sessions WHERE id = $1"
+        ))
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(Session::from))
+    }
+
+    async fn mark_active(
+        &self,
+        session_id: Uuid,
+        vault_pubkey: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let to = SessionStatus::Active;
+        let result = sqlx::query!(
+            r#"UPDATE sessions
+               SET status = $4, vault_pubkey = $2, last_activity = $3
+               WHERE id = $1 AND status = ANY($5)"#,
+            session_id,
+            vault_pubkey,
+            now,
+            to as SessionStatus,
+            to.predecessors() as &[SessionStatus],
+        )
+        .execute(&self.pool)
+        .await?;
+        self.check_transition(session_id, to, result.rows_affected())
+            .await
+    }
+
+    async fn revoke(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        let to = SessionStatus::Revoked;
+        let result = sqlx::query!(
+            r#"UPDATE sessions
+               SET status = $3, last_activity = $2
+               WHERE id = $1 AND status = ANY($4)"#,
+            session_id,
+            now,
+            to as SessionStatus,
+            to.predecessors() as &[SessionStatus],
+        )
+        .execute(&self.pool)
+        .await?;
+        self.check_transition(session_id, to, result.rows_affected())
+            .await
+    }
+
+    async fn encrypted_key(&self, session_id: Uuid) -> Result<Option<Option<String>>> {
+        let row = sqlx::query!(
+            r#"SELECT encrypted_ephemeral_key FROM sessions WHERE id = $1"#,
+            session_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| row.encrypted_ephemeral_key))
+    }
+
+    async fn record_key_recovery(
+        &self,
+        session_id: Uuid,
+        requester: &str,
+        authorized: bool,
+        reason: &str,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO key_recovery_audit (id, session_id, requester, authorized, reason)
+               VALUES ($1, $2, $3, $4, $5)"#,
+            Uuid::new_v4(),
+            session_id,
+            requester,
+            authorized,
+            reason,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn create_auth_challenge(
+        &self,
+        parent_wallet: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query!("DELETE FROM auth_challenges WHERE expires_at < now()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query!(
+            r#"INSERT INTO auth_challenges (nonce, parent_wallet, expires_at)
+               VALUES ($1, $2, $3)"#,
+            nonce,
+            parent_wallet,
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn consume_auth_challenge(&self, parent_wallet: &str, nonce: &str) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"DELETE FROM auth_challenges
+               WHERE nonce = $1 AND parent_wallet = $2 AND expires_at > now()
+               RETURNING nonce"#,
+            nonce,
+            parent_wallet,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.is_some())
+    }
+
+    /// Also revokes the old key's delegation row and records one for the new key, all in the
+    /// transaction the session row is locked by.
+    async fn stage_key_rotation(
+        &self,
+        session_id: Uuid,
+        rotation: &KeyRotation,
+    ) -> Result<Option<PgStagedKeyRotation>> {
+        let mut tx = self.pool.begin().await?;
+
+        let Some(row) = sqlx::query!(
+            r#"SELECT ephemeral_wallet, vault_pubkey, encrypted_ephemeral_key IS NOT NULL AS "server_managed_key!"
+               FROM sessions WHERE id = $1 FOR UPDATE"#,
+            session_id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            tx.rollback().await?;
+            return Ok(None);
+        };
+        if !row.server_managed_key {
+            tx.rollback().await?;
+            return Err(SessionError::ClientManagedKey(session_id).into());
+        }
+
+        sqlx::query!(
+            r#"UPDATE sessions
+               SET ephemeral_wallet = $2,
+                   encrypted_ephemeral_key = $3,
+                   key_fingerprint = $4,
+                   key_rotated_at = $5,
+                   last_activity = $5
+               WHERE id = $1"#,
+            session_id,
+            rotation.ephemeral_wallet,
+            rotation.encrypted_key,
+            rotation.key_fingerprint,
+            rotation.now,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(vault_pubkey) = row.vault_pubkey {
+            sqlx::query!(
+                r#"UPDATE delegations
+                   SET revoked_at = $3
+                   WHERE session_id = $1 AND delegate_pubkey = $2 AND revoked_at IS NULL"#,
+                session_id,
+                row.ephemeral_wallet,
+                rotation.now,
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r#"INSERT INTO delegations (id, session_id, vault_pubkey, delegate_pubkey, approved_at)
+                   VALUES ($1, $2, $3, $4, $5)"#,
+                Uuid::new_v4(),
+                session_id,
+                vault_pubkey,
+                rotation.ephemeral_wallet,
+                rotation.now,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        Ok(Some(PgStagedKeyRotation {
+            tx,
+            old_wallet: row.ephemeral_wallet,
+        }))
+    }
+
+    async fn mark_needs_reconciliation(&self, session_id: Uuid, reason: &str) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE sessions
+               SET needs_reconciliation = TRUE, reconciliation_reason = $2
+               WHERE id = $1"#,
+            session_id,
+            reason,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn cleanup_candidates(&self, now: DateTime<Utc>) -> Result<Vec<CleanupRetry>> {
+        let rows = sqlx::query!(
+            r#"SELECT id, parent_wallet, vault_pubkey AS "vault_pubkey!",
+                      status AS "status: SessionStatus",
+                      cleanup_attempts, last_cleanup_attempt_at, cleanup_last_error
+               FROM sessions
+               WHERE status = ANY($1) AND vault_pubkey IS NOT NULL AND session_expiry <= $2
+               ORDER BY session_expiry"#,
+            &[SessionStatus::Revoked, SessionStatus::Expired] as &[SessionStatus],
+            now,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| CleanupRetry {
+                session_id: row.id,
+                parent_wallet: row.parent_wallet,
+                vault_pubkey: row.vault_pubkey,
+                status: row.status,
+                cleanup_attempts: row.cleanup_attempts as u32,
+                last_cleanup_attempt_at: row.last_cleanup_attempt_at,
+                cleanup_last_error: row.cleanup_last_error,
+            })
+            .collect())
+    }
+
+    async fn record_cleanup_success(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        let to = SessionStatus::Cleaned;
+        let result = sqlx::query!(
+            r#"UPDATE sessions
+               SET status = $2, last_activity = $3, last_cleanup_attempt_at = $3,
+                   cleanup_last_error = NULL
+               WHERE id = $1 AND status = ANY($4)"#,
+            session_id,
+            to as SessionStatus,
+            now,
+            to.predecessors() as &[SessionStatus],
+        )
+        .execute(&self.pool)
+        .await?;
+        self.check_transition(session_id, to, result.rows_affected())
+            .await
+    }
+
+    async fn record_cleanup_failure(
+        &self,
+        session_id: Uuid,
+        error: &str,
+        max_attempts: u32,
+        now: DateTime<Utc>,
+    ) -> Result<SessionStatus> {
+        let to = SessionStatus::NeedsManual;
+        let row = sqlx::query!(
+            r#"UPDATE sessions
+               SET cleanup_attempts = cleanup_attempts + 1,
+                   last_cleanup_attempt_at = $3,
+                   cleanup_last_error = $2,
+                   status = CASE WHEN cleanup_attempts + 1 >= $4 THEN $5 ELSE status END
+               WHERE id = $1 AND status = ANY($6)
+               RETURNING status AS "status: SessionStatus""#,
+            session_id,
+            error,
+            now,
+            max_attempts as i32,
+            to as SessionStatus,
+            to.predecessors() as &[SessionStatus],
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        self.check_transition(session_id, to, row.is_some() as u64)
+            .await?;
+        Ok(row.map_or(to, |row| row.status))
+    }
+}
+
+/// A key rotation held in an open transaction.
+pub struct PgStagedKeyRotation {
+    tx: Transaction<'static, Postgres>,
+    old_wallet: String,
+}
+
+impl StagedKeyRotation for PgStagedKeyRotation {
+    fn old_wallet(&self) -> &str {
+        &self.old_wallet
+    }
+
+    async fn commit(self) -> Result<()> {
+        Ok(self.tx.commit().await?)
+    }
+
+    async fn rollback(self) -> Result<()> {
+        Ok(self.tx.rollback().await?)
+    }
+}
+
+fn is_ephemeral_wallet_conflict(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db) => {
+            db.code().as_deref() == Some(UNIQUE_VIOLATION)
+                && db.constraint() == Some(EPHEMERAL_WALLET_UNIQUE)
+        }
+        _ => false,
+    }
+}
+
+impl PgSessionStore {
+    /// Turns a conditional status UPDATE that matched no rows into the right error. The
+    /// `WHERE status = ANY(..)` guard makes the transition race-safe; this only explains why
+    /// it did not apply.
+    async fn check_transition(
+        &self,
+        session_id: Uuid,
+        to: SessionStatus,
+        rows_affected: u64,
+    ) -> Result<()> {
+        if rows_affected > 0 {
+            return Ok(());
+        }
+        match self.get(session_id).await? {
+            None => Err(SessionError::NotFound(session_id).into()),
+            Some(session) => Err(SessionError::InvalidTransition {
+                from: session.status,
+                to,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Process-local store for tests and local development without Postgres.
+#[cfg(any(test, feature = "test-store"))]
+#[derive(Default)]
+pub struct InMemorySessionStore {
+    sessions: std::sync::Arc<InMemorySessions>,
+    /// Outstanding challenge nonces, mapped to the wallet they were issued to and their expiry.
+    challenges: std::sync::Mutex<std::collections::HashMap<String, (String, DateTime<Utc>)>>,
+    key_recoveries: std::sync::Mutex<Vec<KeyRecoveryAttempt>>,
+    /// Reasons sessions were flagged `needs_reconciliation`, by session.
+    reconciliation: std::sync::Mutex<std::collections::HashMap<Uuid, String>>,
+    /// Makes the next staged key rotation fail to commit.
+    fail_next_commit: std::sync::atomic::AtomicBool,
+    cleanup: std::sync::Mutex<std::collections::HashMap<Uuid, CleanupAttempts>>,
+}
+
+/// A session's failed cleanup bookkeeping in `InMemorySessionStore`.
+#[cfg(any(test, feature = "test-store"))]
+#[derive(Default)]
+struct CleanupAttempts {
+    count: u32,
+    last_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+/// Sessions with their encrypted keys, shared with staged key rotations.
+#[cfg(any(test, feature = "test-store"))]
+type InMemorySessions =
+    std::sync::Mutex<std::collections::HashMap<Uuid, (Session, Option<String>)>>;
+
+/// One `record_key_recovery` call, as kept by `InMemorySessionStore`.
+#[cfg(any(test, feature = "test-store"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRecoveryAttempt {
+    pub session_id: Uuid,
+    pub requester: String,
+    pub authorized: bool,
+    pub reason: String,
+}
+
+#[cfg(any(test, feature = "test-store"))]
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every key recovery attempt recorded so far, oldest first.
+    pub fn key_recoveries(&self) -> Vec<KeyRecoveryAttempt> {
+        self.key_recoveries.lock().unwrap().clone()
+    }
+
+    /// Why the session was flagged `needs_reconciliation`, if it was.
+    pub fn reconciliation_reason(&self, session_id: Uuid) -> Option<String> {
+        self.reconciliation.lock().unwrap().get(&session_id).cloned()
+    }
+
+    /// Makes the next key rotation staged on this store fail at commit, as a dropped database
+    /// connection would.
+    pub fn fail_next_commit(&self) {
+        self.fail_next_commit
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(any(test, feature = "test-store"))]
+impl SessionStore for InMemorySessionStore {
+    type StagedKeyRotation = InMemoryStagedKeyRotation;
+
+    /// Activation callbacks and spend alerts are only dispatched from Postgres, so neither is
+    /// kept.
+    async fn insert(
+        &self,
+        session: &Session,
+        encrypted_ephemeral_key: Option<&str>,
+        _activation_webhook_url: Option<&str>,
+        _spend_alert_bps: Option<u16>,
+    ) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&session.id) {
+            anyhow::bail!("session {} already exists", session.id);
+        }
+        if sessions
+            .values()
+            .any(|(s, _)| s.ephemeral_wallet == session.ephemeral_wallet)
+        {
+            return Err(SessionError::EphemeralWalletTaken(session.ephemeral_wallet.clone()).into());
+        }
+        sessions.insert(
+            session.id,
+            (session.clone(), encrypted_ephemeral_key.map(str::to_string)),
+        );
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<Session>> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(&session_id).map(|(session, _)| session.clone()))
+    }
+
+    async fn mark_active(
+        &self,
+        session_id: Uuid,
+        vault_pubkey: &str,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, _) = sessions
+            .get_mut(&session_id)
+            .ok_or(SessionError::NotFound(session_id))?;
+        transition(session, SessionStatus::Active)?;
+        session.vault_pubkey = Some(vault_pubkey.to_string());
+        session.last_activity = now;
+        session.version += 1;
+        Ok(())
+    }
+
+    async fn revoke(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, _) = sessions
+            .get_mut(&session_id)
+            .ok_or(SessionError::NotFound(session_id))?;
+        transition(session, SessionStatus::Revoked)?;
+        session.last_activity = now;
+        session.version += 1;
+        Ok(())
+    }
+
+    async fn encrypted_key(&self, session_id: Uuid) -> Result<Option<Option<String>>> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(&session_id).map(|(_, key)| key.clone()))
+    }
+
+    async fn record_key_recovery(
+        &self,
+        session_id: Uuid,
+        requester: &str,
+        authorized: bool,
+        reason: &str,
+    ) -> Result<()> {
+        self.key_recoveries.lock().unwrap().push(KeyRecoveryAttempt {
+            session_id,
+            requester: requester.to_string(),
+            authorized,
+            reason: reason.to_string(),
+        });
+        Ok(())
+    }
+
+    async fn create_auth_challenge(
+        &self,
+        parent_wallet: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let now = Utc::now();
+        challenges.retain(|_, (_, expires_at)| *expires_at >= now);
+        challenges.insert(nonce.to_string(), (parent_wallet.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn consume_auth_challenge(&self, parent_wallet: &str, nonce: &str) -> Result<bool> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let redeemable = challenges
+            .get(nonce)
+            .is_some_and(|(wallet, expires_at)| wallet == parent_wallet && *expires_at > Utc::now());
+        if redeemable {
+            challenges.remove(nonce);
+        }
+        Ok(redeemable)
+    }
+
+    /// Nothing is locked: staged rotations apply whatever the session holds at commit.
+    async fn stage_key_rotation(
+        &self,
+        session_id: Uuid,
+        rotation: &KeyRotation,
+    ) -> Result<Option<InMemoryStagedKeyRotation>> {
+        let sessions = self.sessions.lock().unwrap();
+        let Some((session, key)) = sessions.get(&session_id) else {
+            return Ok(None);
+        };
+        if key.is_none() {
+            return Err(SessionError::ClientManagedKey(session_id).into());
+        }
+        Ok(Some(InMemoryStagedKeyRotation {
+            sessions: self.sessions.clone(),
+            session_id,
+            old_wallet: session.ephemeral_wallet.clone(),
+            rotation: KeyRotation {
+                ephemeral_wallet: rotation.ephemeral_wallet.clone(),
+                encrypted_key: rotation.encrypted_key.clone(),
+                key_fingerprint: rotation.key_fingerprint.clone(),
+                now: rotation.now,
+            },
+            fail_commit: self
+                .fail_next_commit
+                .swap(false, std::sync::atomic::Ordering::SeqCst),
+        }))
+    }
+
+    async fn mark_needs_reconciliation(&self, session_id: Uuid, reason: &str) -> Result<()> {
+        self.reconciliation
+            .lock()
+            .unwrap()
+            .insert(session_id, reason.to_string());
+        Ok(())
+    }
+
+    async fn cleanup_candidates(&self, now: DateTime<Utc>) -> Result<Vec<CleanupRetry>> {
+        let sessions = self.sessions.lock().unwrap();
+        let cleanup = self.cleanup.lock().unwrap();
+        let mut candidates: Vec<(DateTime<Utc>, CleanupRetry)> = sessions
+            .values()
+            .filter(|(s, _)| {
+                matches!(s.status, SessionStatus::Revoked | SessionStatus::Expired)
+                    && s.session_expiry <= now
+            })
+            .filter_map(|(s, _)| {
+                let attempts = cleanup.get(&s.id);
+                let retry = CleanupRetry {
+                    session_id: s.id,
+                    parent_wallet: s.parent_wallet.clone(),
+                    vault_pubkey: s.vault_pubkey.clone()?,
+                    status: s.status,
+                    cleanup_attempts: attempts.map_or(0, |a| a.count),
+                    last_cleanup_attempt_at: attempts.and_then(|a| a.last_at),
+                    cleanup_last_error: attempts.and_then(|a| a.last_error.clone()),
+                };
+                Some((s.session_expiry, retry))
+            })
+            .collect();
+        candidates.sort_by_key(|(expiry, _)| *expiry);
+        Ok(candidates.into_iter().map(|(_, retry)| retry).collect())
+    }
+
+    async fn record_cleanup_success(&self, session_id: Uuid, now: DateTime<Utc>) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, _) = sessions
+            .get_mut(&session_id)
+            .ok_or(SessionError::NotFound(session_id))?;
+        transition(session, SessionStatus::Cleaned)?;
+        session.last_activity = now;
+        session.version += 1;
+        let mut cleanup = self.cleanup.lock().unwrap();
+        let attempts = cleanup.entry(session_id).or_default();
+        attempts.last_at = Some(now);
+        attempts.last_error = None;
+        Ok(())
+    }
+
+    async fn record_cleanup_failure(
+        &self,
+        session_id: Uuid,
+        error: &str,
+        max_attempts: u32,
+        now: DateTime<Utc>,
+    ) -> Result<SessionStatus> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, _) = sessions
+            .get_mut(&session_id)
+            .ok_or(SessionError::NotFound(session_id))?;
+        let to = SessionStatus::NeedsManual;
+        if !session.status.can_transition_to(to) {
+            return Err(SessionError::InvalidTransition {
+                from: session.status,
+                to,
+            }
+            .into());
+        }
+        let mut cleanup = self.cleanup.lock().unwrap();
+        let attempts = cleanup.entry(session_id).or_default();
+        attempts.count += 1;
+        attempts.last_at = Some(now);
+        attempts.last_error = Some(error.to_string());
+        if attempts.count >= max_attempts {
+            session.status = to;
+            session.version += 1;
+        }
+        Ok(session.status)
+    }
+}
+
+#[cfg(any(test, feature = "test-store"))]
+pub struct InMemoryStagedKeyRotation {
+    sessions: std::sync::Arc<InMemorySessions>,
+    session_id: Uuid,
+    old_wallet: String,
+    rotation: KeyRotation,
+    fail_commit: bool,
+}
+
+#[cfg(any(test, feature = "test-store"))]
+impl StagedKeyRotation for InMemoryStagedKeyRotation {
+    fn old_wallet(&self) -> &str {
+        &self.old_wallet
+    }
+
+    async fn commit(self) -> Result<()> {
+        if self.fail_commit {
+            anyhow::bail!("injected commit failure");
+        }
+        let mut sessions = self.sessions.lock().unwrap();
+        let (session, key) = sessions
+            .get_mut(&self.session_id)
+            .ok_or(SessionError::NotFound(self.session_id))?;
+        session.ephemeral_wallet = self.rotation.ephemeral_wallet;
+        session.key_fingerprint = self.rotation.key_fingerprint;
+        session.last_activity = self.rotation.now;
+        session.version += 1;
+        *key = Some(self.rotation.encrypted_key);
+        Ok(())
+    }
+
+    async fn rollback(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "test-store"))]
+fn transition(session: &mut Session, to: SessionStatus) -> Result<(), SessionError> {
+    if !session.status.can_transition_to(to) {
+        return Err(SessionError::InvalidTransition {
+            from: session.status,
+            to,
+        });
+    }
+    session.status = to;
+    Ok(())
+}
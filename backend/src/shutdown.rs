@@ -0,0 +1,128 @@
+//! Bounded graceful shutdown.
+//!
+//! `with_graceful_shutdown` waits for every open connection to finish, so one stuck
+//! long-poll or WebSocket could hold a deploy forever. Requests and WebSocket sessions run
+//! under an [`InFlight`] tracker instead; once the grace period after the shutdown signal
+//! runs out, whatever is still tracked is aborted and the server stops waiting for it.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{oneshot, watch};
+
+/// Counts the requests and WebSocket sessions still running, and aborts them on demand.
+#[derive(Clone)]
+pub struct InFlight {
+    count: Arc<AtomicUsize>,
+    abort: Arc<watch::Sender<bool>>,
+}
+
+impl Default for InFlight {
+    fn default() -> Self {
+        Self {
+            count: Arc::new(AtomicUsize::new(0)),
+            abort: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
+impl InFlight {
+    /// Tracked work still running.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Runs `fut` as tracked work. Returns `None` if it was cut off by [`InFlight::abort`].
+    pub async fn run<F: Future>(&self, fut: F) -> Option<F::Output> {
+        let mut aborted = self.abort.subscribe();
+        if *aborted.borrow() {
+            return None;
+        }
+        self.count.fetch_add(1, Ordering::SeqCst);
+        let _guard = CountGuard(&self.count);
+
+        tokio::select! {
+            output = fut => Some(output),
+            _ = aborted.wait_for(|aborted| *aborted) => None,
+        }
+    }
+
+    /// Aborts all tracked work, and any started afterwards. Returns how much was running.
+    pub fn abort(&self) -> usize {
+        let dropped = self.count();
+        self.abort.send_replace(true);
+        dropped
+    }
+}
+
+struct CountGuard<'a>(&'a AtomicUsize);
+
+impl Drop for CountGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware running every request under the [`InFlight`] tracker. An aborted request
+/// answers `503`.
+pub async fn track_request(
+    State(in_flight): State<InFlight>,
+    req: Request,
+    next: Next,
+) -> Response {
+    in_flight
+        .run(next.run(req))
+        .await
+        .unwrap_or_else(|| StatusCode::SERVICE_UNAVAILABLE.into_response())
+}
+
+/// Drives `server` until `signal` fires, then triggers its graceful shutdown through `stop`
+/// and waits up to `grace` for it to drain. Past that, the remaining tracked work is
+/// aborted, the number dropped is logged, and this returns without waiting further.
+pub async fn serve_with_grace<F, E>(
+    server: F,
+    stop: oneshot::Sender<()>,
+    signal: impl Future<Output = ()>,
+    grace: Duration,
+    in_flight: &InFlight,
+) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return result,
+        _ = signal => {}
+    }
+
+    tracing::info!(
+        in_flight = in_flight.count(),
+        grace_secs = grace.as_secs_f64(),
+        "shutdown requested; draining connections"
+    );
+    // The receiver lives inside `server`, which is still pending here.
+    let _ = stop.send(());
+
+    match tokio::time::timeout(grace, &mut server).await {
+        Ok(result) => result,
+        Err(_) => {
+            let dropped = in_flight.abort();
+            tracing::warn!(
+                dropped,
+                "shutdown grace period elapsed; aborted remaining connections"
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Serde helpers for lamport amounts. JSON numbers above 2^53 lose precision in JavaScript,
+//! so `u64` amounts are written as decimal strings. Both strings and numbers are accepted on
+//! input so older clients keep working.
+//!
+//! Use as `#[serde(with = "crate::string_u64")]`.
+
+use serde::{de, Deserializer, Serializer};
+use std::fmt;
+
+pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_any(U64Visitor)
+}
+
+struct U64Visitor;
+
+impl<'de> de::Visitor<'de> for U64Visitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a u64 as a decimal string or an integer")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        u64::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        v.parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Amount {
+        #[serde(with = "crate::string_u64")]
+        lamports: u64,
+    }
+
+    #[test]
+    fn serializes_as_a_decimal_string() {
+        let amount = Amount { lamports: u64::MAX };
+        assert_eq!(
+            serde_json::to_value(&amount).unwrap(),
+            json!({ "lamports": "18446744073709551615" })
+        );
+    }
+
+    #[test]
+    fn accepts_strings_and_numbers() {
+        let from_str: Amount = serde_json::from_value(json!({ "lamports": "18446744073709551615" }))
+            .unwrap();
+        assert_eq!(from_str.lamports, u64::MAX);
+        let from_num: Amount = serde_json::from_value(json!({ "lamports": 42 })).unwrap();
+        assert_eq!(from_num.lamports, 42);
+    }
+
+    #[test]
+    fn rejects_negative_and_non_numeric_values() {
+        for value in [json!(-1), json!("-1"), json!("1.5"), json!("abc"), json!(1.5)] {
+            let parsed = serde_json::from_value::<Amount>(json!({ "lamports": value }));
+            assert!(parsed.is_err(), "{value} should be rejected");
+        }
+    }
+}
@@ -1,66 +1,221 @@
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose, Engine as _};
-use ring::aead;
+use futures::future::BoxFuture;
+use ring::{
+    aead,
+    rand::{SecureRandom, SystemRandom},
+};
 use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
     signature::{Keypair, Signature, Signer},
     transaction::Transaction,
 };
+use std::time::{Duration, Instant};
 
-pub fn encrypt_keypair(keypair: &Keypair, kek: &str) -> Result<String> {
-    let serialized = keypair.to_bytes();
-    let kek_bytes = kek.as_bytes();
+/// PBKDF2 rounds used to stretch the KEK. Dominates the cost of both `encrypt_keypair` and
+/// `decrypt_keypair`; see `benches/key_encryption.rs`. Changing it makes existing blobs
+/// undecryptable.
+pub const KDF_ITERATIONS: u32 = 100_000;
 
+fn derive_key(kek: &str) -> [u8; 32] {
     let salt = b"evs-key-salt";
     let mut key = [0u8; 32];
     ring::pbkdf2::derive(
         ring::pbkdf2::PBKDF2_HMAC_SHA256,
-        std::num::NonZeroU32::new(100_000).unwrap(),
+        std::num::NonZeroU32::new(KDF_ITERATIONS).unwrap(),
         salt,
-        kek_bytes,
+        kek.as_bytes(),
         &mut key,
     );
+    key
+}
+
+/// Seals the keypair with AES-256-GCM under a fresh random nonce. The output is
+/// `base64(nonce || ciphertext || tag)`, so each blob carries the nonce needed to open it.
+pub fn encrypt_keypair(keypair: &Keypair, kek: &str) -> Result<String> {
+    let key = derive_key(kek);
+
+    let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| anyhow::anyhow!("failed to generate nonce"))?;
 
     let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, &key).context("invalid aead key")?;
-    let nonce = aead::Nonce::assume_unique_for_key([0u8; 12]);
-    let mut sealing_key = aead::LessSafeKey::new(unbound_key);
-    let mut in_out = serialized.to_vec();
-    in_out.extend_from_slice(&[0u8; aead::AES_256_GCM.tag_len()]);
+    let sealing_key = aead::LessSafeKey::new(unbound_key);
+    let mut in_out = keypair.to_bytes().to_vec();
     sealing_key
-        .seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
+        .seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce_bytes),
+            aead::Aad::empty(),
+            &mut in_out,
+        )
         .context("failed to encrypt keypair")?;
 
-    Ok(general_purpose::STANDARD_NO_PAD.encode(in_out))
+    let mut blob = nonce_bytes.to_vec();
+    blob.extend_from_slice(&in_out);
+    Ok(general_purpose::STANDARD_NO_PAD.encode(blob))
+}
+
+/// `encrypt_keypair` on the blocking pool, so the KDF does not stall a runtime worker.
+pub async fn encrypt_keypair_blocking(keypair: &Keypair, kek: &str) -> Result<String> {
+    let bytes = keypair.to_bytes();
+    let kek = kek.to_string();
+    tokio::task::spawn_blocking(move || {
+        let keypair = Keypair::from_bytes(&bytes).context("invalid keypair bytes")?;
+        encrypt_keypair(&keypair, &kek)
+    })
+    .await?
 }
 
+/// Salted SHA-256 of the ephemeral pubkey, hex encoded. Safe to expose: it identifies a
+/// key generation for audit purposes without revealing the key itself.
+pub fn key_fingerprint(pubkey: &Pubkey) -> String {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    ctx.update(b"evs-key-fingerprint");
+    ctx.update(pubkey.as_ref());
+    ctx.finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Opens a blob produced by [`encrypt_keypair`].
 pub fn decrypt_keypair(ciphertext_b64: &str, kek: &str) -> Result<Keypair> {
-    let mut ciphertext = general_purpose::STANDARD_NO_PAD
+    let blob = general_purpose::STANDARD_NO_PAD
         .decode(ciphertext_b64)
         .context("invalid base64")?;
-
-    let kek_bytes = kek.as_bytes();
-    let salt = b"evs-key-salt";
-    let mut key = [0u8; 32];
-    ring::pbkdf2::derive(
-        ring::pbkdf2::PBKDF2_HMAC_SHA256,
-        std::num::NonZeroU32::new(100_000).unwrap(),
-        salt,
-        kek_bytes,
-        &mut key,
+    anyhow::ensure!(
+        blob.len() > aead::NONCE_LEN + aead::AES_256_GCM.tag_len(),
+        "encrypted keypair is truncated"
     );
+    let (nonce_bytes, ciphertext) = blob.split_at(aead::NONCE_LEN);
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce_bytes)
+        .map_err(|_| anyhow::anyhow!("invalid nonce"))?;
+
+    let key = derive_key(kek);
 
     let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, &key).context("invalid aead key")?;
-    let nonce = aead::Nonce::assume_unique_for_key([0u8; 12]);
-    let mut opening_key = aead::LessSafeKey::new(unbound_key);
+    let opening_key = aead::LessSafeKey::new(unbound_key);
+    let mut in_out = ciphertext.to_vec();
     let plaintext = opening_key
-        .open_in_place(nonce, aead::Aad::empty(), &mut ciphertext)
+        .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
         .context("failed to decrypt keypair")?;
 
     let kp = Keypair::from_bytes(plaintext).context("invalid keypair bytes")?;
     Ok(kp)
 }
 
+/// `decrypt_keypair` on the blocking pool, so the KDF does not stall a runtime worker.
+pub async fn decrypt_keypair_blocking(ciphertext_b64: String, kek: &str) -> Result<Keypair> {
+    let kek = kek.to_string();
+    tokio::task::spawn_blocking(move || decrypt_keypair(&ciphertext_b64, &kek)).await?
+}
+
+/// Produces the parent wallet's signature without the key living in this process, e.g. a
+/// hardware wallet or a remote signing service. `Keypair` implements it for local signing.
+pub trait RemoteSigner: Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+
+    /// Signs the serialized transaction message.
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> BoxFuture<'a, Result<Signature>>;
+}
+
+impl RemoteSigner for Keypair {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    fn sign_message<'a>(&'a self, message: &'a [u8]) -> BoxFuture<'a, Result<Signature>> {
+        Box::pin(async move { Ok(Signer::sign_message(self, message)) })
+    }
+}
+
+/// Signs `tx` with the in-process `local` keys (e.g. the ephemeral wallet), then asks
+/// `parent` for its signature over the same message. The parent's signature is verified
+/// before it is used, and every required signature must be present afterwards.
+pub async fn sign_transaction(
+    tx: &mut Transaction,
+    parent: &dyn RemoteSigner,
+    local: &[&Keypair],
+) -> Result<()> {
+    let blockhash = tx.message.recent_blockhash;
+    tx.try_partial_sign(local, blockhash)
+        .context("local signer is not part of the transaction")?;
+
+    let parent_pubkey = parent.pubkey();
+    let position = tx
+        .get_signing_keypair_positions(&[parent_pubkey])?
+        .into_iter()
+        .next()
+        .flatten()
+        .with_context(|| format!("{parent_pubkey} is not a signer of the transaction"))?;
+    let message = tx.message_data();
+    let signature = parent.sign_message(&message).await?;
+    anyhow::ensure!(
+        signature.verify(parent_pubkey.as_ref(), &message),
+        "remote signer returned an invalid signature for {parent_pubkey}"
+    );
+    tx.signatures[position] = signature;
+
+    anyhow::ensure!(tx.is_signed(), "transaction is missing required signatures");
+    Ok(())
+}
+
+/// Largest serialized transaction, signatures included, that fits in one packet.
+pub const MAX_TRANSACTION_SIZE: usize = PACKET_DATA_SIZE;
+/// Most distinct accounts a transaction may reference.
+pub const MAX_TRANSACTION_ACCOUNTS: usize = 64;
+
+/// Greedily packs `instructions`, in order, into as few transactions paid by `fee_payer` as
+/// fit [`MAX_TRANSACTION_SIZE`] and [`MAX_TRANSACTION_ACCOUNTS`]. Instructions are never
+/// reordered or split, so one too large on its own is an error.
+///
+/// The transactions carry placeholder signatures and no blockhash; set
+/// `message.recent_blockhash` before signing.
+pub fn pack_instructions(
+    fee_payer: &Pubkey,
+    instructions: Vec<Instruction>,
+) -> Result<Vec<Transaction>> {
+    let mut packed = Vec::new();
+    let mut current: Vec<Instruction> = Vec::new();
+    for ix in instructions {
+        current.push(ix);
+        if fits_in_transaction(fee_payer, &current)? {
+            continue;
+        }
+        let ix = current.pop().expect("pushed above");
+        if !current.is_empty() {
+            packed.push(Transaction::new_with_payer(&current, Some(fee_payer)));
+            current.clear();
+        }
+        current.push(ix);
+        anyhow::ensure!(
+            fits_in_transaction(fee_payer, &current)?,
+            "instruction for program {} does not fit in a single transaction",
+            current[0].program_id
+        );
+    }
+    if !current.is_empty() {
+        packed.push(Transaction::new_with_payer(&current, Some(fee_payer)));
+    }
+    Ok(packed)
+}
+
+fn fits_in_transaction(fee_payer: &Pubkey, instructions: &[Instruction]) -> Result<bool> {
+    let tx = Transaction::new_with_payer(instructions, Some(fee_payer));
+    let size = bincode::serialized_size(&tx)? as usize;
+    Ok(size <= MAX_TRANSACTION_SIZE && tx.message.account_keys.len() <= MAX_TRANSACTION_ACCOUNTS)
+}
+
+/// How long `send_and_confirm` waits for the requested commitment before giving up.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct TransactionSigner {
     rpc: RpcClient,
 }
@@ -71,8 +226,144 @@ impl TransactionSigner {
         Self { rpc }
     }
 
-    pub async fn send_and_confirm(&self, tx: &Transaction) -> Result<Signature> {
-        let sig = self.rpc.send_and_confirm_transaction(tx)?;
-        Ok(sig)
+    /// Submits `tx` and waits until it reaches `commitment`, e.g. `finalized` for trades that
+    /// must not be rolled back. Use `SolanaConfig::commitment_for` to pick the level.
+    pub async fn send_and_confirm(
+        &self,
+        tx: &Transaction,
+        commitment: CommitmentConfig,
+    ) -> Result<Signature> {
+        let sig = self.rpc.send_transaction(tx)?;
+        let deadline = Instant::now() + CONFIRM_TIMEOUT;
+        loop {
+            if self
+                .rpc
+                .confirm_transaction_with_commitment(&sig, commitment)?
+                .value
+            {
+                return Ok(sig);
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "transaction {sig} did not reach {:?} within {:?}",
+                    commitment.commitment,
+                    CONFIRM_TIMEOUT
+                );
+            }
+            tokio::time::sleep(CONFIRM_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(data_len: usize) -> Instruction {
+        Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: Vec::new(),
+            data: vec![7; data_len],
+        }
+    }
+
+    fn assert_fits(tx: &Transaction) {
+        assert!(bincode::serialized_size(tx).unwrap() as usize <= MAX_TRANSACTION_SIZE);
+        assert!(tx.message.account_keys.len() <= MAX_TRANSACTION_ACCOUNTS);
+    }
+
+    #[test]
+    fn splits_oversized_batches_across_two_transactions() {
+        let payer = Pubkey::new_unique();
+        let instructions: Vec<Instruction> = (0..4).map(|_| instruction(400)).collect();
+        let programs: Vec<Pubkey> = instructions.iter().map(|ix| ix.program_id).collect();
+
+        let packed = pack_instructions(&payer, instructions).unwrap();
+        assert_eq!(packed.len(), 2);
+        for tx in &packed {
+            assert_fits(tx);
+            assert_eq!(tx.message.account_keys[0], payer);
+        }
+        let packed_programs: Vec<Pubkey> = packed
+            .iter()
+            .flat_map(|tx| {
+                tx.message
+                    .instructions
+                    .iter()
+                    .map(|ix| tx.message.account_keys[ix.program_id_index as usize])
+            })
+            .collect();
+        assert_eq!(packed_programs, programs);
+    }
+
+    #[test]
+    fn small_batches_stay_in_one_transaction() {
+        let payer = Pubkey::new_unique();
+        let instructions = vec![instruction(32), instruction(32)];
+        assert_eq!(pack_instructions(&payer, instructions).unwrap().len(), 1);
+        assert!(pack_instructions(&payer, Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_an_instruction_too_large_on_its_own() {
+        let payer = Pubkey::new_unique();
+        let instructions = vec![instruction(32), instruction(MAX_TRANSACTION_SIZE)];
+        assert!(pack_instructions(&payer, instructions).is_err());
+    }
+
+    const KEK: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn encrypted_keypair_round_trips() {
+        let keypair = Keypair::new();
+        let blob = encrypt_keypair(&keypair, KEK).unwrap();
+        let decrypted = decrypt_keypair(&blob, KEK).unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+    }
+
+    #[tokio::test]
+    async fn blocking_pool_round_trip_matches_the_inline_functions() {
+        let keypair = Keypair::new();
+        let blob = encrypt_keypair_blocking(&keypair, KEK).await.unwrap();
+        assert_eq!(decrypt_keypair(&blob, KEK).unwrap().to_bytes(), keypair.to_bytes());
+
+        let blob = encrypt_keypair(&keypair, KEK).unwrap();
+        let decrypted = decrypt_keypair_blocking(blob, KEK).await.unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+
+        let wrong_key = decrypt_keypair_blocking(
+            encrypt_keypair_blocking(&keypair, KEK).await.unwrap(),
+            "another-key-encryption-key-32-bytes",
+        );
+        assert!(wrong_key.await.is_err());
+    }
+
+    #[test]
+    fn encrypting_twice_uses_fresh_nonces() {
+        let keypair = Keypair::new();
+        let first = encrypt_keypair(&keypair, KEK).unwrap();
+        let second = encrypt_keypair(&keypair, KEK).unwrap();
+        assert_ne!(first, second);
+
+        let nonce = |blob: &str| {
+            let bytes = general_purpose::STANDARD_NO_PAD.decode(blob).unwrap();
+            bytes[..aead::NONCE_LEN].to_vec()
+        };
+        assert_ne!(nonce(&first), nonce(&second));
+    }
+
+    #[test]
+    fn decrypt_rejects_wrong_key_and_tampered_blobs() {
+        let blob = encrypt_keypair(&Keypair::new(), KEK).unwrap();
+        assert!(decrypt_keypair(&blob, "another-key-encryption-key-32-bytes").is_err());
+
+        let mut bytes = general_purpose::STANDARD_NO_PAD.decode(&blob).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = general_purpose::STANDARD_NO_PAD.encode(&bytes);
+        assert!(decrypt_keypair(&tampered, KEK).is_err());
+
+        let truncated = general_purpose::STANDARD_NO_PAD.encode(&bytes[..aead::NONCE_LEN]);
+        assert!(decrypt_keypair(&truncated, KEK).is_err());
     }
 }
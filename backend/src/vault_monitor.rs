@@ -1,32 +1,372 @@
-use crate::{config::Config, session_manager::SessionManager};
+use crate::{
+    api::SessionEvent,
+    config::{Config, TxOperation},
+    delegation_manager::{self, DelegationManager},
+    session_manager::{CleanupRetry, SessionError, SessionManager, SessionStatus},
+    session_store::SessionStore,
+    transaction_signer::TransactionSigner,
+};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
 use sqlx::{Pool, Postgres};
-use tokio::time::{self, Duration};
-use tracing::info;
+use std::{future::Future, str::FromStr, sync::Arc};
+use tokio::{
+    sync::broadcast,
+    task::{JoinError, JoinSet},
+    time::{self, Duration, MissedTickBehavior},
+};
+use tracing::{info, warn};
+
+/// Upper bound on the delay between two cleanup attempts for one session.
+const MAX_CLEANUP_BACKOFF_SECS: u64 = 3600;
 
 pub struct VaultMonitor {
     pool: Pool<Postgres>,
     cfg: Config,
+    tx_events: broadcast::Sender<SessionEvent>,
 }
 
 impl VaultMonitor {
-    pub fn new(pool: Pool<Postgres>, cfg: Config) -> Self {
-        Self { pool, cfg }
+    pub fn new(
+        pool: Pool<Postgres>,
+        cfg: Config,
+        tx_events: broadcast::Sender<SessionEvent>,
+    ) -> Self {
+        Self {
+            pool,
+            cfg,
+            tx_events,
+        }
     }
 
-    pub async fn run(self) -> Result<()> {
-        let mut interval = time::interval(Duration::from_secs(30));
-        let session_manager = SessionManager::new(self.pool.clone(), self.cfg.clone());
+    /// Every `interval_secs`, moves sessions past `session_expiry` to `EXPIRED` (broadcasting
+    /// `SessionEvent::Expired` for each), syncs the spend of sessions with an armed spend
+    /// alert, then retries cleanup for sessions whose vault is past expiry. `submit_cleanup` submits the on-chain `cleanup_vault` for one session;
+    /// [`CleanupSubmitter`] is the production implementation.
+    ///
+    /// Each tick fans the due sessions out over a `JoinSet` of at most
+    /// `max_concurrent_checks` tasks and waits for all of them before the next tick. A panicking
+    /// task is logged and does not stop the loop.
+    ///
+    /// Failures back off exponentially per session; after `cleanup_max_attempts` the session
+    /// is parked in `NEEDS_MANUAL` and no longer retried.
+    pub async fn run<F, Fut>(self, submit_cleanup: F) -> Result<()>
+    where
+        F: Fn(CleanupRetry) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let period = Duration::from_secs(self.cfg.monitor.interval_secs.max(1));
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let session_manager = Arc::new(SessionManager::new(self.pool.clone(), self.cfg.clone()));
+        let delegations = DelegationManager::new(self.cfg.clone());
 
         loop {
             interval.tick().await;
-            // For brevity we only log; a real implementation would:
-            // * Query active sessions from DB
-            // * Check on-chain vault state for expiry/balance
-            // * Trigger cleanup_vault transactions when needed
             info!("vault_monitor_heartbeat");
 
-            let _ = &session_manager; // silence unused for now
+            if let Err(err) = self.expire_sessions(&session_manager).await {
+                warn!(error = %err, "vault_monitor_expiry_failed");
+            }
+            if let Err(err) = self.check_spend_alerts(&session_manager, &delegations).await {
+                warn!(error = %err, "vault_monitor_spend_check_failed");
+            }
+            if let Err(err) = self.tick(&session_manager, &submit_cleanup).await {
+                warn!(error = %err, "vault_monitor_tick_failed");
+            }
+        }
+    }
+
+    async fn expire_sessions(&self, sm: &SessionManager) -> Result<()> {
+        let expired = sm.mark_expired(Utc::now()).await?;
+        if !expired.is_empty() {
+            info!(expired = expired.len(), "sessions_expired");
         }
+        for session in expired {
+            // No subscribers is fine; the status change is already persisted.
+            let _ = self.tx_events.send(SessionEvent::Expired(session));
+        }
+        Ok(())
+    }
+
+    /// Reads the on-chain totals of every vault with an armed spend alert and records them,
+    /// broadcasting `SessionEvent::SpendAlert` for each session whose spend just crossed its
+    /// threshold. A vault that cannot be read is skipped until the next tick.
+    async fn check_spend_alerts(
+        &self,
+        sm: &SessionManager,
+        delegations: &DelegationManager,
+    ) -> Result<()> {
+        for watch in sm.spend_watches().await? {
+            let vault = match Pubkey::from_str(&watch.vault_pubkey) {
+                Ok(vault) => vault,
+                Err(err) => {
+                    warn!(session_id = %watch.session_id, error = %err, "spend_check_bad_vault");
+                    continue;
+                }
+            };
+            let onchain = match delegations.fetch_vault(vault).await {
+                Ok(onchain) => onchain,
+                Err(err) => {
+                    warn!(session_id = %watch.session_id, error = %err, "spend_check_fetch_failed");
+                    continue;
+                }
+            };
+            let alert = sm
+                .record_spend(
+                    watch.session_id,
+                    onchain.total_deposited,
+                    onchain.total_spent,
+                    watch.is_due(onchain.total_spent),
+                )
+                .await?;
+            if let Some(alert) = alert {
+                info!(
+                    session_id = %alert.session_id,
+                    total_spent = alert.total_spent,
+                    "spend_alert"
+                );
+                let _ = self.tx_events.send(SessionEvent::SpendAlert(alert));
+            }
+        }
+        Ok(())
+    }
+
+    async fn tick<F, Fut>(&self, sm: &Arc<SessionManager>, submit_cleanup: &F) -> Result<()>
+    where
+        F: Fn(CleanupRetry) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let now = Utc::now();
+        let monitor = &self.cfg.monitor;
+        let max_concurrent = monitor.max_concurrent_checks.max(1);
+        let mut tasks = JoinSet::new();
+        let mut checked = 0usize;
+
+        for candidate in sm.cleanup_candidates(now).await? {
+            if !cleanup_due(&candidate, monitor.cleanup_backoff_base_secs, now) {
+                continue;
+            }
+            if tasks.len() >= max_concurrent {
+                if let Some(joined) = tasks.join_next().await {
+                    log_join(joined);
+                }
+            }
+            let sm = sm.clone();
+            let submit_cleanup = submit_cleanup.clone();
+            let max_attempts = monitor.cleanup_max_attempts;
+            tasks.spawn(async move {
+                attempt_cleanup(&sm, submit_cleanup, candidate, max_attempts).await
+            });
+            checked += 1;
+        }
+
+        while let Some(joined) = tasks.join_next().await {
+            log_join(joined);
+        }
+        if checked > 0 {
+            info!(checked, "vault_monitor_tick_complete");
+        }
+        Ok(())
+    }
+}
+
+/// Submits `cleanup_vault` with the session's server-managed ephemeral key as cleaner and
+/// fee payer, so the cleaner reward returns to that wallet. Sessions with a client-managed
+/// key fail here and end up in `NEEDS_MANUAL` once retries are exhausted.
+pub struct CleanupSubmitter {
+    sessions: SessionManager,
+    delegations: DelegationManager,
+    signer: TransactionSigner,
+    cfg: Config,
+}
+
+impl CleanupSubmitter {
+    pub fn new(pool: Pool<Postgres>, cfg: Config) -> Self {
+        Self {
+            sessions: SessionManager::new(pool, cfg.clone()),
+            delegations: DelegationManager::new(cfg.clone()),
+            signer: TransactionSigner::new(&cfg.solana.rpc_url),
+            cfg,
+        }
+    }
+
+    pub async fn submit(self: Arc<Self>, candidate: CleanupRetry) -> Result<()> {
+        let program_id = self.cfg.solana.program_id()?;
+        let parent_wallet = Pubkey::from_str(&candidate.parent_wallet)?;
+        let vault = Pubkey::from_str(&candidate.vault_pubkey)?;
+        let cleaner = self
+            .sessions
+            .load_ephemeral_keypair(candidate.session_id)
+            .await?
+            .ok_or(SessionError::NotFound(candidate.session_id))?;
+
+        let delegation = delegation_manager::delegation_pda(&program_id, &vault, &cleaner.pubkey());
+        let delegation = self
+            .delegations
+            .account_exists(delegation)
+            .await?
+            .then_some(delegation);
+        let refund_recipient = self.delegations.fetch_vault(vault).await?.refund_recipient;
+        let ix = self.delegations.build_cleanup_vault_ix(
+            program_id,
+            parent_wallet,
+            vault,
+            cleaner.pubkey(),
+            delegation,
+            refund_recipient,
+        );
+        let tx = self
+            .delegations
+            .build_and_sign_transactions(&cleaner, &[], vec![ix])
+            .await?;
+        let commitment = self.cfg.solana.commitment_for(TxOperation::Cleanup)?;
+        let signature = self.signer.send_and_confirm(&tx, commitment).await?;
+        info!(session_id = %candidate.session_id, %signature, "cleanup_vault_submitted");
+        Ok(())
+    }
+}
+
+async fn attempt_cleanup<S, F, Fut>(
+    sm: &SessionManager<S>,
+    submit_cleanup: F,
+    candidate: CleanupRetry,
+    max_attempts: u32,
+) -> Result<()>
+where
+    S: SessionStore,
+    F: Fn(CleanupRetry) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let session_id = candidate.session_id;
+    match submit_cleanup(candidate).await {
+        Ok(()) => sm.record_cleanup_success(session_id, Utc::now()).await,
+        Err(err) => {
+            let status = sm
+                .record_cleanup_failure(session_id, &format!("{err:#}"), max_attempts, Utc::now())
+                .await?;
+            if status == SessionStatus::NeedsManual {
+                warn!(%session_id, error = %err, "cleanup_moved_to_manual");
+            } else {
+                warn!(%session_id, error = %err, "cleanup_attempt_failed");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn log_join(joined: Result<Result<()>, JoinError>) {
+    match joined {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => warn!(error = %err, "cleanup_check_failed"),
+        Err(err) if err.is_panic() => warn!(error = %err, "cleanup_check_panicked"),
+        Err(err) => warn!(error = %err, "cleanup_check_cancelled"),
+    }
+}
+
+/// Whether enough time has passed since the last failed attempt:
+/// `base * 2^(attempts - 1)` seconds, capped at [`MAX_CLEANUP_BACKOFF_SECS`].
+fn cleanup_due(candidate: &CleanupRetry, base_secs: u64, now: DateTime<Utc>) -> bool {
+    let Some(last) = candidate.last_cleanup_attempt_at else {
+        return true;
+    };
+    if candidate.cleanup_attempts == 0 {
+        return true;
+    }
+    let shift = (candidate.cleanup_attempts - 1).min(16);
+    let backoff = base_secs
+        .saturating_mul(1 << shift)
+        .min(MAX_CLEANUP_BACKOFF_SECS);
+    now >= last + chrono::Duration::seconds(backoff as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{session_manager::Session, session_store::InMemorySessionStore};
+
+    /// An expired session with a vault, as `cleanup_candidates` picks them up.
+    fn expired_session() -> Session {
+        let now = Utc::now();
+        Session {
+            id: uuid::Uuid::new_v4(),
+            parent_wallet: Pubkey::new_unique().to_string(),
+            ephemeral_wallet: Pubkey::new_unique().to_string(),
+            vault_pubkey: Some(Pubkey::new_unique().to_string()),
+            status: SessionStatus::Expired,
+            session_start: now - chrono::Duration::hours(2),
+            session_expiry: now - chrono::Duration::hours(1),
+            last_activity: now - chrono::Duration::hours(1),
+            max_deposit: 1_000_000,
+            total_deposited: 0,
+            total_spent: 0,
+            key_fingerprint: String::new(),
+            server_managed_key: false,
+            version: 1,
+        }
+    }
+
+    fn retry(attempts: u32, secs_since_last: i64, now: DateTime<Utc>) -> CleanupRetry {
+        CleanupRetry {
+            session_id: uuid::Uuid::new_v4(),
+            parent_wallet: String::new(),
+            vault_pubkey: String::new(),
+            status: SessionStatus::Expired,
+            cleanup_attempts: attempts,
+            last_cleanup_attempt_at: Some(now - chrono::Duration::seconds(secs_since_last)),
+            cleanup_last_error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_cleanup_moves_to_needs_manual_after_max_attempts() {
+        let session = expired_session();
+        let store = InMemorySessionStore::new();
+        store.insert(&session, None, None, None).await.unwrap();
+        let sm = SessionManager::with_store(store, Config::for_tests());
+        let failing = |_: CleanupRetry| async { Err(anyhow::anyhow!("cleanup_vault failed")) };
+
+        for attempt in 0..3 {
+            let candidates = sm.cleanup_candidates(Utc::now()).await.unwrap();
+            assert_eq!(candidates.len(), 1);
+            assert_eq!(candidates[0].session_id, session.id);
+            assert_eq!(candidates[0].cleanup_attempts, attempt);
+            let candidate = candidates.into_iter().next().unwrap();
+            attempt_cleanup(&sm, failing, candidate, 3).await.unwrap();
+        }
+
+        assert!(sm.cleanup_candidates(Utc::now()).await.unwrap().is_empty());
+        let parked = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(parked.status, SessionStatus::NeedsManual);
+    }
+
+    #[tokio::test]
+    async fn successful_cleanup_marks_the_session_cleaned() {
+        let session = expired_session();
+        let store = InMemorySessionStore::new();
+        store.insert(&session, None, None, None).await.unwrap();
+        let sm = SessionManager::with_store(store, Config::for_tests());
+
+        let candidate = sm.cleanup_candidates(Utc::now()).await.unwrap().remove(0);
+        attempt_cleanup(&sm, |_| async { Ok(()) }, candidate, 3)
+            .await
+            .unwrap();
+
+        assert!(sm.cleanup_candidates(Utc::now()).await.unwrap().is_empty());
+        let cleaned = sm.get(session.id).await.unwrap().unwrap();
+        assert_eq!(cleaned.status, SessionStatus::Cleaned);
+    }
+
+    #[test]
+    fn cleanup_backoff_doubles_up_to_the_cap() {
+        let now = Utc::now();
+        assert!(cleanup_due(&retry(0, 0, now), 60, now));
+        assert!(!cleanup_due(&retry(1, 59, now), 60, now));
+        assert!(cleanup_due(&retry(1, 60, now), 60, now));
+        assert!(!cleanup_due(&retry(3, 239, now), 60, now));
+        assert!(cleanup_due(&retry(3, 240, now), 60, now));
+        assert!(!cleanup_due(&retry(30, MAX_CLEANUP_BACKOFF_SECS as i64 - 1, now), 60, now));
+        assert!(cleanup_due(&retry(30, MAX_CLEANUP_BACKOFF_SECS as i64, now), 60, now));
     }
 }
@@ -0,0 +1,188 @@
+use crate::{
+    api::SessionEvent,
+    config::WebhookConfig,
+    session_manager::{Session, SessionManager},
+};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_BACKOFF_MS: u64 = 500;
+/// Request timeout for per-session callbacks, which carry no `WebhookConfig` of their own.
+const SESSION_CALLBACK_TIMEOUT_SECS: u64 = 5;
+
+/// Forwards `SessionEvent`s to the configured webhook targets.
+///
+/// Each event is delivered to every target concurrently, so a slow or hanging target only
+/// delays its own deliveries. Every request is bounded by the target's `timeout_secs`; a
+/// timeout is treated like any other transient failure and retried with exponential backoff.
+pub struct WebhookDispatcher {
+    client: reqwest::Client,
+    targets: Vec<WebhookConfig>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(targets: Vec<WebhookConfig>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            targets,
+        }
+    }
+
+    pub async fn run(self, mut rx: broadcast::Receiver<SessionEvent>) {
+        loop {
+            let evt = match rx.recv().await {
+                Ok(e) => e,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!(skipped = n, "webhook_dispatcher_lagged");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            for target in &self.targets {
+                let client = self.client.clone();
+                let target = target.clone();
+                let evt = evt.clone();
+                tokio::spawn(async move { deliver(&client, &target, &evt).await });
+            }
+        }
+    }
+}
+
+/// Sends `session`'s one-time activation callback, if it registered one at creation, as a
+/// `SessionEvent::Active` payload. The callback is claimed in the database before sending, so
+/// repeated activations (e.g. a retried `resume-bootstrap`) never fire it twice; delivery
+/// retries with the same backoff as the configured targets.
+pub fn spawn_activation_callback(sm: SessionManager, session: Session) {
+    tokio::spawn(async move {
+        let session_id = session.id;
+        let url = match sm.claim_activation_webhook(session_id).await {
+            Ok(Some(url)) => url,
+            Ok(None) => return,
+            Err(err) => {
+                warn!(%session_id, error = %err, "activation_webhook_claim_failed");
+                return;
+            }
+        };
+        let target = WebhookConfig {
+            url,
+            timeout_secs: SESSION_CALLBACK_TIMEOUT_SECS,
+        };
+        let evt = SessionEvent::Active(session);
+        if deliver(&reqwest::Client::new(), &target, &evt).await {
+            if let Err(err) = sm.record_activation_webhook_delivered(session_id).await {
+                warn!(%session_id, error = %err, "activation_webhook_record_failed");
+            }
+        }
+    });
+}
+
+async fn deliver(client: &reqwest::Client, target: &WebhookConfig, evt: &SessionEvent) -> bool {
+    for attempt in 0..MAX_ATTEMPTS {
+        let result = client
+            .post(&target.url)
+            .timeout(Duration::from_secs(target.timeout_secs))
+            .json(evt)
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if resp.status().is_success() => return true,
+            Ok(resp) if resp.status().is_client_error() => {
+                warn!(url = %target.url, status = %resp.status(), "webhook_rejected");
+                return false;
+            }
+            Ok(resp) => {
+                warn!(url = %target.url, status = %resp.status(), attempt, "webhook_failed");
+            }
+            Err(err) if err.is_timeout() => {
+                warn!(url = %target.url, timeout_secs = target.timeout_secs, attempt, "webhook_timeout");
+            }
+            Err(err) => {
+                warn!(url = %target.url, error = %err, attempt, "webhook_failed");
+            }
+        }
+
+        tokio::time::sleep(Duration::from_millis(BASE_BACKOFF_MS << attempt)).await;
+    }
+
+    info!(url = %target.url, "webhook_gave_up");
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_manager::SpendAlert;
+    use axum::{http::StatusCode, routing::post, Json, Router};
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    /// Serves `router` on a free local port and returns its URL.
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        url
+    }
+
+    fn target(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            timeout_secs: 30,
+        }
+    }
+
+    fn spend_alert(total_spent: u64) -> SessionEvent {
+        SessionEvent::SpendAlert(SpendAlert {
+            session_id: Uuid::new_v4(),
+            parent_wallet: String::new(),
+            vault_pubkey: None,
+            spend_alert_bps: 8_000,
+            total_spent,
+            max_deposit: 1_000,
+        })
+    }
+
+    #[tokio::test]
+    async fn slow_and_failing_sinks_do_not_hold_up_the_others() {
+        let hanging = serve(Router::new().route(
+            "/",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+                StatusCode::OK
+            }),
+        ))
+        .await;
+        let failing =
+            serve(Router::new().route("/", post(|| async { StatusCode::INTERNAL_SERVER_ERROR })))
+                .await;
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        let healthy = serve(Router::new().route(
+            "/",
+            post(move |Json(evt): Json<serde_json::Value>| async move {
+                received_tx.send(evt).unwrap();
+                StatusCode::OK
+            }),
+        ))
+        .await;
+
+        // A small channel: a dispatcher stuck on one sink would lag and skip events.
+        let (events, rx) = broadcast::channel(2);
+        let targets = vec![target(hanging), target(failing), target(healthy)];
+        tokio::spawn(WebhookDispatcher::new(targets).run(rx));
+
+        for total_spent in 1..=5 {
+            events.send(spend_alert(total_spent)).unwrap();
+            let evt = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .expect("healthy sink was held up")
+                .unwrap();
+            assert_eq!(evt["type"], "SpendAlert");
+            assert_eq!(evt["data"]["total_spent"], total_spent.to_string());
+        }
+    }
+}
+
@@ -1,9 +1,47 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
 use anchor_lang::solana_program::system_instruction;
 use anchor_lang::solana_program::program::invoke;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
 
 declare_id!("EpheVau1t1111111111111111111111111111111111");
 
+/// Hard ceiling on any vault's `max_deposit` (100 SOL), whatever the parent requests.
+#[constant]
+pub const ABSOLUTE_MAX_DEPOSIT: u64 = 100_000_000_000;
+
+/// Ephemeral wallets a vault can hold delegations for at once, including its own.
+#[constant]
+pub const MAX_DELEGATES: usize = 4;
+
+/// Longest a session may run (7 days): the cap on `session_duration` when a vault is created,
+/// and on the time left after `extend_session`.
+#[constant]
+pub const MAX_SESSION_DURATION: i64 = 7 * 24 * 60 * 60;
+
+/// Bytes in a delegation's `purpose` label.
+#[constant]
+pub const DELEGATION_PURPOSE_LEN: usize = 16;
+
+/// DEX programs a vault can whitelist for its trades.
+#[constant]
+pub const MAX_ALLOWED_PROGRAMS: usize = 4;
+
+/// Default `EphemeralVault::min_cleaner_balance` (0.01 SOL): what a caller of `cleanup_vault`
+/// or `settle_vault` must already hold to be paid a cleaner reward, so throwaway accounts
+/// cannot race each other for rewards across vaults. Parents change it per vault with
+/// `set_min_cleaner_balance`.
+#[constant]
+pub const MIN_CLEANER_BALANCE_LAMPORTS: u64 = 10_000_000;
+
+/// Number of fee-size buckets in `EphemeralVault::trade_histogram`.
+pub const TRADE_HISTOGRAM_BUCKETS: usize = 3;
+
+/// Upper bounds (exclusive, in lamports) of the `trade_histogram` buckets; the last bucket
+/// holds every fee at or above the final bound.
+pub const TRADE_HISTOGRAM_BOUNDS: [u64; TRADE_HISTOGRAM_BUCKETS - 1] = [10_000, 100_000];
+
 #[program]
 pub mod ephemeral_vault {
     use super::*;
@@ -13,58 +51,225 @@ pub mod ephemeral_vault {
         session_duration: i64,
         max_deposit: u64,
         ephemeral_wallet: Pubkey,
+        parent_topup_allowance: u64,
+        single_use: bool,
+        grace_trades: u8,
+        grace_period: i64,
+        slot_time_ms: u32,
+        per_trade_limit: u64,
+        min_trade_interval_secs: i64,
+        allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+    ) -> Result<()> {
+        assert_valid_ephemeral_wallet(
+            &ctx.accounts.ephemeral_wallet,
+            ephemeral_wallet,
+            ctx.accounts.parent.key(),
+            ctx.program_id,
+        )?;
+        let bump = *ctx
+            .bumps
+            .get("vault")
+            .ok_or(EphemeralVaultError::BumpNotFound)?;
+        init_vault(
+            &mut ctx.accounts.vault,
+            ctx.accounts.parent.key(),
+            ephemeral_wallet,
+            bump,
+            VaultParams {
+                session_duration,
+                max_deposit,
+                parent_topup_allowance,
+                single_use,
+                grace_trades,
+                grace_period,
+                slot_time_ms,
+                per_trade_limit,
+                min_trade_interval_secs,
+                allowed_programs,
+            },
+        )
+    }
+
+    /// `delegate_expiry_slot` bounds the delegation to end after that slot, and
+    /// `delegation_ttl_secs` to end that many seconds after approval; zero disables either,
+    /// leaving the delegation bounded only by the vault's session expiry. Approving again
+    /// (e.g. after a revoke) reuses the delegation account and bumps `approval_count`;
+    /// `spent` carries over.
+    ///
+    /// `purpose` is a free-form label such as "spot-bot", UTF-8 padded with trailing zeros.
+    pub fn approve_delegate(
+        ctx: Context<ApproveDelegate>,
+        delegate: Pubkey,
+        delegate_expiry_slot: u64,
+        delegation_ttl_secs: i64,
+        purpose: [u8; DELEGATION_PURPOSE_LEN],
     ) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
+
+        require!(
+            is_allowed_delegate(vault, &delegate),
+            EphemeralVaultError::InvalidDelegate
+        );
+        require!(
+            delegation_ttl_secs >= 0,
+            EphemeralVaultError::InvalidDuration
+        );
+        assert_valid_purpose(&purpose)?;
+
         let clock = Clock::get()?;
+        let expires_at = if delegation_ttl_secs == 0 {
+            0
+        } else {
+            clock
+                .unix_timestamp
+                .checked_add(delegation_ttl_secs)
+                .ok_or(EphemeralVaultError::MathOverflow)?
+        };
+        let bump = *ctx
+            .bumps
+            .get("delegation")
+            .ok_or(EphemeralVaultError::BumpNotFound)?;
+        record_approval(
+            vault,
+            &mut ctx.accounts.delegation,
+            delegate,
+            clock.unix_timestamp,
+            delegate_expiry_slot,
+            expires_at,
+            purpose,
+            bump,
+        )
+    }
 
-        vault.parent_wallet = ctx.accounts.parent.key();
-        vault.ephemeral_wallet = ephemeral_wallet;
-        vault.session_start = clock.unix_timestamp;
-        vault.session_expiry = clock
-            .unix_timestamp
-            .checked_add(session_duration)
-            .ok_or(EphemeralVaultError::MathOverflow)?;
-        vault.is_active = true;
-        vault.total_deposited = 0;
-        vault.total_spent = 0;
-        vault.max_deposit = max_deposit;
-        vault.bump = *ctx.bumps.get("vault").unwrap();
-
-        emit!(VaultCreated {
-            parent: ctx.accounts.parent.key(),
+    /// Adds `delegate` to the vault's allowed ephemeral wallets so the parent can then
+    /// `approve_delegate` it, giving it its own `VaultDelegation`. Allowing an already
+    /// allowed wallet is a no-op.
+    pub fn allow_delegate(ctx: Context<AllowDelegate>, delegate: Pubkey) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        require!(delegate != Pubkey::default(), EphemeralVaultError::InvalidDelegate);
+        if is_allowed_delegate(vault, &delegate) {
+            return Ok(());
+        }
+        let slot = vault
+            .allowed_delegates
+            .iter()
+            .position(|key| *key == Pubkey::default())
+            .ok_or(EphemeralVaultError::DelegateListFull)?;
+        vault.allowed_delegates[slot] = delegate;
+
+        let seq = next_event_seq(vault)?;
+        emit!(DelegateAllowed {
+            seq,
             vault: vault.key(),
-            ephemeral_wallet,
-            max_deposit,
-            session_start: vault.session_start,
-            session_expiry: vault.session_expiry,
+            delegate,
         });
 
         Ok(())
     }
 
-    pub fn approve_delegate(ctx: Context<ApproveDelegate>, delegate: Pubkey) -> Result<()> {
-        let vault = &ctx.accounts.vault;
+    /// Revokes one delegation while the vault and its other delegates stay live. Use
+    /// `revoke_access` to shut down the whole vault.
+    pub fn revoke_delegate(ctx: Context<RevokeDelegate>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(
+            delegation.revoked_at.is_none(),
+            EphemeralVaultError::DelegationRevoked
+        );
 
-        require_keys_eq!(
-            delegate,
-            vault.ephemeral_wallet,
-            EphemeralVaultError::InvalidDelegate
+        let now = Clock::get()?.unix_timestamp;
+        delegation.revoked_at = Some(now);
+
+        let seq = next_event_seq(vault)?;
+        emit!(DelegateRevoked {
+            seq,
+            vault: vault.key(),
+            delegate: delegation.delegate,
+            revoked_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Swaps the vault's ephemeral wallet for `new_ephemeral` in one parent-signed
+    /// instruction, e.g. after the backend rotated the session key. The old wallet loses its
+    /// `allowed_delegates` slot and its delegation, if passed, is revoked. Passing
+    /// `new_delegation` also approves the new wallet, with no slot bound, TTL or purpose.
+    ///
+    /// The vault PDA keeps its address: it stays derived from `seed_ephemeral_wallet`.
+    pub fn rotate_ephemeral(ctx: Context<RotateEphemeral>, new_ephemeral: Pubkey) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        ensure_vault_active_and_not_expired(vault)?;
+        let old_ephemeral = vault.ephemeral_wallet;
+        require!(
+            new_ephemeral != Pubkey::default()
+                && new_ephemeral != vault.parent_wallet
+                && new_ephemeral != old_ephemeral,
+            EphemeralVaultError::InvalidEphemeralWallet
         );
 
-        let clock = Clock::get()?;
-        let delegation = &mut ctx.accounts.delegation;
-        delegation.vault = vault.key();
-        delegation.delegate = delegate;
-        delegation.approved_at = clock.unix_timestamp;
-        delegation.revoked_at = None;
-        delegation.bump = *ctx.bumps.get("delegation").unwrap();
+        // The new wallet takes the old one's slot; any other slot it held is freed.
+        for key in vault.allowed_delegates.iter_mut() {
+            if *key == new_ephemeral {
+                *key = Pubkey::default();
+            }
+        }
+        match vault.allowed_delegates.iter().position(|key| *key == old_ephemeral) {
+            Some(slot) => vault.allowed_delegates[slot] = new_ephemeral,
+            None => {
+                let slot = vault
+                    .allowed_delegates
+                    .iter()
+                    .position(|key| *key == Pubkey::default())
+                    .ok_or(EphemeralVaultError::DelegateListFull)?;
+                vault.allowed_delegates[slot] = new_ephemeral;
+            }
+        }
+        vault.ephemeral_wallet = new_ephemeral;
+
+        let now = Clock::get()?.unix_timestamp;
+        let old_revoked = match ctx.accounts.old_delegation.as_mut() {
+            Some(old) if old.revoked_at.is_none() => {
+                old.revoked_at = Some(now);
+                true
+            }
+            _ => false,
+        };
+        if old_revoked {
+            let seq = next_event_seq(vault)?;
+            emit!(DelegateRevoked {
+                seq,
+                vault: vault.key(),
+                delegate: old_ephemeral,
+                revoked_at: now,
+            });
+        }
 
-        emit!(DelegateApproved {
+        let seq = next_event_seq(vault)?;
+        emit!(EphemeralRotated {
+            seq,
             vault: vault.key(),
-            delegate,
-            approved_at: clock.unix_timestamp,
+            old_ephemeral,
+            new_ephemeral,
         });
 
+        if let Some(new_delegation) = ctx.accounts.new_delegation.as_mut() {
+            let bump = *ctx
+                .bumps
+                .get("new_delegation")
+                .ok_or(EphemeralVaultError::BumpNotFound)?;
+            record_approval(
+                vault,
+                new_delegation,
+                new_ephemeral,
+                now,
+                0,
+                0,
+                [0; DELEGATION_PURPOSE_LEN],
+                bump,
+            )?;
+        }
+
         Ok(())
     }
 
@@ -72,48 +277,73 @@ pub mod ephemeral_vault {
         ctx: Context<AutoDeposit>,
         trade_fee_estimate: u64,
     ) -> Result<()> {
-        let vault = &mut ctx.accounts.vault;
-        let parent = &ctx.accounts.parent;
-        let system_program = &ctx.accounts.system_program;
+        let parent = ctx.accounts.parent.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        process_deposit(&mut ctx.accounts.vault, &parent, &system_program, trade_fee_estimate)
+    }
 
-        ensure_vault_active_and_not_expired(vault)?;
+    /// Pre-flight for `auto_deposit_for_trade`: emits whether `amount` would fit under
+    /// `max_deposit` without mutating state, so rejected attempts are visible to indexers.
+    pub fn check_deposit_capacity(ctx: Context<CheckDepositCapacity>, amount: u64) -> Result<()> {
+        let vault = &ctx.accounts.vault;
 
-        let new_total = vault
-            .total_deposited
-            .checked_add(trade_fee_estimate)
-            .ok_or(EphemeralVaultError::MathOverflow)?;
+        let remaining_capacity = vault.max_deposit.saturating_sub(vault.total_deposited);
+        let would_fit = vault.is_active && amount <= remaining_capacity;
+
+        emit!(DepositCheck {
+            vault: vault.key(),
+            amount,
+            would_fit,
+            remaining_capacity,
+        });
+
+        Ok(())
+    }
+
+    /// Parent raises `max_deposit` by `amount`. Unbounded: the parent owns the funds.
+    pub fn top_up_max_deposit(ctx: Context<TopUpMaxDeposit>, amount: u64) -> Result<()> {
+        let authority = ctx.accounts.parent_wallet.key();
+        raise_max_deposit(&mut ctx.accounts.vault, authority, amount)
+    }
+
+    /// Parent raises `max_deposit` to `new_max`. Unlike `top_up_max_deposit` this names the
+    /// target cap, so a retried transaction fails instead of raising the cap twice.
+    pub fn increase_max_deposit(ctx: Context<TopUpMaxDeposit>, new_max: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        ensure_vault_active_and_not_expired(vault)?;
         require!(
-            new_total <= vault.max_deposit,
-            EphemeralVaultError::OverDeposit
+            new_max > vault.max_deposit,
+            EphemeralVaultError::MaxDepositNotIncreased
+        );
+        require!(
+            new_max <= ABSOLUTE_MAX_DEPOSIT,
+            EphemeralVaultError::ExceedsProgramLimit
         );
 
-        let ix = system_instruction::transfer(&parent.key(), &vault.key(), trade_fee_estimate);
-        invoke(
-            &ix,
-            &[parent.to_account_info(), vault.to_account_info(), system_program.to_account_info()],
-        )?;
-
-        vault.total_deposited = new_total;
+        let old_max_deposit = vault.max_deposit;
+        vault.max_deposit = new_max;
 
-        emit!(AutoDeposit {
+        let seq = next_event_seq(vault)?;
+        emit!(MaxDepositIncreased {
+            seq,
             vault: vault.key(),
-            amount: trade_fee_estimate,
-            total_deposited: vault.total_deposited,
+            old_max_deposit,
+            new_max_deposit: new_max,
         });
 
         Ok(())
     }
 
-    pub fn execute_trade(
-        ctx: Context<ExecuteTrade>,
-        fee_paid: u64,
+    /// Delegate raises `max_deposit` by `amount` without the parent, as long as its
+    /// cumulative top-ups stay within the parent-set `parent_topup_allowance`.
+    pub fn delegate_top_up_max_deposit(
+        ctx: Context<DelegateTopUpMaxDeposit>,
+        amount: u64,
     ) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
         let delegation = &ctx.accounts.delegation;
+        let ephemeral = ctx.accounts.ephemeral.key();
 
-        ensure_vault_active_and_not_expired(vault)?;
-
-        // Ensure delegation is valid and not revoked.
         require_keys_eq!(
             delegation.vault,
             vault.key(),
@@ -125,28 +355,409 @@ pub mod ephemeral_vault {
         );
         require_keys_eq!(
             delegation.delegate,
-            ctx.accounts.ephemeral.key(),
+            ephemeral,
             EphemeralVaultError::InvalidDelegate
         );
 
-        // In a full implementation, this is where CPI(s) to the dark pool DEX program
-        // would be invoked using the vault funds and ephemeral wallet authority.
-
-        let new_spent = vault
-            .total_spent
-            .checked_add(fee_paid)
+        let topped_up = vault
+            .topped_up_by_delegate
+            .checked_add(amount)
             .ok_or(EphemeralVaultError::MathOverflow)?;
         require!(
-            new_spent <= vault.total_deposited,
-            EphemeralVaultError::InsufficientVaultBalance
+            topped_up <= vault.parent_topup_allowance,
+            EphemeralVaultError::TopUpAllowanceExceeded
         );
-        vault.total_spent = new_spent;
+        vault.topped_up_by_delegate = topped_up;
+
+        raise_max_deposit(vault, ephemeral, amount)
+    }
 
-        emit!(TradeExecuted {
+    /// Restricts the delegate to one specific trade: while a preauthorization is pending,
+    /// `execute_trade` only accepts a trade whose `trade_hash(fee_paid, venue)` matches, and
+    /// consumes the preauthorization. Calling again replaces any pending hash.
+    pub fn preauthorize_trade(ctx: Context<PreauthorizeTrade>, trade_hash: [u8; 32]) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        ensure_vault_active_and_not_expired(vault)?;
+        vault.preauthorized_trade = Some(trade_hash);
+
+        let seq = next_event_seq(vault)?;
+        emit!(TradePreauthorized {
+            seq,
             vault: vault.key(),
-            delegate: ctx.accounts.ephemeral.key(),
+            trade_hash,
+        });
+
+        Ok(())
+    }
+
+    /// `venue` is the DEX program or market the trade targets; it is recorded in
+    /// `TradeExecuted` so indexers can break spend down by venue.
+    ///
+    /// `min_output` is reserved for the slippage guard the DEX CPI will need. Until that CPI
+    /// lands there is no output to measure, so it must be zero (`MinOutputUnsupported`).
+    pub fn execute_trade(
+        ctx: Context<ExecuteTrade>,
+        fee_paid: u64,
+        venue: Pubkey,
+        min_output: u64,
+    ) -> Result<()> {
+        require!(min_output == 0, EphemeralVaultError::MinOutputUnsupported);
+
+        let ephemeral = ctx.accounts.ephemeral.key();
+        let target_program = ctx.accounts.target_program.key();
+        process_trade(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.delegation,
+            ephemeral,
+            target_program,
+            fee_paid,
+            venue,
+        )
+    }
+
+    /// Deposits and trades in one atomic instruction, so funds never sit idle between the two.
+    /// All deposit caps and delegation checks apply; if the trade fails the deposit reverts.
+    pub fn deposit_and_trade(
+        ctx: Context<DepositAndTrade>,
+        deposit: u64,
+        fee_paid: u64,
+        venue: Pubkey,
+    ) -> Result<()> {
+        let parent = ctx.accounts.parent_wallet.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let ephemeral = ctx.accounts.ephemeral.key();
+        let target_program = ctx.accounts.target_program.key();
+
+        process_deposit(&mut ctx.accounts.vault, &parent, &system_program, deposit)?;
+        process_trade(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.delegation,
+            ephemeral,
+            target_program,
             fee_paid,
+            venue,
+        )
+    }
+
+    /// Atomically retunes the vault's risk policy. `u64::MAX` (or `i64::MIN` for
+    /// `idle_timeout`) leaves the corresponding parameter unchanged; zero disables a limit.
+    pub fn update_risk_params(
+        ctx: Context<UpdateRiskParams>,
+        per_trade_cap: u64,
+        daily_limit: u64,
+        idle_timeout: i64,
+        max_velocity: u64,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        ensure_vault_active_and_not_expired(vault)?;
+        require!(
+            idle_timeout == i64::MIN || idle_timeout >= 0,
+            EphemeralVaultError::InvalidRiskParams
+        );
+
+        if per_trade_cap != u64::MAX {
+            vault.per_trade_limit = per_trade_cap;
+        }
+        if daily_limit != u64::MAX {
+            vault.daily_limit = daily_limit;
+        }
+        if idle_timeout != i64::MIN {
+            vault.idle_timeout = idle_timeout;
+        }
+        if max_velocity != u64::MAX {
+            vault.max_velocity = max_velocity;
+        }
+
+        let seq = next_event_seq(vault)?;
+        emit!(RiskParamsUpdated {
+            seq,
+            vault: vault.key(),
+            per_trade_limit: vault.per_trade_limit,
+            daily_limit: vault.daily_limit,
+            idle_timeout: vault.idle_timeout,
+            max_velocity: vault.max_velocity,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only view of the vault's accounting. Emits both the accounted available balance
+    /// and the real lamports above rent; a nonzero `delta` indicates drift (e.g. direct
+    /// transfers into the PDA) that clients should reconcile.
+    pub fn vault_summary(ctx: Context<VaultSummaryView>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let vault_info = vault.to_account_info();
+
+        let accounted_available = vault
+            .total_deposited
+            .checked_sub(vault.total_spent)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
+        let real_available = vault_info.lamports().saturating_sub(min_balance);
+        let delta = i64::try_from(real_available as i128 - accounted_available as i128)
+            .map_err(|_| EphemeralVaultError::MathOverflow)?;
+
+        emit!(VaultSummary {
+            vault: vault.key(),
+            is_active: vault.is_active,
+            session_expiry: vault.session_expiry,
+            total_deposited: vault.total_deposited,
+            total_spent: vault.total_spent,
+            accounted_available,
+            real_available,
+            delta,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only checkpoint of the vault's deposit accounting. Indexers can start from the
+    /// latest snapshot instead of summing every historical `AutoDeposit`; `as_of_seq` is the
+    /// last sequenced event the totals already include.
+    pub fn emit_deposit_snapshot(ctx: Context<DepositSnapshotView>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+
+        let available = vault
+            .total_deposited
+            .checked_sub(vault.total_spent)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+
+        emit!(DepositSnapshot {
+            vault: vault.key(),
+            total_deposited: vault.total_deposited,
             total_spent: vault.total_spent,
+            available,
+            as_of_slot: Clock::get()?.slot,
+            as_of_seq: vault.event_seq,
+        });
+
+        Ok(())
+    }
+
+    /// Answers "is this delegate currently allowed to trade?" in a single log line, combining
+    /// the revocation, vault-active, session-expiry and delegation-expiry checks that
+    /// `execute_trade` applies. Checks that depend on the trade itself (target program
+    /// whitelist, trade cooldown, preauthorized hash, risk limits) are not considered, so a
+    /// trade may still be rejected while `valid` is true.
+    pub fn is_delegation_valid(ctx: Context<DelegationStatusView>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let delegation = &ctx.accounts.delegation;
+
+        let clock = Clock::get()?;
+        let expired = session_expired(vault, &clock)
+            || delegation_expired(delegation, &clock)
+            || delegation_ttl_elapsed(delegation, &clock);
+        let valid = vault.is_active
+            && delegation.vault == vault.key()
+            && delegation.revoked_at.is_none()
+            && !expired;
+
+        emit!(DelegationStatus {
+            vault: vault.key(),
+            delegate: delegation.delegate,
+            valid,
+            revoked_at: delegation.revoked_at,
+            expired,
+        });
+
+        Ok(())
+    }
+
+    /// Read-only rollup of the vault's delegations. PDAs cannot be enumerated on-chain, so the
+    /// caller passes the delegation accounts in `remaining_accounts`; each must belong to this
+    /// vault and appear once.
+    pub fn view_delegations<'info>(
+        ctx: Context<'_, '_, '_, 'info, ViewDelegations<'info>>,
+    ) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut revoked: u16 = 0;
+        let mut total_spent: u64 = 0;
+        for info in ctx.remaining_accounts {
+            require!(
+                !seen.contains(info.key),
+                EphemeralVaultError::InvalidDelegationAccount
+            );
+            seen.push(*info.key);
+
+            let delegation = Account::<VaultDelegation>::try_from(info)?;
+            require_keys_eq!(
+                delegation.vault,
+                vault.key(),
+                EphemeralVaultError::InvalidDelegationAccount
+            );
+            if delegation.revoked_at.is_some() {
+                revoked += 1;
+            }
+            total_spent = total_spent
+                .checked_add(delegation.spent)
+                .ok_or(EphemeralVaultError::MathOverflow)?;
+        }
+        let count =
+            u16::try_from(seen.len()).map_err(|_| EphemeralVaultError::MathOverflow)?;
+
+        emit!(DelegationsOverview {
+            vault: vault.key(),
+            count,
+            active: count - revoked,
+            revoked,
+            total_spent,
+        });
+
+        Ok(())
+    }
+
+    /// Pushes `session_expiry` forward by `additional_secs` so a long-running strategy can
+    /// keep its vault and delegation. The new expiry may be at most `MAX_SESSION_DURATION`
+    /// from now. A slot-based expiry is pushed forward at the slot rate it was created with.
+    pub fn extend_session(ctx: Context<ExtendSession>, additional_secs: i64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        ensure_vault_active_and_not_expired(vault)?;
+        require!(additional_secs > 0, EphemeralVaultError::InvalidDuration);
+
+        let clock = Clock::get()?;
+        let old_expiry = vault.session_expiry;
+        let new_expiry = old_expiry
+            .checked_add(additional_secs)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        let latest_allowed = clock
+            .unix_timestamp
+            .checked_add(MAX_SESSION_DURATION)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        require!(
+            new_expiry <= latest_allowed,
+            EphemeralVaultError::SessionTooLong
+        );
+
+        if vault.expiry_slot != 0 {
+            vault.expiry_slot = extended_expiry_slot(vault, additional_secs)?;
+        }
+        vault.session_expiry = new_expiry;
+        assert_valid_window(vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(SessionExtended {
+            seq,
+            vault: vault.key(),
+            old_expiry,
+            new_expiry,
+        });
+
+        Ok(())
+    }
+
+    /// Records that an external consumer has durably processed every event up to `seq`.
+    /// Acks are monotonic and cannot run ahead of the vault's latest `event_seq`.
+    pub fn ack_seq(ctx: Context<AckSeq>, seq: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+
+        require!(
+            seq >= vault.last_acked_seq && seq <= vault.event_seq,
+            EphemeralVaultError::SeqOutOfRange
+        );
+        vault.last_acked_seq = seq;
+
+        emit!(SeqAcked {
+            vault: vault.key(),
+            last_acked_seq: seq,
+            event_seq: vault.event_seq,
+        });
+
+        Ok(())
+    }
+
+    /// Returns lamports the vault holds beyond its accounted balance
+    /// (`total_deposited - total_spent`) and rent-exempt minimum to the parent, without
+    /// ending the session.
+    pub fn withdraw_excess(ctx: Context<WithdrawExcess>, amount: u64) -> Result<()> {
+        ensure_vault_active_and_not_expired(&ctx.accounts.vault)?;
+
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let withdrawable = withdrawable_excess(&vault_info, &ctx.accounts.vault)?;
+        require!(
+            amount <= withdrawable,
+            EphemeralVaultError::ExceedsWithdrawableExcess
+        );
+
+        let parent_info = ctx.accounts.parent_wallet.to_account_info();
+        move_lamports(&vault_info, &parent_info, amount)?;
+
+        let vault = &mut ctx.accounts.vault;
+        assert_solvent(&vault_info, vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(ExcessWithdrawn {
+            seq,
+            vault: vault.key(),
+            parent: parent_info.key(),
+            amount,
+            remaining_excess: withdrawable
+                .checked_sub(amount)
+                .ok_or(EphemeralVaultError::MathOverflow)?,
+        });
+
+        Ok(())
+    }
+
+    /// Turns the `cleanup_vault` reward on or off. Operators running their own cleanup
+    /// infrastructure can disable it so every residual lamport returns to the parent;
+    /// anyone can still trigger cleanup.
+    pub fn set_cleaner_reward_enabled(
+        ctx: Context<SetCleanerRewardEnabled>,
+        enabled: bool,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.cleaner_reward_enabled = enabled;
+
+        let seq = next_event_seq(vault)?;
+        emit!(CleanerRewardToggled {
+            seq,
+            vault: vault.key(),
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the balance a cleaner must hold to be paid the cleanup reward. Zero pays every
+    /// caller; an under-funded caller still completes the sweep, unpaid.
+    pub fn set_min_cleaner_balance(
+        ctx: Context<SetMinCleanerBalance>,
+        lamports: u64,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.min_cleaner_balance = lamports;
+
+        let seq = next_event_seq(vault)?;
+        emit!(MinCleanerBalanceSet {
+            seq,
+            vault: vault.key(),
+            lamports,
+        });
+
+        Ok(())
+    }
+
+    /// Sends the residual balance of later `revoke_access` and `cleanup_vault` calls to
+    /// `recipient` instead of the parent.
+    pub fn set_refund_recipient(
+        ctx: Context<SetRefundRecipient>,
+        recipient: Pubkey,
+    ) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        require!(
+            recipient != Pubkey::default(),
+            EphemeralVaultError::InvalidRefundRecipient
+        );
+        vault.refund_recipient = recipient;
+
+        let seq = next_event_seq(vault)?;
+        emit!(RefundRecipientSet {
+            seq,
+            vault: vault.key(),
+            recipient,
         });
 
         Ok(())
@@ -165,20 +776,14 @@ pub mod ephemeral_vault {
         let clock = Clock::get()?;
         delegation.revoked_at = Some(clock.unix_timestamp);
 
-        // Return remaining lamports (minus rent-exempt minimum) to parent.
-        let vault_info = vault.to_account_info();
-        let parent_info = parent.to_account_info();
-        let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
-        let current_balance = **vault_info.lamports.borrow();
-        if current_balance > min_balance {
-            let amount = current_balance
-                .checked_sub(min_balance)
-                .ok_or(EphemeralVaultError::MathOverflow)?;
-            **vault_info.try_borrow_mut_lamports()? -= amount;
-            **parent_info.try_borrow_mut_lamports()? += amount;
-        }
+        // Return remaining lamports (minus rent-exempt minimum) to the refund recipient.
+        let refund_recipient = &ctx.accounts.refund_recipient;
+        sweep_to(&vault.to_account_info(), &refund_recipient.to_account_info())?;
+        assert_solvent(&vault.to_account_info(), vault)?;
 
+        let seq = next_event_seq(vault)?;
         emit!(AccessRevoked {
+            seq,
             vault: vault.key(),
             parent: parent.key(),
             revoked_at: clock.unix_timestamp,
@@ -187,73 +792,1558 @@ pub mod ephemeral_vault {
         Ok(())
     }
 
-    pub fn cleanup_vault(ctx: Context<CleanupVault>) -> Result<()> {
+    /// Kill switch for a compromised delegate: revokes the delegation, deactivates the vault
+    /// and sweeps every lamport above rent to the vault's `refund_recipient` in one
+    /// instruction, so no trade can land between the steps. Unlike `revoke_access` it also
+    /// succeeds on a vault that is already inactive, expired or revoked, re-sweeping whatever
+    /// has arrived since.
+    pub fn emergency_revoke(ctx: Context<EmergencyRevoke>) -> Result<()> {
         let vault = &mut ctx.accounts.vault;
-        let cleaner = &ctx.accounts.cleaner;
-        let parent = &ctx.accounts.parent;
+        let delegation = &mut ctx.accounts.delegation;
+        let parent = &ctx.accounts.parent_wallet;
+        let refund_recipient = &ctx.accounts.refund_recipient;
+
+        let clock = Clock::get()?;
+        vault.is_active = false;
+        let revoked_at = *delegation.revoked_at.get_or_insert(clock.unix_timestamp);
+
+        let swept = sweep_to(&vault.to_account_info(), &refund_recipient.to_account_info())?;
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(EmergencyRevoked {
+            seq,
+            vault: vault.key(),
+            parent: parent.key(),
+            delegate: delegation.delegate,
+            refund_recipient: refund_recipient.key(),
+            swept,
+            revoked_at,
+        });
+
+        Ok(())
+    }
+
+    /// Like `revoke_access`, but splits the remaining balance across `shares` instead of
+    /// returning it to the parent. Recipient accounts are passed, writable and in the same
+    /// order, in `remaining_accounts`. Rounding dust goes to the last recipient.
+    pub fn revoke_and_distribute<'info>(
+        ctx: Context<'_, '_, '_, 'info, RevokeAndDistribute<'info>>,
+        shares: Vec<DistributionShare>,
+    ) -> Result<()> {
+        require!(
+            !shares.is_empty() && shares.len() <= MAX_DISTRIBUTION_RECIPIENTS,
+            EphemeralVaultError::InvalidDistribution
+        );
+        require!(
+            ctx.remaining_accounts.len() == shares.len(),
+            EphemeralVaultError::InvalidDistribution
+        );
+        let total_bps = shares
+            .iter()
+            .try_fold(0u16, |acc, share| acc.checked_add(share.bps))
+            .ok_or(EphemeralVaultError::InvalidDistribution)?;
+        require!(
+            total_bps == BPS_DENOMINATOR,
+            EphemeralVaultError::InvalidDistribution
+        );
+        for (share, account) in shares.iter().zip(ctx.remaining_accounts) {
+            require_keys_eq!(
+                share.recipient,
+                account.key(),
+                EphemeralVaultError::InvalidDistribution
+            );
+            require!(account.is_writable, EphemeralVaultError::InvalidDistribution);
+        }
+
+        let vault = &mut ctx.accounts.vault;
+        let delegation = &mut ctx.accounts.delegation;
+
+        ensure_vault_not_already_inactive(vault)?;
+        vault.is_active = false;
+
+        let clock = Clock::get()?;
+        delegation.revoked_at = Some(clock.unix_timestamp);
+
+        let vault_info = vault.to_account_info();
+        let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
+        let available = vault_info.lamports().saturating_sub(min_balance);
+
+        let mut distributed: u64 = 0;
+        for (i, (share, account)) in shares.iter().zip(ctx.remaining_accounts).enumerate() {
+            let amount = if i + 1 == shares.len() {
+                available
+                    .checked_sub(distributed)
+                    .ok_or(EphemeralVaultError::MathOverflow)?
+            } else {
+                (available as u128 * share.bps as u128 / BPS_DENOMINATOR as u128) as u64
+            };
+            distributed = distributed
+                .checked_add(amount)
+                .ok_or(EphemeralVaultError::MathOverflow)?;
+            move_lamports(&vault_info, account, amount)?;
+        }
+        assert_solvent(&vault_info, vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(FundsDistributed {
+            seq,
+            vault: vault.key(),
+            parent: ctx.accounts.parent_wallet.key(),
+            total: distributed,
+            recipients: shares.len() as u8,
+        });
+        let seq = next_event_seq(vault)?;
+        emit!(AccessRevoked {
+            seq,
+            vault: vault.key(),
+            parent: ctx.accounts.parent_wallet.key(),
+            revoked_at: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a vault that holds an SPL token instead of SOL, together with its associated
+    /// token account, owned by the vault PDA. Trades draw on `token_deposited`; the vault
+    /// still carries the usual SOL accounting at zero.
+    pub fn create_token_vault(
+        ctx: Context<CreateTokenVault>,
+        session_duration: i64,
+        max_token_deposit: u64,
+        ephemeral_wallet: Pubkey,
+        per_trade_limit: u64,
+        min_trade_interval_secs: i64,
+        allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+    ) -> Result<()> {
+        assert_valid_ephemeral_wallet(
+            &ctx.accounts.ephemeral_wallet,
+            ephemeral_wallet,
+            ctx.accounts.parent.key(),
+            ctx.program_id,
+        )?;
+        let bump = *ctx
+            .bumps
+            .get("vault")
+            .ok_or(EphemeralVaultError::BumpNotFound)?;
+        let vault = &mut ctx.accounts.vault;
+        init_vault(
+            vault,
+            ctx.accounts.parent.key(),
+            ephemeral_wallet,
+            bump,
+            VaultParams {
+                session_duration,
+                max_deposit: 0,
+                parent_topup_allowance: 0,
+                single_use: false,
+                grace_trades: 0,
+                grace_period: 0,
+                slot_time_ms: 0,
+                per_trade_limit,
+                min_trade_interval_secs,
+                allowed_programs,
+            },
+        )?;
+        vault.token_mint = ctx.accounts.token_mint.key();
+        vault.token_account = ctx.accounts.vault_token_account.key();
+        vault.max_token_deposit = max_token_deposit;
+
+        let seq = next_event_seq(vault)?;
+        emit!(TokenVaultCreated {
+            seq,
+            vault: vault.key(),
+            token_mint: vault.token_mint,
+            token_account: vault.token_account,
+            max_token_deposit,
+        });
+
+        Ok(())
+    }
+
+    /// Moves `amount` tokens from the parent's token account into the vault's, capped by
+    /// `max_token_deposit`.
+    pub fn auto_deposit_token(ctx: Context<AutoDepositToken>, amount: u64) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        ensure_vault_active_and_not_expired(vault)?;
+        require!(
+            vault.token_mint != Pubkey::default(),
+            EphemeralVaultError::NotTokenVault
+        );
+
+        let new_total = vault
+            .token_deposited
+            .checked_add(amount)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        require!(
+            new_total <= vault.max_token_deposit,
+            EphemeralVaultError::OverDeposit
+        );
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.parent_token_account.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.parent_wallet.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        vault.token_deposited = new_total;
+        vault.last_activity = Clock::get()?.unix_timestamp;
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(TokenDeposit {
+            seq,
+            vault: vault.key(),
+            amount,
+            token_deposited: vault.token_deposited,
+        });
+
+        Ok(())
+    }
+
+    /// Token-vault counterpart of `execute_trade`: the same delegation, cooldown and risk
+    /// checks, with `amount` debited from `token_deposited` in the mint's base units.
+    pub fn execute_token_trade(
+        ctx: Context<ExecuteTokenTrade>,
+        amount: u64,
+        venue: Pubkey,
+    ) -> Result<()> {
+        let ephemeral = ctx.accounts.ephemeral.key();
+        let target_program = ctx.accounts.target_program.key();
+        let vault = &mut ctx.accounts.vault;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(
+            vault.token_mint != Pubkey::default(),
+            EphemeralVaultError::NotTokenVault
+        );
+        let now = authorize_trade(vault, delegation, ephemeral, target_program, amount, venue)?;
+
+        // In a full implementation, the DEX CPI would spend from the vault token account
+        // here, signed by the vault PDA.
+
+        let new_spent = vault
+            .token_spent
+            .checked_add(amount)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        require!(
+            new_spent <= vault.token_deposited,
+            EphemeralVaultError::InsufficientVaultBalance
+        );
+        vault.token_spent = new_spent;
+        record_trade(vault, delegation, amount, now)?;
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(TokenTradeExecuted {
+            seq,
+            vault: vault.key(),
+            delegate: ephemeral,
+            venue,
+            amount,
+            token_spent: vault.token_spent,
+            trade_count: vault.trade_count,
+        });
+
+        revoke_if_single_use(vault, delegation, now)
+    }
+
+    /// Returns a finished token vault's remaining tokens to the parent and closes its token
+    /// account, sending the rent to the parent. Anyone may call it once the vault is
+    /// inactive or its session has expired; `cleanup_vault` requires it first.
+    pub fn close_token_account(ctx: Context<CloseTokenAccount>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        let clock = Clock::get()?;
+        require!(
+            !vault.is_active || session_expired(vault, &clock),
+            EphemeralVaultError::SessionNotExpired
+        );
+
+        let parent_key = vault.parent_wallet;
+        let ephemeral_key = vault.seed_ephemeral_wallet;
+        let bump = [vault.bump];
+        let seeds: &[&[u8]] = &[
+            b"vault",
+            parent_key.as_ref(),
+            ephemeral_key.as_ref(),
+            &bump,
+        ];
+        let signer = &[seeds];
+        let vault_info = vault.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+
+        let returned = ctx.accounts.vault_token_account.amount;
+        if returned > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.clone(),
+                    Transfer {
+                        from: ctx.accounts.vault_token_account.to_account_info(),
+                        to: ctx.accounts.parent_token_account.to_account_info(),
+                        authority: vault_info.clone(),
+                    },
+                    signer,
+                ),
+                returned,
+            )?;
+        }
+        token::close_account(CpiContext::new_with_signer(
+            token_program,
+            CloseAccount {
+                account: ctx.accounts.vault_token_account.to_account_info(),
+                destination: ctx.accounts.parent_wallet.to_account_info(),
+                authority: vault_info,
+            },
+            signer,
+        ))?;
+
+        let vault = &mut ctx.accounts.vault;
+        let token_account = vault.token_account;
+        vault.token_account = Pubkey::default();
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(TokenAccountClosed {
+            seq,
+            vault: vault.key(),
+            token_account,
+            returned,
+        });
+
+        Ok(())
+    }
+
+    pub fn cleanup_vault(ctx: Context<CleanupVault>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let cleaner = &ctx.accounts.cleaner;
+        let parent = &ctx.accounts.parent;
 
         let clock = Clock::get()?;
         require!(
             clock.unix_timestamp >= vault.session_expiry,
             EphemeralVaultError::SessionNotExpired
         );
+        require!(
+            vault.token_account == Pubkey::default(),
+            EphemeralVaultError::TokenAccountOpen
+        );
 
         // If still active, mark inactive.
         if vault.is_active {
             vault.is_active = false;
         }
 
-        // Pay small reward to cleaner from vault lamports, bounded by constant.
-        let vault_info = vault.to_account_info();
-        let parent_info = parent.to_account_info();
-        let cleaner_info = cleaner.to_account_info();
-        let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
-        let current_balance = **vault_info.lamports.borrow();
+        let recipient_info = ctx.accounts.refund_recipient.to_account_info();
+        let (reward, refunded) =
+            pay_out_residual(vault, &cleaner.to_account_info(), &recipient_info)?;
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        // Anchor closes the delegation to `parent` on exit; report the rent it returns.
+        let delegation_rent_reclaimed = ctx
+            .accounts
+            .delegation
+            .as_ref()
+            .map_or(0, |delegation| delegation.to_account_info().lamports());
+
+        // The vault's final fee-size breakdown, so analytics need not replay `TradeExecuted`.
+        // `settle_vault` already reported it for a settled vault, and no trade ran since.
+        if !vault.is_settled {
+            let seq = next_event_seq(vault)?;
+            emit!(TradeHistogram {
+                seq,
+                vault: vault.key(),
+                bounds: TRADE_HISTOGRAM_BOUNDS,
+                counts: vault.trade_histogram,
+            });
+        }
+
+        let seq = next_event_seq(vault)?;
+        emit!(VaultCleaned {
+            seq,
+            vault: vault.key(),
+            parent: parent.key(),
+            cleaner: cleaner.key(),
+            reward,
+            refund_recipient: recipient_info.key(),
+            refunded,
+            delegation_rent_reclaimed,
+        });
+
+        Ok(())
+    }
+
+    /// `cleanup_vault` without the close: after expiry anyone can stop the vault and sweep
+    /// its residual balance (cleaner reward, then `refund_recipient`), but the vault and its
+    /// delegations stay on-chain for audit. The vault is left inactive and `is_settled`; a
+    /// later `cleanup_vault` still closes it and reclaims the rent.
+    pub fn settle_vault(ctx: Context<SettleVault>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        let cleaner = &ctx.accounts.cleaner;
+
+        let clock = Clock::get()?;
+        require!(
+            clock.unix_timestamp >= vault.session_expiry,
+            EphemeralVaultError::SessionNotExpired
+        );
+        require!(!vault.is_settled, EphemeralVaultError::VaultSettled);
+        require!(
+            vault.token_account == Pubkey::default(),
+            EphemeralVaultError::TokenAccountOpen
+        );
+
+        vault.is_active = false;
+        vault.is_settled = true;
+
+        let recipient_info = ctx.accounts.refund_recipient.to_account_info();
+        let (reward, refunded) =
+            pay_out_residual(vault, &cleaner.to_account_info(), &recipient_info)?;
+        assert_solvent(&vault.to_account_info(), vault)?;
+
+        let seq = next_event_seq(vault)?;
+        emit!(TradeHistogram {
+            seq,
+            vault: vault.key(),
+            bounds: TRADE_HISTOGRAM_BOUNDS,
+            counts: vault.trade_histogram,
+        });
+
+        let seq = next_event_seq(vault)?;
+        emit!(VaultSettled {
+            seq,
+            vault: vault.key(),
+            cleaner: cleaner.key(),
+            reward,
+            refund_recipient: recipient_info.key(),
+            refunded,
+        });
+
+        Ok(())
+    }
+}
+
+/// Cap on the reward `cleanup_vault` and `settle_vault` pay their caller.
+const MAX_CLEANUP_REWARD_LAMPORTS: u64 = 10_000;
+
+/// Moves everything above the vault's rent minimum out: a reward of up to
+/// `MAX_CLEANUP_REWARD_LAMPORTS` to `cleaner` (if `cleaner_reward_enabled`), the rest to
+/// `recipient`. Returns `(reward, refunded)`. A drained vault pays nothing, but callers still
+/// emit their event so indexers see every vault end. A cleaner holding less than the vault's
+/// `min_cleaner_balance` beforehand is paid nothing and the reward goes to `recipient`.
+fn pay_out_residual(
+    vault: &Account<EphemeralVault>,
+    cleaner: &AccountInfo,
+    recipient: &AccountInfo,
+) -> Result<(u64, u64)> {
+    let vault_info = vault.to_account_info();
+    let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
+    let available = vault_info.lamports().saturating_sub(min_balance);
+    let reward = if vault.cleaner_reward_enabled
+        && cleaner.lamports() >= vault.min_cleaner_balance
+    {
+        available.min(MAX_CLEANUP_REWARD_LAMPORTS)
+    } else {
+        0
+    };
+    let refunded = available
+        .checked_sub(reward)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    move_lamports(&vault_info, cleaner, reward)?;
+    move_lamports(&vault_info, recipient, refunded)?;
+    Ok((reward, refunded))
+}
+
+/// Basis points in a whole; `revoke_and_distribute` shares must sum to this.
+const BPS_DENOMINATOR: u16 = 10_000;
+
+/// Upper bound on `revoke_and_distribute` recipients, keeping the transaction within
+/// account and compute limits.
+const MAX_DISTRIBUTION_RECIPIENTS: usize = 8;
+
+fn raise_max_deposit(
+    vault: &mut Account<EphemeralVault>,
+    authority: Pubkey,
+    amount: u64,
+) -> Result<()> {
+    ensure_vault_active_and_not_expired(vault)?;
+
+    vault.max_deposit = vault
+        .max_deposit
+        .checked_add(amount)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    require!(
+        vault.max_deposit <= ABSOLUTE_MAX_DEPOSIT,
+        EphemeralVaultError::ExceedsProgramLimit
+    );
+
+    let seq = next_event_seq(vault)?;
+    emit!(MaxDepositRaised {
+        seq,
+        vault: vault.key(),
+        authority,
+        amount,
+        max_deposit: vault.max_deposit,
+        topped_up_by_delegate: vault.topped_up_by_delegate,
+    });
+
+    Ok(())
+}
+
+/// Settings `create_vault` and `create_token_vault` share.
+struct VaultParams {
+    session_duration: i64,
+    max_deposit: u64,
+    parent_topup_allowance: u64,
+    single_use: bool,
+    grace_trades: u8,
+    grace_period: i64,
+    slot_time_ms: u32,
+    per_trade_limit: u64,
+    min_trade_interval_secs: i64,
+    allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+}
+
+/// Initializes a freshly created vault as a native SOL vault and emits `VaultCreated`.
+fn init_vault(
+    vault: &mut Account<EphemeralVault>,
+    parent: Pubkey,
+    ephemeral_wallet: Pubkey,
+    bump: u8,
+    params: VaultParams,
+) -> Result<()> {
+    // A zero or negative window would leave a vault that is expired on arrival.
+    require!(
+        params.session_duration > 0 && params.session_duration <= MAX_SESSION_DURATION,
+        EphemeralVaultError::InvalidSessionDuration
+    );
+    let clock = Clock::get()?;
+
+    vault.parent_wallet = parent;
+    vault.ephemeral_wallet = ephemeral_wallet;
+    vault.session_start = clock.unix_timestamp;
+    vault.created_slot = clock.slot;
+    vault.session_expiry = clock
+        .unix_timestamp
+        .checked_add(params.session_duration)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    assert_valid_window(vault)?;
+    require!(params.grace_period >= 0, EphemeralVaultError::InvalidDuration);
+    require!(
+        params.min_trade_interval_secs >= 0,
+        EphemeralVaultError::InvalidDuration
+    );
+    vault.expiry_slot =
+        expiry_slot_for(clock.slot, params.session_duration, params.slot_time_ms)?;
+    require!(
+        params.max_deposit <= ABSOLUTE_MAX_DEPOSIT,
+        EphemeralVaultError::ExceedsProgramLimit
+    );
+    vault.is_active = true;
+    vault.total_deposited = 0;
+    vault.total_spent = 0;
+    vault.max_deposit = params.max_deposit;
+    vault.per_trade_limit = params.per_trade_limit;
+    vault.daily_limit = 0;
+    vault.idle_timeout = 0;
+    vault.max_velocity = 0;
+    vault.daily_window_start = clock.unix_timestamp;
+    vault.daily_spent = 0;
+    vault.velocity_window_start = clock.unix_timestamp;
+    vault.velocity_window_trades = 0;
+    vault.last_activity = clock.unix_timestamp;
+    vault.parent_topup_allowance = params.parent_topup_allowance;
+    vault.topped_up_by_delegate = 0;
+    vault.single_use = params.single_use;
+    vault.event_seq = 0;
+    vault.last_acked_seq = 0;
+    vault.grace_trades = params.grace_trades;
+    vault.grace_period = params.grace_period;
+    vault.allowed_delegates = [Pubkey::default(); MAX_DELEGATES];
+    vault.allowed_delegates[0] = ephemeral_wallet;
+    vault.cleaner_reward_enabled = true;
+    vault.min_cleaner_balance = MIN_CLEANER_BALANCE_LAMPORTS;
+    vault.trade_count = 0;
+    vault.last_trade_ts = 0;
+    vault.min_trade_interval_secs = params.min_trade_interval_secs;
+    vault.token_mint = Pubkey::default();
+    vault.token_account = Pubkey::default();
+    vault.max_token_deposit = 0;
+    vault.token_deposited = 0;
+    vault.token_spent = 0;
+    vault.trade_histogram = [0; TRADE_HISTOGRAM_BUCKETS];
+    vault.allowed_programs = params.allowed_programs;
+    vault.refund_recipient = parent;
+    vault.seed_ephemeral_wallet = ephemeral_wallet;
+    vault.is_settled = false;
+    vault.bump = bump;
+
+    let seq = next_event_seq(vault)?;
+    emit!(VaultCreated {
+        seq,
+        parent,
+        vault: vault.key(),
+        ephemeral_wallet,
+        max_deposit: params.max_deposit,
+        session_start: vault.session_start,
+        session_expiry: vault.session_expiry,
+        created_slot: vault.created_slot,
+    });
+
+    Ok(())
+}
+
+fn process_deposit<'info>(
+    vault: &mut Account<'info, EphemeralVault>,
+    parent: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    ensure_vault_active_and_not_expired(vault)?;
+
+    let new_total = vault
+        .total_deposited
+        .checked_add(amount)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    require!(
+        new_total <= vault.max_deposit,
+        EphemeralVaultError::OverDeposit
+    );
+
+    let ix = system_instruction::transfer(&parent.key(), &vault.key(), amount);
+    invoke(
+        &ix,
+        &[parent.clone(), vault.to_account_info(), system_program.clone()],
+    )?;
+
+    vault.total_deposited = new_total;
+    vault.last_activity = Clock::get()?.unix_timestamp;
+
+    assert_solvent(&vault.to_account_info(), vault)?;
+
+    let seq = next_event_seq(vault)?;
+    emit!(AutoDeposit {
+        seq,
+        vault: vault.key(),
+        amount,
+        total_deposited: vault.total_deposited,
+    });
+
+    Ok(())
+}
+
+fn process_trade(
+    vault: &mut Account<EphemeralVault>,
+    delegation: &mut VaultDelegation,
+    ephemeral: Pubkey,
+    target_program: Pubkey,
+    fee_paid: u64,
+    venue: Pubkey,
+) -> Result<()> {
+    let now = authorize_trade(vault, delegation, ephemeral, target_program, fee_paid, venue)?;
+
+    // In a full implementation, this is where CPI(s) to the dark pool DEX program
+    // would be invoked using the vault funds and ephemeral wallet authority.
+
+    let new_spent = vault
+        .total_spent
+        .checked_add(fee_paid)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    require!(
+        new_spent <= vault.total_deposited,
+        EphemeralVaultError::InsufficientVaultBalance
+    );
+    vault.total_spent = new_spent;
+    record_trade(vault, delegation, fee_paid, now)?;
+    record_trade_bucket(vault, fee_paid)?;
+
+    assert_solvent(&vault.to_account_info(), vault)?;
+
+    let seq = next_event_seq(vault)?;
+    emit!(TradeExecuted {
+        seq,
+        vault: vault.key(),
+        delegate: ephemeral,
+        venue,
+        fee_paid,
+        total_spent: vault.total_spent,
+        per_trade_limit: vault.per_trade_limit,
+        trade_count: vault.trade_count,
+        last_trade_ts: vault.last_trade_ts,
+    });
+
+    revoke_if_single_use(vault, delegation, now)
+}
+
+/// Checks every trade must pass whatever asset it spends: vault state and grace, the
+/// delegation, the target program whitelist, any preauthorized hash, the cooldown and the
+/// risk limits. Returns the trade's timestamp.
+fn authorize_trade(
+    vault: &mut Account<EphemeralVault>,
+    delegation: &VaultDelegation,
+    ephemeral: Pubkey,
+    target_program: Pubkey,
+    amount: u64,
+    venue: Pubkey,
+) -> Result<i64> {
+    require!(vault.is_active, EphemeralVaultError::VaultInactive);
+    require!(
+        is_allowed_program(vault, &target_program),
+        EphemeralVaultError::InvalidTargetProgram
+    );
+    let clock = Clock::get()?;
+    let now = clock.unix_timestamp;
+    consume_grace_trade_if_expired(vault, &clock)?;
+
+    // Ensure delegation is valid and not revoked.
+    require_keys_eq!(
+        delegation.vault,
+        vault.key(),
+        EphemeralVaultError::InvalidDelegationAccount
+    );
+    require!(
+        delegation.revoked_at.is_none(),
+        EphemeralVaultError::DelegationRevoked
+    );
+    require_keys_eq!(
+        delegation.delegate,
+        ephemeral,
+        EphemeralVaultError::InvalidDelegate
+    );
+    require!(
+        !delegation_expired(delegation, &clock),
+        EphemeralVaultError::DelegateExpired
+    );
+    require!(
+        !delegation_ttl_elapsed(delegation, &clock),
+        EphemeralVaultError::DelegationExpired
+    );
+    if let Some(expected) = vault.preauthorized_trade {
+        require!(
+            trade_hash(amount, &venue) == expected,
+            EphemeralVaultError::TradeNotPreauthorized
+        );
+        vault.preauthorized_trade = None;
+    }
+
+    if vault.min_trade_interval_secs > 0 {
+        require!(
+            now.saturating_sub(vault.last_trade_ts) >= vault.min_trade_interval_secs,
+            EphemeralVaultError::TradeCooldown
+        );
+    }
+    enforce_risk_limits(vault, amount, now)?;
+
+    Ok(now)
+}
+
+/// Bookkeeping shared by every trade once its asset-specific spend is recorded.
+fn record_trade(
+    vault: &mut EphemeralVault,
+    delegation: &mut VaultDelegation,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    vault.last_activity = now;
+    vault.trade_count = vault
+        .trade_count
+        .checked_add(1)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    vault.last_trade_ts = now;
+    delegation.spent = delegation
+        .spent
+        .checked_add(amount)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(())
+}
+
+/// Counts a SOL trade in the `trade_histogram` bucket for its fee.
+fn record_trade_bucket(vault: &mut EphemeralVault, fee_paid: u64) -> Result<()> {
+    let bucket = TRADE_HISTOGRAM_BOUNDS
+        .iter()
+        .position(|bound| fee_paid < *bound)
+        .unwrap_or(TRADE_HISTOGRAM_BUCKETS - 1);
+    vault.trade_histogram[bucket] = vault.trade_histogram[bucket]
+        .checked_add(1)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(())
+}
+
+/// One-shot vaults self-revoke after their first successful trade.
+fn revoke_if_single_use(
+    vault: &mut Account<EphemeralVault>,
+    delegation: &mut VaultDelegation,
+    now: i64,
+) -> Result<()> {
+    if vault.single_use {
+        vault.is_active = false;
+        delegation.revoked_at = Some(now);
+        let seq = next_event_seq(vault)?;
+        emit!(AccessRevoked {
+            seq,
+            vault: vault.key(),
+            parent: vault.parent_wallet,
+            revoked_at: now,
+        });
+    }
+    Ok(())
+}
+
+fn ensure_vault_active_and_not_expired(vault: &EphemeralVault) -> Result<()> {
+    require!(vault.is_active, EphemeralVaultError::VaultInactive);
+    let clock = Clock::get()?;
+    require!(
+        !session_expired(vault, &clock),
+        EphemeralVaultError::SessionExpired
+    );
+    Ok(())
+}
+
+/// The session has ended once past `session_expiry` or, with slot expiry enabled, past
+/// `expiry_slot`. Whichever bound is reached first governs, so skewing one clock alone
+/// cannot extend a session.
+fn session_expired(vault: &EphemeralVault, clock: &Clock) -> bool {
+    clock.unix_timestamp > vault.session_expiry
+        || (vault.expiry_slot != 0 && clock.slot > vault.expiry_slot)
+}
+
+/// Slot at which a session of `session_duration` seconds starting at `start_slot` ends,
+/// assuming `slot_time_ms` per slot and rounding up. Zero `slot_time_ms` disables slot
+/// expiry.
+fn expiry_slot_for(start_slot: u64, session_duration: i64, slot_time_ms: u32) -> Result<u64> {
+    if slot_time_ms == 0 {
+        return Ok(0);
+    }
+    let duration_ms = u64::try_from(session_duration)
+        .ok()
+        .and_then(|secs| secs.checked_mul(1_000))
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    let slot_time_ms = slot_time_ms as u64;
+    let slots = duration_ms
+        .checked_add(slot_time_ms - 1)
+        .ok_or(EphemeralVaultError::MathOverflow)?
+        / slot_time_ms;
+    let expiry_slot = start_slot
+        .checked_add(slots)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(expiry_slot)
+}
+
+/// Hash a parent commits to with `preauthorize_trade`: sha256 over the little-endian
+/// `fee_paid` followed by the `venue` pubkey.
+pub fn trade_hash(fee_paid: u64, venue: &Pubkey) -> [u8; 32] {
+    hashv(&[&fee_paid.to_le_bytes(), venue.as_ref()]).to_bytes()
+}
+
+fn is_allowed_program(vault: &EphemeralVault, program: &Pubkey) -> bool {
+    *program != Pubkey::default() && vault.allowed_programs.contains(program)
+}
+
+/// Writes a (re)approval of `delegate` into `delegation` and emits `DelegateApproved`.
+#[allow(clippy::too_many_arguments)]
+fn record_approval(
+    vault: &mut Account<EphemeralVault>,
+    delegation: &mut Account<VaultDelegation>,
+    delegate: Pubkey,
+    now: i64,
+    expiry_slot: u64,
+    expires_at: i64,
+    purpose: [u8; DELEGATION_PURPOSE_LEN],
+    bump: u8,
+) -> Result<()> {
+    let reapproved = delegation.approval_count > 0;
+    delegation.approval_count = delegation
+        .approval_count
+        .checked_add(1)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    delegation.vault = vault.key();
+    delegation.delegate = delegate;
+    delegation.approved_at = now;
+    delegation.revoked_at = None;
+    delegation.expiry_slot = expiry_slot;
+    delegation.expires_at = expires_at;
+    delegation.purpose = purpose;
+    delegation.bump = bump;
+
+    let seq = next_event_seq(vault)?;
+    emit!(DelegateApproved {
+        seq,
+        vault: vault.key(),
+        delegate,
+        approved_at: now,
+        expiry_slot,
+        expires_at,
+        purpose,
+        approval_count: delegation.approval_count,
+        reapproved,
+    });
+
+    Ok(())
+}
+
+fn is_allowed_delegate(vault: &EphemeralVault, delegate: &Pubkey) -> bool {
+    *delegate != Pubkey::default() && vault.allowed_delegates.contains(delegate)
+}
+
+/// Whether the delegation's own slot bound has passed. Zero means no bound.
+fn delegation_expired(delegation: &VaultDelegation, clock: &Clock) -> bool {
+    delegation.expiry_slot != 0 && clock.slot > delegation.expiry_slot
+}
+
+/// The delegation's own time window has closed, independent of the session's.
+fn delegation_ttl_elapsed(delegation: &VaultDelegation, clock: &Clock) -> bool {
+    delegation.expires_at != 0 && clock.unix_timestamp > delegation.expires_at
+}
+
+/// Past the session's end, lets a trade through only while within `grace_period` of
+/// `session_expiry` and with grace trades left, consuming one. Before the end this is a
+/// no-op.
+fn consume_grace_trade_if_expired(vault: &mut EphemeralVault, clock: &Clock) -> Result<()> {
+    if !session_expired(vault, clock) {
+        return Ok(());
+    }
+    let now = clock.unix_timestamp;
+    let grace_end = vault
+        .session_expiry
+        .checked_add(vault.grace_period)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    require!(
+        now <= grace_end && vault.grace_trades > 0,
+        EphemeralVaultError::SessionExpired
+    );
+    vault.grace_trades -= 1;
+    Ok(())
+}
+
+/// Allocates the sequence number for the next vault-mutating event. Sequence numbers start
+/// at 1 (`VaultCreated`) and increase by one per event, so indexers can detect gaps.
+fn next_event_seq(vault: &mut EphemeralVault) -> Result<u64> {
+    vault.event_seq = vault
+        .event_seq
+        .checked_add(1)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(vault.event_seq)
+}
+
+/// `expiry_slot` moved `additional_secs` later, at the slots-per-second ratio implied by the
+/// vault's current slot and timestamp windows, rounding up.
+fn extended_expiry_slot(vault: &EphemeralVault, additional_secs: i64) -> Result<u64> {
+    let window_slots = vault.expiry_slot.saturating_sub(vault.created_slot) as u128;
+    let window_secs = vault
+        .session_expiry
+        .checked_sub(vault.session_start)
+        .ok_or(EphemeralVaultError::MathOverflow)? as u128;
+    let extra_slots = (additional_secs as u128)
+        .checked_mul(window_slots)
+        .and_then(|n| n.checked_add(window_secs - 1))
+        .ok_or(EphemeralVaultError::MathOverflow)?
+        / window_secs;
+    let extra_slots = u64::try_from(extra_slots).map_err(|_| EphemeralVaultError::MathOverflow)?;
+    let expiry_slot = vault
+        .expiry_slot
+        .checked_add(extra_slots)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(expiry_slot)
+}
+
+/// The ephemeral wallet must sign trades, so it cannot be one of this program's own accounts:
+/// a vault or delegation PDA has no private key. It must not be the parent either, or the
+/// delegate would be the parent itself and the vault would isolate nothing. The account
+/// passed must also be the wallet named in the arguments, since it seeds the vault PDA.
+///
+/// PDAs are off the ed25519 curve, but the curve check is not available on-chain, so a PDA
+/// that has not been initialized yet is only caught off-chain.
+fn assert_valid_ephemeral_wallet(
+    account: &AccountInfo,
+    ephemeral_wallet: Pubkey,
+    parent: Pubkey,
+    program_id: &Pubkey,
+) -> Result<()> {
+    require_keys_neq!(
+        ephemeral_wallet,
+        Pubkey::default(),
+        EphemeralVaultError::InvalidEphemeralWallet
+    );
+    require_keys_neq!(
+        ephemeral_wallet,
+        parent,
+        EphemeralVaultError::InvalidEphemeralWallet
+    );
+    require_keys_eq!(
+        account.key(),
+        ephemeral_wallet,
+        EphemeralVaultError::InvalidEphemeralWallet
+    );
+    require_keys_neq!(
+        *account.owner,
+        *program_id,
+        EphemeralVaultError::InvalidEphemeralWallet
+    );
+    Ok(())
+}
+
+/// A purpose label is UTF-8 followed only by zero padding, so explorers can render it.
+fn assert_valid_purpose(purpose: &[u8; DELEGATION_PURPOSE_LEN]) -> Result<()> {
+    let len = purpose.iter().position(|b| *b == 0).unwrap_or(purpose.len());
+    require!(
+        purpose[len..].iter().all(|b| *b == 0) && std::str::from_utf8(&purpose[..len]).is_ok(),
+        EphemeralVaultError::InvalidPurpose
+    );
+    Ok(())
+}
+
+/// Invariant: a session window is never empty. Call after every write to `session_expiry`.
+fn assert_valid_window(vault: &EphemeralVault) -> Result<()> {
+    require!(
+        vault.session_expiry > vault.session_start,
+        EphemeralVaultError::InvalidDuration
+    );
+    Ok(())
+}
+
+/// Invariant: an active vault must hold at least `total_deposited - total_spent` lamports
+/// above its rent-exempt minimum. Inactive vaults have been swept, so they need only keep
+/// the minimum itself. Every instruction that moves vault funds ends with this check.
+fn assert_solvent(vault_info: &AccountInfo, vault: &EphemeralVault) -> Result<()> {
+    check_solvent(vault_info, vault, &Rent::get()?)
+}
+
+fn check_solvent(vault_info: &AccountInfo, vault: &EphemeralVault, rent: &Rent) -> Result<()> {
+    let min_balance = rent.minimum_balance(vault_info.data_len());
+    if !vault.is_active {
+        require!(
+            vault_info.lamports() >= min_balance,
+            EphemeralVaultError::InsolventVault
+        );
+        return Ok(());
+    }
+    let accounted_available = vault
+        .total_deposited
+        .checked_sub(vault.total_spent)
+        .ok_or(EphemeralVaultError::InsolventVault)?;
+    let real_available = vault_info.lamports().saturating_sub(min_balance);
+    require!(
+        real_available >= accounted_available,
+        EphemeralVaultError::InsolventVault
+    );
+    Ok(())
+}
+
+/// Lamports above the rent-exempt minimum that are not owed to the session's accounted
+/// balance, i.e. what `withdraw_excess` may return to the parent.
+fn withdrawable_excess(vault_info: &AccountInfo, vault: &EphemeralVault) -> Result<u64> {
+    let accounted_available = vault
+        .total_deposited
+        .checked_sub(vault.total_spent)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
+    let reserved = min_balance
+        .checked_add(accounted_available)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    Ok(vault_info.lamports().saturating_sub(reserved))
+}
+
+/// Applies the parent-configured risk policy to a trade of `fee_paid` lamports, rolling the
+/// daily and per-minute windows forward as needed. A zero limit means "no limit".
+fn enforce_risk_limits(vault: &mut EphemeralVault, fee_paid: u64, now: i64) -> Result<()> {
+    const DAY_SECS: i64 = 86_400;
+    const VELOCITY_WINDOW_SECS: i64 = 60;
+
+    if vault.per_trade_limit > 0 {
+        require!(
+            fee_paid <= vault.per_trade_limit,
+            EphemeralVaultError::PerTradeLimitExceeded
+        );
+    }
+
+    if vault.idle_timeout > 0 {
+        let idle_for = now
+            .checked_sub(vault.last_activity)
+            .ok_or(EphemeralVaultError::MathOverflow)?;
+        require!(
+            idle_for <= vault.idle_timeout,
+            EphemeralVaultError::SessionIdle
+        );
+    }
+
+    if now.saturating_sub(vault.daily_window_start) >= DAY_SECS {
+        vault.daily_window_start = now;
+        vault.daily_spent = 0;
+    }
+    let daily_spent = vault
+        .daily_spent
+        .checked_add(fee_paid)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    if vault.daily_limit > 0 {
+        require!(
+            daily_spent <= vault.daily_limit,
+            EphemeralVaultError::DailyLimitExceeded
+        );
+    }
+    vault.daily_spent = daily_spent;
+
+    if now.saturating_sub(vault.velocity_window_start) >= VELOCITY_WINDOW_SECS {
+        vault.velocity_window_start = now;
+        vault.velocity_window_trades = 0;
+    }
+    let window_trades = vault
+        .velocity_window_trades
+        .checked_add(1)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    if vault.max_velocity > 0 {
+        require!(
+            window_trades <= vault.max_velocity,
+            EphemeralVaultError::VelocityExceeded
+        );
+    }
+    vault.velocity_window_trades = window_trades;
+
+    Ok(())
+}
+
+/// Moves every lamport above the vault's rent-exempt minimum to `recipient`, returning the
+/// amount moved.
+fn sweep_to(vault_info: &AccountInfo, recipient: &AccountInfo) -> Result<u64> {
+    let min_balance = Rent::get()?.minimum_balance(vault_info.data_len());
+    let amount = vault_info.lamports().saturating_sub(min_balance);
+    move_lamports(vault_info, recipient, amount)?;
+    Ok(amount)
+}
+
+/// Debits `amount` lamports from program-owned `from` and credits them to `to`, failing with
+/// `MathOverflow` rather than wrapping either balance. Moving zero is a no-op.
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let from_lamports = from
+        .lamports()
+        .checked_sub(amount)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    **from.try_borrow_mut_lamports()? = from_lamports;
+    // Read after the debit, so `from == to` nets to zero.
+    let to_lamports = to
+        .lamports()
+        .checked_add(amount)
+        .ok_or(EphemeralVaultError::MathOverflow)?;
+    **to.try_borrow_mut_lamports()? = to_lamports;
+    Ok(())
+}
+
+fn ensure_vault_not_already_inactive(vault: &EphemeralVault) -> Result<()> {
+    require!(vault.is_active, EphemeralVaultError::VaultInactive);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateVault<'info> {
+    #[account(mut)]
+    pub parent: Signer<'info>,
+
+    /// CHECK: Ephemeral wallet is an off-chain keypair; we only store its pubkey.
+    pub ephemeral_wallet: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = parent,
+        space = 8 + EphemeralVault::LEN,
+        seeds = [b"vault", parent.key().as_ref(), ephemeral_wallet.key().as_ref()],
+        bump,
+    )]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct ApproveDelegate<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    #[account(mut)]
+    pub parent: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = parent,
+        space = 8 + VaultDelegation::LEN,
+        seeds = [b"delegation", vault.key().as_ref(), delegate.as_ref()],
+        bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(new_ephemeral: Pubkey)]
+pub struct RotateEphemeral<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Parent authorizes the rotation and pays for `new_delegation` if it is created.
+    #[account(mut)]
+    pub parent_wallet: Signer<'info>,
+
+    /// The current ephemeral wallet's delegation, revoked by the rotation. Omit it if that
+    /// wallet was never approved.
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), vault.ephemeral_wallet.as_ref()],
+        bump = old_delegation.bump,
+    )]
+    pub old_delegation: Option<Account<'info, VaultDelegation>>,
+
+    /// Delegation for `new_ephemeral`; pass it to approve the new wallet in the same step.
+    #[account(
+        init_if_needed,
+        payer = parent_wallet,
+        space = 8 + VaultDelegation::LEN,
+        seeds = [b"delegation", vault.key().as_ref(), new_ephemeral.as_ref()],
+        bump,
+    )]
+    pub new_delegation: Option<Account<'info, VaultDelegation>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AllowDelegate<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeDelegate<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), delegation.delegate.as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+}
+
+#[derive(Accounts)]
+pub struct AutoDeposit<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    #[account(mut)]
+    pub parent: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CheckDepositCapacity<'info> {
+    pub vault: Account<'info, EphemeralVault>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpMaxDeposit<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DelegateTopUpMaxDeposit<'info> {
+    #[account(mut)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Delegate spends from the parent-set top-up allowance.
+    pub ephemeral: Signer<'info>,
+
+    #[account(
+        seeds = [b"delegation", vault.key().as_ref(), ephemeral.key().as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTrade<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// CHECK: Ephemeral wallet must sign to execute trades.
+    #[account(signer)]
+    pub ephemeral: AccountInfo<'info>,
+
+    /// Derived from this vault and signer with a freshly computed bump, so a delegation of
+    /// another vault, or one with a tampered stored `bump`, fails with `ConstraintSeeds`.
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), ephemeral.key().as_ref()],
+        bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+
+    /// Parent wallet is stored for has_one checks but does not need to sign here.
+    /// CHECK: Only used for has_one relationship; actual authority for executing trades is the ephemeral wallet.
+    pub parent_wallet: UncheckedAccount<'info>,
+
+    /// CHECK: Reserved for the token account the DEX CPI will pay into; not read until then.
+    /// Omit it.
+    #[account(mut)]
+    pub trade_output: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: The DEX program the trade routes through; must be in `vault.allowed_programs`.
+    #[account(executable)]
+    pub target_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositAndTrade<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Parent funds the deposit.
+    #[account(mut)]
+    pub parent_wallet: Signer<'info>,
+
+    /// Delegate authorizes the trade.
+    pub ephemeral: Signer<'info>,
+
+    /// Bump recomputed as in `ExecuteTrade`.
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), ephemeral.key().as_ref()],
+        bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: The DEX program the trade routes through; must be in `vault.allowed_programs`.
+    #[account(executable)]
+    pub target_program: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateRiskParams<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Parent must sign to retune the vault's risk policy.
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VaultSummaryView<'info> {
+    pub vault: Account<'info, EphemeralVault>,
+}
+
+#[derive(Accounts)]
+pub struct DepositSnapshotView<'info> {
+    pub vault: Account<'info, EphemeralVault>,
+}
+
+#[derive(Accounts)]
+pub struct DelegationStatusView<'info> {
+    pub vault: Account<'info, EphemeralVault>,
+
+    #[account(
+        seeds = [b"delegation", vault.key().as_ref(), delegation.delegate.as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+}
+
+#[derive(Accounts)]
+pub struct ViewDelegations<'info> {
+    pub vault: Account<'info, EphemeralVault>,
+}
+
+#[derive(Accounts)]
+pub struct PreauthorizeTrade<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExtendSession<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AckSeq<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawExcess<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Parent must sign; also receives the withdrawn lamports.
+    #[account(mut)]
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetCleanerRewardEnabled<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinCleanerBalance<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetRefundRecipient<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAccess<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    #[account(mut, signer)]
+    pub parent: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), delegation.delegate.as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Only used for has_one constraint.
+    pub parent_wallet: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the swept balance; must be the vault's configured recipient.
+    #[account(
+        mut,
+        address = vault.refund_recipient @ EphemeralVaultError::InvalidRefundRecipient,
+    )]
+    pub refund_recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyRevoke<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    /// Parent must sign.
+    pub parent_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), delegation.delegate.as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+
+    /// CHECK: Receives the swept balance; must be the vault's configured recipient.
+    #[account(
+        mut,
+        address = vault.refund_recipient @ EphemeralVaultError::InvalidRefundRecipient,
+    )]
+    pub refund_recipient: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAndDistribute<'info> {
+    #[account(mut, has_one = parent_wallet)]
+    pub vault: Account<'info, EphemeralVault>,
+
+    pub parent_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"delegation", vault.key().as_ref(), delegation.delegate.as_ref()],
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, VaultDelegation>,
+}
+
+#[derive(Accounts)]
+pub struct CleanupVault<'info> {
+    #[account(mut, has_one = parent_wallet, close = parent)]
+    pub vault: Account<'info, EphemeralVault>,
 
-        const MAX_CLEANUP_REWARD_LAMPORTS: u64 = 10_000; // small fixed reward cap
+    /// CHECK: Receives the reclaimed vault and delegation rent; must be the vault's parent,
+    /// since anyone can call cleanup.
+    #[account(mut, address = vault.parent_wallet)]
+    pub parent: AccountInfo<'info>,
 
-        if current_balance > min_balance {
-            let available = current_balance
-                .checked_sub(min_balance)
-                .ok_or(EphemeralVaultError::MathOverflow)?;
-            let reward = available.min(MAX_CLEANUP_REWARD_LAMPORTS);
-            let to_parent = available
-                .checked_sub(reward)
-                .ok_or(EphemeralVaultError::MathOverflow)?;
+    /// CHECK: Anyone can trigger cleanup and receive a small reward.
+    #[account(mut, signer)]
+    pub cleaner: AccountInfo<'info>,
 
-            **vault_info.try_borrow_mut_lamports()? -= available;
-            **cleaner_info.try_borrow_mut_lamports()? += reward;
-            **parent_info.try_borrow_mut_lamports()? += to_parent;
+    /// CHECK: Only used for has_one constraint.
+    pub parent_wallet: UncheckedAccount<'info>,
 
-            emit!(VaultCleaned {
-                vault: vault.key(),
-                parent: parent.key(),
-                cleaner: cleaner.key(),
-                reward,
-            });
-        }
+    /// The vault's delegation, closed to `parent` so its rent is not stranded. Omit it for a
+    /// vault that never approved a delegate; further delegations stay open.
+    #[account(
+        mut,
+        close = parent,
+        constraint = delegation.vault == vault.key() @ EphemeralVaultError::InvalidDelegationAccount,
+    )]
+    pub delegation: Option<Account<'info, VaultDelegation>>,
 
-        Ok(())
-    }
+    /// CHECK: Receives the residual balance; must be the vault's configured recipient.
+    #[account(
+        mut,
+        address = vault.refund_recipient @ EphemeralVaultError::InvalidRefundRecipient,
+    )]
+    pub refund_recipient: UncheckedAccount<'info>,
 }
 
-fn ensure_vault_active_and_not_expired(vault: &EphemeralVault) -> Result<()> {
-    require!(vault.is_active, EphemeralVaultError::VaultInactive);
-    let clock = Clock::get()?;
-    require!(
-        clock.unix_timestamp <= vault.session_expiry,
-        EphemeralVaultError::SessionExpired
-    );
-    Ok(())
-}
+#[derive(Accounts)]
+pub struct SettleVault<'info> {
+    #[account(mut)]
+    pub vault: Account<'info, EphemeralVault>,
 
-fn ensure_vault_not_already_inactive(vault: &EphemeralVault) -> Result<()> {
-    require!(vault.is_active, EphemeralVaultError::VaultInactive);
-    Ok(())
+    /// CHECK: Anyone can settle an expired vault and receive a small reward.
+    #[account(mut, signer)]
+    pub cleaner: AccountInfo<'info>,
+
+    /// CHECK: Receives the residual balance; must be the vault's configured recipient.
+    #[account(
+        mut,
+        address = vault.refund_recipient @ EphemeralVaultError::InvalidRefundRecipient,
+    )]
+    pub refund_recipient: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CreateVault<'info> {
+pub struct CreateTokenVault<'info> {
     #[account(mut)]
     pub parent: Signer<'info>,
 
@@ -269,42 +2359,43 @@ pub struct CreateVault<'info> {
     )]
     pub vault: Account<'info, EphemeralVault>,
 
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct ApproveDelegate<'info> {
-    #[account(mut, has_one = parent_wallet)]
-    pub vault: Account<'info, EphemeralVault>,
-
-    #[account(mut)]
-    pub parent: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
 
     #[account(
         init,
         payer = parent,
-        space = 8 + VaultDelegation::LEN,
-        seeds = [b"delegation", vault.key().as_ref()],
-        bump,
+        associated_token::mint = token_mint,
+        associated_token::authority = vault,
     )]
-    pub delegation: Account<'info, VaultDelegation>,
+    pub vault_token_account: Account<'info, TokenAccount>,
 
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct AutoDeposit<'info> {
+pub struct AutoDepositToken<'info> {
     #[account(mut, has_one = parent_wallet)]
     pub vault: Account<'info, EphemeralVault>,
 
-    #[account(mut)]
-    pub parent: Signer<'info>,
+    pub parent_wallet: Signer<'info>,
 
-    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        token::mint = vault.token_mint,
+        token::authority = parent_wallet,
+    )]
+    pub parent_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, address = vault.token_account @ EphemeralVaultError::NotTokenVault)]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct ExecuteTrade<'info> {
+pub struct ExecuteTokenTrade<'info> {
     #[account(mut, has_one = parent_wallet)]
     pub vault: Account<'info, EphemeralVault>,
 
@@ -312,54 +2403,42 @@ pub struct ExecuteTrade<'info> {
     #[account(signer)]
     pub ephemeral: AccountInfo<'info>,
 
+    /// Bump recomputed as in `ExecuteTrade`.
     #[account(
         mut,
-        seeds = [b"delegation", vault.key().as_ref()],
-        bump = delegation.bump,
+        seeds = [b"delegation", vault.key().as_ref(), ephemeral.key().as_ref()],
+        bump,
     )]
     pub delegation: Account<'info, VaultDelegation>,
 
-    /// Parent wallet is stored for has_one checks but does not need to sign here.
     /// CHECK: Only used for has_one relationship; actual authority for executing trades is the ephemeral wallet.
     pub parent_wallet: UncheckedAccount<'info>,
+
+    /// CHECK: The DEX program the trade routes through; must be in `vault.allowed_programs`.
+    #[account(executable)]
+    pub target_program: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct RevokeAccess<'info> {
+pub struct CloseTokenAccount<'info> {
     #[account(mut, has_one = parent_wallet)]
     pub vault: Account<'info, EphemeralVault>,
 
-    #[account(mut, signer)]
-    pub parent: AccountInfo<'info>,
+    /// CHECK: Receives the token account's rent; only used for the has_one constraint.
+    #[account(mut)]
+    pub parent_wallet: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        seeds = [b"delegation", vault.key().as_ref()],
-        bump = delegation.bump,
+        token::mint = vault.token_mint,
+        token::authority = parent_wallet,
     )]
-    pub delegation: Account<'info, VaultDelegation>,
-
-    pub system_program: Program<'info, System>,
-
-    /// CHECK: Only used for has_one constraint.
-    pub parent_wallet: UncheckedAccount<'info>,
-}
-
-#[derive(Accounts)]
-pub struct CleanupVault<'info> {
-    #[account(mut, has_one = parent_wallet, close = parent)]
-    pub vault: Account<'info, EphemeralVault>,
+    pub parent_token_account: Account<'info, TokenAccount>,
 
-    /// CHECK: Parent wallet receives reclaimed rent and remaining funds.
-    #[account(mut)]
-    pub parent: AccountInfo<'info>,
-
-    /// CHECK: Anyone can trigger cleanup and receive a small reward.
-    #[account(mut, signer)]
-    pub cleaner: AccountInfo<'info>,
+    #[account(mut, address = vault.token_account @ EphemeralVaultError::NotTokenVault)]
+    pub vault_token_account: Account<'info, TokenAccount>,
 
-    /// CHECK: Only used for has_one constraint.
-    pub parent_wallet: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[account]
@@ -372,11 +2451,94 @@ pub struct EphemeralVault {
     pub total_deposited: u64,
     pub total_spent: u64,
     pub max_deposit: u64,
+    pub created_slot: u64,
+    // Risk policy; zero disables the corresponding check.
+    pub per_trade_limit: u64,
+    pub daily_limit: u64,
+    pub idle_timeout: i64,
+    pub max_velocity: u64,
+    // Rolling state backing the risk policy.
+    pub daily_window_start: i64,
+    pub daily_spent: u64,
+    pub velocity_window_start: i64,
+    pub velocity_window_trades: u64,
+    pub last_activity: i64,
+    // Cumulative amount the delegate may raise `max_deposit` by without the parent.
+    pub parent_topup_allowance: u64,
+    pub topped_up_by_delegate: u64,
+    /// Auto-revoke after the first successful trade.
+    pub single_use: bool,
+    /// Trades still allowed within `grace_period` seconds after `session_expiry`.
+    pub grace_trades: u8,
+    pub grace_period: i64,
+    /// Sequence number of the latest vault-mutating event.
+    pub event_seq: u64,
+    /// Highest `event_seq` an external consumer has acknowledged via `ack_seq`.
+    pub last_acked_seq: u64,
+    /// Slot-based session end, checked alongside `session_expiry`; zero disables it.
+    pub expiry_slot: u64,
+    /// Hash of the one trade the delegate may execute next, if the parent set one.
+    pub preauthorized_trade: Option<[u8; 32]>,
+    /// Ephemeral wallets that may be approved as delegates; slot 0 is `ephemeral_wallet`
+    /// and unused slots hold `Pubkey::default()`.
+    pub allowed_delegates: [Pubkey; MAX_DELEGATES],
+    /// Whether `cleanup_vault` pays its caller a reward; defaults to `true`.
+    pub cleaner_reward_enabled: bool,
+    /// Successful trades so far, and when the latest one executed.
+    pub trade_count: u64,
+    pub last_trade_ts: i64,
+    /// Minimum seconds between trades; zero disables the cooldown.
+    pub min_trade_interval_secs: i64,
+    /// SPL mint a token vault holds; `Pubkey::default()` for a native SOL vault.
+    pub token_mint: Pubkey,
+    /// The vault's associated token account, reset to `Pubkey::default()` once
+    /// `close_token_account` has closed it.
+    pub token_account: Pubkey,
+    /// Token-vault counterparts of `max_deposit`, `total_deposited` and `total_spent`, in
+    /// the mint's base units.
+    pub max_token_deposit: u64,
+    pub token_deposited: u64,
+    pub token_spent: u64,
+    /// SOL trades counted by fee size, bucketed by `TRADE_HISTOGRAM_BOUNDS`.
+    pub trade_histogram: [u64; TRADE_HISTOGRAM_BUCKETS],
+    /// DEX programs trades may target; unused slots hold `Pubkey::default()`. With none
+    /// set, every trade is rejected.
+    pub allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+    /// Receives the residual balance on `revoke_access` and `cleanup_vault`; starts as the
+    /// parent. Rent of closed accounts still returns to the parent, who paid it.
+    pub refund_recipient: Pubkey,
+    /// The ephemeral wallet the vault PDA was derived from. Equal to `ephemeral_wallet`
+    /// until `rotate_ephemeral`, after which only this one reproduces the PDA seeds.
+    pub seed_ephemeral_wallet: Pubkey,
+    /// Set by `settle_vault`: funds were swept but the account was kept open. Never cleared.
+    pub is_settled: bool,
+    /// Balance a cleaner must hold to be paid the cleanup reward; starts at
+    /// `MIN_CLEANER_BALANCE_LAMPORTS`.
+    pub min_cleaner_balance: u64,
     pub bump: u8,
 }
 
 impl EphemeralVault {
-    pub const LEN: usize = 32 + 32 + 8 + 8 + 1 + 8 + 8 + 8 + 1;
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 1 + 8 + 8 + 8 + 8
+        + 8 + 8 + 8 + 8 // risk policy
+        + 8 + 8 + 8 + 8 + 8 // risk state
+        + 8 + 8 // delegate top-up
+        + 1 // single_use
+        + 1 + 8 // grace_trades, grace_period
+        + 8 + 8 // event_seq, last_acked_seq
+        + 8 // expiry_slot
+        + 1 + 32 // preauthorized_trade
+        + 32 * MAX_DELEGATES // allowed_delegates
+        + 1 // cleaner_reward_enabled
+        + 8 + 8 + 8 // trade cooldown
+        + 32 + 32 + 8 + 8 + 8 // token vault
+        + 8 * TRADE_HISTOGRAM_BUCKETS // trade_histogram
+        + 32 * MAX_ALLOWED_PROGRAMS // allowed_programs
+        + 32 // refund_recipient
+        + 32 // seed_ephemeral_wallet
+        + 1 // is_settled
+        + 8 // min_cleaner_balance
+        + 1;
 }
 
 #[account]
@@ -385,59 +2547,343 @@ pub struct VaultDelegation {
     pub delegate: Pubkey,
     pub approved_at: i64,
     pub revoked_at: Option<i64>,
+    /// Fees paid by trades executed under this delegation.
+    pub spent: u64,
+    /// Last slot in which the delegate may trade; zero for no slot bound.
+    pub expiry_slot: u64,
+    /// Timestamp after which the delegate may no longer trade, from `delegation_ttl_secs`;
+    /// zero inherits the session expiry.
+    pub expires_at: i64,
+    /// Parent-chosen label for explorers, UTF-8 padded with trailing zeros.
+    pub purpose: [u8; DELEGATION_PURPOSE_LEN],
+    /// Times this delegation has been approved; above 1 means it was re-approved.
+    pub approval_count: u32,
     pub bump: u8,
 }
 
 impl VaultDelegation {
-    // 32 (vault) + 32 (delegate) + 8 (approved_at) + 1 + 8 (Option<i64>) + 1 (bump)
-    pub const LEN: usize = 32 + 32 + 8 + 1 + 8 + 1;
+    // 32 (vault) + 32 (delegate) + 8 (approved_at) + 1 + 8 (Option<i64>) + 8 (spent)
+    // + 8 (expiry_slot) + 8 (expires_at) + 16 (purpose) + 4 (approval_count) + 1 (bump)
+    pub const LEN: usize = 32 + 32 + 8 + 1 + 8 + 8 + 8 + 8 + DELEGATION_PURPOSE_LEN + 4 + 1;
+}
+
+/// One recipient of `revoke_and_distribute` and its share in basis points.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct DistributionShare {
+    pub recipient: Pubkey,
+    pub bps: u16,
 }
 
 #[event]
 pub struct VaultCreated {
+    pub seq: u64,
     pub parent: Pubkey,
     pub vault: Pubkey,
     pub ephemeral_wallet: Pubkey,
     pub max_deposit: u64,
     pub session_start: i64,
     pub session_expiry: i64,
+    /// Slot of creation; a strict ordering key when several vaults share a timestamp.
+    pub created_slot: u64,
 }
 
 #[event]
 pub struct DelegateApproved {
+    pub seq: u64,
     pub vault: Pubkey,
     pub delegate: Pubkey,
     pub approved_at: i64,
+    pub expiry_slot: u64,
+    pub expires_at: i64,
+    pub purpose: [u8; DELEGATION_PURPOSE_LEN],
+    pub approval_count: u32,
+    pub reapproved: bool,
 }
 
 #[event]
 pub struct AutoDeposit {
+    pub seq: u64,
     pub vault: Pubkey,
     pub amount: u64,
     pub total_deposited: u64,
 }
 
+#[event]
+pub struct DepositCheck {
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub would_fit: bool,
+    pub remaining_capacity: u64,
+}
+
+#[event]
+pub struct MaxDepositRaised {
+    pub seq: u64,
+    pub vault: Pubkey,
+    /// Parent or delegate that signed the top-up.
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub max_deposit: u64,
+    pub topped_up_by_delegate: u64,
+}
+
 #[event]
 pub struct TradeExecuted {
+    pub seq: u64,
     pub vault: Pubkey,
     pub delegate: Pubkey,
+    /// DEX program or market the trade targeted.
+    pub venue: Pubkey,
     pub fee_paid: u64,
     pub total_spent: u64,
+    /// The vault's cap at the time of the trade (zero: none), so monitors can alert on
+    /// trades approaching it.
+    pub per_trade_limit: u64,
+    /// Successful trades on the vault, this one included.
+    pub trade_count: u64,
+    pub last_trade_ts: i64,
+}
+
+#[event]
+pub struct RiskParamsUpdated {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub per_trade_limit: u64,
+    pub daily_limit: u64,
+    pub idle_timeout: i64,
+    pub max_velocity: u64,
+}
+
+#[event]
+pub struct VaultSummary {
+    pub vault: Pubkey,
+    pub is_active: bool,
+    pub session_expiry: i64,
+    pub total_deposited: u64,
+    pub total_spent: u64,
+    /// `total_deposited - total_spent`.
+    pub accounted_available: u64,
+    /// Real lamports held above the rent-exempt minimum.
+    pub real_available: u64,
+    /// `real_available - accounted_available`; nonzero means accounting drift.
+    pub delta: i64,
+}
+
+#[event]
+pub struct DepositSnapshot {
+    pub vault: Pubkey,
+    pub total_deposited: u64,
+    pub total_spent: u64,
+    /// `total_deposited - total_spent`.
+    pub available: u64,
+    pub as_of_slot: u64,
+    /// `event_seq` at snapshot time; events with a higher `seq` are not yet reflected.
+    pub as_of_seq: u64,
+}
+
+#[event]
+pub struct DelegationStatus {
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub valid: bool,
+    pub revoked_at: Option<i64>,
+    /// The session, the delegation's `expiry_slot` or its `expires_at` has passed.
+    pub expired: bool,
+}
+
+#[event]
+pub struct DelegationsOverview {
+    pub vault: Pubkey,
+    pub count: u16,
+    /// Delegations not yet revoked. Vault activity and session expiry are not considered.
+    pub active: u16,
+    pub revoked: u16,
+    /// Sum of `VaultDelegation.spent` over the passed delegations.
+    pub total_spent: u64,
 }
 
 #[event]
 pub struct AccessRevoked {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub parent: Pubkey,
+    pub revoked_at: i64,
+}
+
+#[event]
+pub struct EmergencyRevoked {
+    pub seq: u64,
     pub vault: Pubkey,
     pub parent: Pubkey,
+    pub delegate: Pubkey,
+    pub refund_recipient: Pubkey,
+    /// Lamports moved to `refund_recipient` by this call.
+    pub swept: u64,
+    /// First revocation time; kept if the delegation was already revoked.
+    pub revoked_at: i64,
+}
+
+#[event]
+pub struct FundsDistributed {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub parent: Pubkey,
+    pub total: u64,
+    pub recipients: u8,
+}
+
+#[event]
+pub struct TradePreauthorized {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub trade_hash: [u8; 32],
+}
+
+#[event]
+pub struct DelegateAllowed {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct DelegateRevoked {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
     pub revoked_at: i64,
 }
 
+#[event]
+pub struct EphemeralRotated {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub old_ephemeral: Pubkey,
+    pub new_ephemeral: Pubkey,
+}
+
+#[event]
+pub struct ExcessWithdrawn {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub parent: Pubkey,
+    pub amount: u64,
+    /// Still withdrawable after this withdrawal.
+    pub remaining_excess: u64,
+}
+
+#[event]
+pub struct MaxDepositIncreased {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub old_max_deposit: u64,
+    pub new_max_deposit: u64,
+}
+
+#[event]
+pub struct CleanerRewardToggled {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct MinCleanerBalanceSet {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct RefundRecipientSet {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub recipient: Pubkey,
+}
+
+#[event]
+pub struct SessionExtended {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub old_expiry: i64,
+    pub new_expiry: i64,
+}
+
+#[event]
+pub struct SeqAcked {
+    pub vault: Pubkey,
+    pub last_acked_seq: u64,
+    pub event_seq: u64,
+}
+
 #[event]
 pub struct VaultCleaned {
+    pub seq: u64,
     pub vault: Pubkey,
     pub parent: Pubkey,
     pub cleaner: Pubkey,
     pub reward: u64,
+    pub refund_recipient: Pubkey,
+    /// Residual balance, net of `reward`, sent to `refund_recipient`.
+    pub refunded: u64,
+    /// Rent returned to the parent by closing the delegation; zero if none was passed.
+    pub delegation_rent_reclaimed: u64,
+}
+
+#[event]
+pub struct VaultSettled {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub cleaner: Pubkey,
+    pub reward: u64,
+    pub refund_recipient: Pubkey,
+    /// Residual balance, net of `reward`, sent to `refund_recipient`.
+    pub refunded: u64,
+}
+
+#[event]
+pub struct TradeHistogram {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub bounds: [u64; TRADE_HISTOGRAM_BUCKETS - 1],
+    pub counts: [u64; TRADE_HISTOGRAM_BUCKETS],
+}
+
+#[event]
+pub struct TokenVaultCreated {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub max_token_deposit: u64,
+}
+
+#[event]
+pub struct TokenDeposit {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub token_deposited: u64,
+}
+
+#[event]
+pub struct TokenTradeExecuted {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub venue: Pubkey,
+    pub amount: u64,
+    pub token_spent: u64,
+    pub trade_count: u64,
+}
+
+#[event]
+pub struct TokenAccountClosed {
+    pub seq: u64,
+    pub vault: Pubkey,
+    pub token_account: Pubkey,
+    /// Tokens sent back to the parent's token account before closing.
+    pub returned: u64,
 }
 
 #[error_code]
@@ -460,4 +2906,141 @@ pub enum EphemeralVaultError {
     OverDeposit,
     #[msg("Insufficient vault balance for requested fee")] 
     InsufficientVaultBalance,
-}
\ No newline at end of file
+    #[msg("Invalid risk parameters")] 
+    InvalidRiskParams,
+    #[msg("Trade exceeds the per-trade limit")] 
+    PerTradeLimitExceeded,
+    #[msg("Trade exceeds the daily spending limit")] 
+    DailyLimitExceeded,
+    #[msg("Session idle for longer than idle_timeout")] 
+    SessionIdle,
+    #[msg("Trade velocity limit exceeded")] 
+    VelocityExceeded,
+    #[msg("Vault lamports do not cover its accounted balance")] 
+    InsolventVault,
+    #[msg("PDA bump not found for the named seed")] 
+    BumpNotFound,
+    #[msg("Session expiry must be after session start")] 
+    InvalidDuration,
+    #[msg("Delegate top-up exceeds the parent-set allowance")] 
+    TopUpAllowanceExceeded,
+    #[msg("Distribution shares must name 1-8 writable recipients summing to 10000 bps")] 
+    InvalidDistribution,
+    #[msg("Acked sequence is behind the last ack or ahead of the latest event")] 
+    SeqOutOfRange,
+    #[msg("max_deposit would exceed the program-wide ABSOLUTE_MAX_DEPOSIT")] 
+    ExceedsProgramLimit,
+    #[msg("Delegation is past its expiry slot")] 
+    DelegateExpired,
+    #[msg("Trade does not match the parent's preauthorized trade hash")] 
+    TradeNotPreauthorized,
+    #[msg("Session would run longer than MAX_SESSION_DURATION")] 
+    SessionTooLong,
+    #[msg("Vault already allows MAX_DELEGATES ephemeral wallets")] 
+    DelegateListFull,
+    #[msg("Withdrawal would dip into the vault's accounted balance or rent reserve")] 
+    ExceedsWithdrawableExcess,
+    #[msg("New max_deposit must be above the current one")] 
+    MaxDepositNotIncreased,
+    #[msg("Ephemeral wallet must be a signing keypair, not an account of this program")] 
+    InvalidEphemeralWallet,
+    #[msg("Trade submitted before min_trade_interval_secs elapsed since the last one")] 
+    TradeCooldown,
+    #[msg("min_output is not supported until the DEX CPI lands; pass zero")] 
+    MinOutputUnsupported,
+    #[msg("session_duration must be positive and at most MAX_SESSION_DURATION")] 
+    InvalidSessionDuration,
+    #[msg("Instruction requires a token vault with an open token account")] 
+    NotTokenVault,
+    #[msg("Token vault's token account must be closed with close_token_account first")] 
+    TokenAccountOpen,
+    #[msg("Delegation is past its expires_at; the session itself may still be live")] 
+    DelegationExpired,
+    #[msg("Trade targets a program outside the vault's allowed_programs")] 
+    InvalidTargetProgram,
+    #[msg("Delegation purpose must be UTF-8 followed only by zero padding")] 
+    InvalidPurpose,
+    #[msg("Refund recipient does not match the vault's configured recipient")] 
+    InvalidRefundRecipient,
+    #[msg("Vault has already been settled")] 
+    VaultSettled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vault with every field zeroed: inactive, nothing deposited.
+    fn zeroed_vault() -> EphemeralVault {
+        let bytes = vec![0u8; EphemeralVault::LEN];
+        EphemeralVault::deserialize(&mut bytes.as_slice()).unwrap()
+    }
+
+    /// A writable, non-signer account owned by this program.
+    fn program_account<'a>(
+        key: &'a Pubkey,
+        lamports: &'a mut u64,
+        data: &'a mut [u8],
+    ) -> AccountInfo<'a> {
+        AccountInfo::new(key, false, true, lamports, data, &ID, false, 0)
+    }
+
+    fn check(vault: &EphemeralVault, lamports: u64) -> Result<()> {
+        let key = Pubkey::new_unique();
+        let mut lamports = lamports;
+        let mut data = vec![0u8; 8 + EphemeralVault::LEN];
+        let info = program_account(&key, &mut lamports, &mut data);
+        check_solvent(&info, vault, &Rent::default())
+    }
+
+    fn rent_minimum() -> u64 {
+        Rent::default().minimum_balance(8 + EphemeralVault::LEN)
+    }
+
+    #[test]
+    fn vault_holding_less_than_its_accounted_balance_is_insolvent() {
+        let vault = EphemeralVault {
+            is_active: true,
+            total_deposited: 1_000_000,
+            total_spent: 250_000,
+            ..zeroed_vault()
+        };
+
+        let err = check(&vault, rent_minimum() + 749_999).unwrap_err();
+        assert_eq!(err, EphemeralVaultError::InsolventVault.into());
+        assert!(check(&vault, rent_minimum() + 750_000).is_ok());
+    }
+
+    #[test]
+    fn swept_vault_must_keep_its_rent_minimum() {
+        let vault = EphemeralVault {
+            total_deposited: 1_000_000,
+            ..zeroed_vault()
+        };
+
+        assert!(check(&vault, rent_minimum()).is_ok());
+        let err = check(&vault, rent_minimum() - 1).unwrap_err();
+        assert_eq!(err, EphemeralVaultError::InsolventVault.into());
+    }
+
+    #[test]
+    fn moving_lamports_is_checked_both_ways() {
+        let keys = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let (mut from_lamports, mut to_lamports) = (1_000u64, u64::MAX - 10);
+        let (mut from_data, mut to_data) = (Vec::new(), Vec::new());
+        let from = program_account(&keys[0], &mut from_lamports, &mut from_data);
+        let to = program_account(&keys[1], &mut to_lamports, &mut to_data);
+
+        let overflow: Error = EphemeralVaultError::MathOverflow.into();
+        assert_eq!(move_lamports(&from, &to, 1_001).unwrap_err(), overflow);
+        assert_eq!(move_lamports(&from, &to, 11).unwrap_err(), overflow);
+
+        **from.try_borrow_mut_lamports().unwrap() = 1_000;
+        **to.try_borrow_mut_lamports().unwrap() = 0;
+        move_lamports(&from, &to, 400).unwrap();
+        assert_eq!((from.lamports(), to.lamports()), (600, 400));
+        move_lamports(&from, &from, 600).unwrap();
+        assert_eq!(from.lamports(), 600);
+    }
+}
+
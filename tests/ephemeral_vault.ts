@@ -1,7 +1,17 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { EphemeralVault } from "../target/types/ephemeral_vault";
-import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import { PublicKey, Keypair, SystemProgram, Transaction } from "@solana/web3.js";
+import { createHash } from "crypto";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccount,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  mintTo,
+} from "@solana/spl-token";
 
 // Basic Anchor test skeleton to demonstrate create_vault + approve_delegate flow.
 
@@ -12,24 +22,73 @@ describe("ephemeral_vault", () => {
 
   const program = anchor.workspace.EphemeralVault as Program<EphemeralVault>;
 
-  it("can create a vault and approve delegate", async () => {
-    const parent = Keypair.generate();
-    const ephemeral = Keypair.generate();
-
-    // Airdrop some SOL to parent
-    const sig = await provider.connection.requestAirdrop(parent.publicKey, 1_000_000_000);
+  const airdrop = async (to: PublicKey, lamports = 1_000_000_000) => {
+    const sig = await provider.connection.requestAirdrop(to, lamports);
     await provider.connection.confirmTransaction(sig);
+  };
 
-    const [vaultPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("vault"), parent.publicKey.toBuffer(), ephemeral.publicKey.toBuffer()],
+  const findVaultPda = (parent: PublicKey, ephemeral: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), parent.toBuffer(), ephemeral.toBuffer()],
       program.programId
-    );
+    )[0];
+
+  const findDelegationPda = (vault: PublicKey, delegate: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("delegation"), vault.toBuffer(), delegate.toBuffer()],
+      program.programId
+    )[0];
 
-    const sessionDuration = new anchor.BN(3600);
-    const maxDeposit = new anchor.BN(500_000_000);
+  // Delegation of the vault's own ephemeral wallet, for helpers that only know the vault.
+  const primaryDelegationPda = async (vault: PublicKey) =>
+    findDelegationPda(vault, (await program.account.ephemeralVault.fetch(vault)).ephemeralWallet);
 
+  const VENUE = Keypair.generate().publicKey;
+  // Any deployed program stands in for a whitelisted DEX; the trade CPI is a placeholder.
+  const DEX_PROGRAM = TOKEN_PROGRAM_ID;
+  // `purpose` labels are 16 bytes of UTF-8, zero padded.
+  const purpose = (label: string) => {
+    const bytes = Buffer.alloc(16);
+    Buffer.from(label, "utf8").copy(bytes);
+    return [...bytes];
+  };
+  const allowedPrograms = (programs: PublicKey[]) => [
+    ...programs,
+    ...Array(4 - programs.length).fill(PublicKey.default),
+  ];
+
+  const U64_UNCHANGED = new anchor.BN("18446744073709551615");
+  const I64_UNCHANGED = new anchor.BN("-9223372036854775808");
+
+  const createVault = async (
+    parent: Keypair,
+    ephemeral: Keypair,
+    sessionDuration = 3600,
+    maxDeposit = 500_000_000,
+    topupAllowance = 0,
+    singleUse = false,
+    graceTrades = 0,
+    gracePeriod = 0,
+    slotTimeMs = 0,
+    perTradeLimit = 0,
+    minTradeIntervalSecs = 0,
+    dexPrograms = [DEX_PROGRAM]
+  ) => {
+    const vaultPda = findVaultPda(parent.publicKey, ephemeral.publicKey);
     await program.methods
-      .createVault(sessionDuration, maxDeposit, ephemeral.publicKey)
+      .createVault(
+        new anchor.BN(sessionDuration),
+        new anchor.BN(maxDeposit),
+        ephemeral.publicKey,
+        new anchor.BN(topupAllowance),
+        singleUse,
+        graceTrades,
+        new anchor.BN(gracePeriod),
+        slotTimeMs,
+        new anchor.BN(perTradeLimit),
+        new anchor.BN(minTradeIntervalSecs),
+        allowedPrograms(dexPrograms)
+      )
       .accounts({
         parent: parent.publicKey,
         ephemeralWallet: ephemeral.publicKey,
@@ -38,14 +97,25 @@ describe("ephemeral_vault", () => {
       })
       .signers([parent])
       .rpc();
+    return vaultPda;
+  };
 
-    const [delegationPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("delegation"), vaultPda.toBuffer()],
-      program.programId
-    );
-
+  const approveDelegate = async (
+    parent: Keypair,
+    vaultPda: PublicKey,
+    delegate: PublicKey,
+    expirySlot = 0,
+    ttlSecs = 0,
+    purposeLabel = ""
+  ) => {
+    const delegationPda = findDelegationPda(vaultPda, delegate);
     await program.methods
-      .approveDelegate(ephemeral.publicKey)
+      .approveDelegate(
+        delegate,
+        new anchor.BN(expirySlot),
+        new anchor.BN(ttlSecs),
+        purpose(purposeLabel)
+      )
       .accounts({
         vault: vaultPda,
         parent: parent.publicKey,
@@ -54,9 +124,1855 @@ describe("ephemeral_vault", () => {
       })
       .signers([parent])
       .rpc();
+    return delegationPda;
+  };
+
+  const deposit = async (parent: Keypair, vaultPda: PublicKey, amount: number) =>
+    program.methods
+      .autoDepositForTrade(new anchor.BN(amount))
+      .accounts({
+        vault: vaultPda,
+        parent: parent.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([parent])
+      .rpc();
+
+  const trade = async (
+    parent: Keypair,
+    ephemeral: Keypair,
+    vaultPda: PublicKey,
+    fee: number,
+    venue = VENUE
+  ) =>
+    program.methods
+      .executeTrade(new anchor.BN(fee), venue, new anchor.BN(0))
+      .accounts({
+        vault: vaultPda,
+        ephemeral: ephemeral.publicKey,
+        delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        parentWallet: parent.publicKey,
+        tradeOutput: null,
+        targetProgram: DEX_PROGRAM,
+      })
+      .signers([ephemeral])
+      .rpc();
+
+  const revoke = async (
+    parent: Keypair,
+    vaultPda: PublicKey,
+    refundRecipient = parent.publicKey
+  ) =>
+    program.methods
+      .revokeAccess()
+      .accounts({
+        vault: vaultPda,
+        parent: parent.publicKey,
+        delegation: await primaryDelegationPda(vaultPda),
+        systemProgram: SystemProgram.programId,
+        parentWallet: parent.publicKey,
+        refundRecipient,
+      })
+      .signers([parent])
+      .rpc();
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  it("can create a vault and approve delegate", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
 
     const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
     expect(vaultAccount.isActive).toBe(true);
     expect(vaultAccount.maxDeposit.toNumber()).toBe(500_000_000);
   });
+
+  it("stores the canonical bumps for the vault and delegation PDAs", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+
+    const [, vaultBump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), parent.publicKey.toBuffer(), ephemeral.publicKey.toBuffer()],
+      program.programId
+    );
+    const [, delegationBump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("delegation"), vaultPda.toBuffer(), ephemeral.publicKey.toBuffer()],
+      program.programId
+    );
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).bump).toBe(vaultBump);
+    expect((await program.account.vaultDelegation.fetch(delegationPda)).bump).toBe(delegationBump);
+  });
+
+  it("records the creation slot on the vault", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    const currentSlot = await provider.connection.getSlot();
+    expect(vaultAccount.createdSlot.toNumber()).toBeGreaterThan(0);
+    expect(vaultAccount.createdSlot.toNumber()).toBeLessThanOrEqual(currentSlot);
+  });
+
+  it("updates a subset of risk params and preserves the rest", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+
+    await program.methods
+      .updateRiskParams(new anchor.BN(1_000), new anchor.BN(50_000), new anchor.BN(600), new anchor.BN(10))
+      .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+
+    await program.methods
+      .updateRiskParams(U64_UNCHANGED, new anchor.BN(75_000), I64_UNCHANGED, U64_UNCHANGED)
+      .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.perTradeLimit.toNumber()).toBe(1_000);
+    expect(vaultAccount.dailyLimit.toNumber()).toBe(75_000);
+    expect(vaultAccount.idleTimeout.toNumber()).toBe(600);
+    expect(vaultAccount.maxVelocity.toNumber()).toBe(10);
+  });
+
+  it("reports accounting drift in the vault summary", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+
+    const before = await program.methods.vaultSummary().accounts({ vault: vaultPda }).simulate();
+    expect(before.events[0].data.delta.toNumber()).toBe(0);
+
+    // A direct transfer bypasses auto_deposit_for_trade and is not accounted for.
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.transfer({ fromPubkey: parent.publicKey, toPubkey: vaultPda, lamports: 12_345 })
+      ),
+      [parent]
+    );
+
+    const after = await program.methods.vaultSummary().accounts({ vault: vaultPda }).simulate();
+    const summary = after.events[0].data;
+    expect(summary.accountedAvailable.toNumber()).toBe(0);
+    expect(summary.realAvailable.toNumber()).toBe(12_345);
+    expect(summary.delta.toNumber()).toBe(12_345);
+  });
+
+  it("emits a deposit snapshot matching the running totals", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    for (const amount of [10_000, 15_000, 25_000]) {
+      await deposit(parent, vaultPda, amount);
+    }
+    await trade(parent, ephemeral, vaultPda, 20_000);
+
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    const snapshot = (
+      await program.methods.emitDepositSnapshot().accounts({ vault: vaultPda }).simulate()
+    ).events[0].data;
+    expect(snapshot.totalDeposited.toNumber()).toBe(vaultAccount.totalDeposited.toNumber());
+    expect(snapshot.totalSpent.toNumber()).toBe(vaultAccount.totalSpent.toNumber());
+    expect(snapshot.available.toNumber()).toBe(50_000 - vaultAccount.totalSpent.toNumber());
+    expect(snapshot.asOfSeq.toNumber()).toBe(vaultAccount.eventSeq.toNumber());
+    expect(snapshot.asOfSlot.toNumber()).toBeGreaterThan(0);
+  });
+
+  it("pre-flights deposits against max_deposit", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600, 100_000);
+
+    const fits = await program.methods
+      .checkDepositCapacity(new anchor.BN(60_000))
+      .accounts({ vault: vaultPda })
+      .simulate();
+    expect(fits.events[0].data.wouldFit).toBe(true);
+    expect(fits.events[0].data.remainingCapacity.toNumber()).toBe(100_000);
+
+    const tooLarge = await program.methods
+      .checkDepositCapacity(new anchor.BN(100_001))
+      .accounts({ vault: vaultPda })
+      .simulate();
+    expect(tooLarge.events[0].data.wouldFit).toBe(false);
+    expect(tooLarge.events[0].data.remainingCapacity.toNumber()).toBe(100_000);
+  });
+
+  it("keeps the vault solvent across deposits and trades", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 50_000);
+    await trade(parent, ephemeral, vaultPda, 20_000);
+
+    const summary = (await program.methods.vaultSummary().accounts({ vault: vaultPda }).simulate())
+      .events[0].data;
+    expect(summary.accountedAvailable.toNumber()).toBe(30_000);
+    expect(summary.realAvailable.toNumber()).toBeGreaterThanOrEqual(30_000);
+  });
+
+  it("deposits and trades atomically, rolling back the deposit on trade failure", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    const depositAndTrade = (amount: number, fee: number) =>
+      program.methods
+        .depositAndTrade(new anchor.BN(amount), new anchor.BN(fee), VENUE)
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          ephemeral: ephemeral.publicKey,
+          delegation: delegationPda,
+          systemProgram: SystemProgram.programId,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([parent, ephemeral]);
+
+    const lamportsBefore = await provider.connection.getBalance(vaultPda);
+    // Fee exceeds the deposit, so the trade half fails and the whole instruction reverts.
+    await expect(depositAndTrade(10_000, 20_000).rpc()).rejects.toThrow(/InsufficientVaultBalance/);
+    let vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.totalDeposited.toNumber()).toBe(0);
+    expect(await provider.connection.getBalance(vaultPda)).toBe(lamportsBefore);
+
+    const simulated = await depositAndTrade(20_000, 15_000).simulate();
+    expect(simulated.events.map((e) => e.name)).toEqual(["AutoDeposit", "TradeExecuted"]);
+    await depositAndTrade(20_000, 15_000).rpc();
+    vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.totalDeposited.toNumber()).toBe(20_000);
+    expect(vaultAccount.totalSpent.toNumber()).toBe(15_000);
+  });
+
+  it("reports delegation status for valid, revoked and expired delegations", async () => {
+    const status = async (vaultPda: PublicKey) =>
+      (
+        await program.methods
+          .isDelegationValid()
+          .accounts({ vault: vaultPda, delegation: await primaryDelegationPda(vaultPda) })
+          .simulate()
+      ).events[0].data;
+
+    const parent = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const validEphemeral = Keypair.generate();
+    const validVault = await createVault(parent, validEphemeral);
+    await approveDelegate(parent, validVault, validEphemeral.publicKey);
+    const valid = await status(validVault);
+    expect(valid.valid).toBe(true);
+    expect(valid.revokedAt).toBeNull();
+    expect(valid.expired).toBe(false);
+
+    const revokedEphemeral = Keypair.generate();
+    const revokedVault = await createVault(parent, revokedEphemeral);
+    await approveDelegate(parent, revokedVault, revokedEphemeral.publicKey);
+    await revoke(parent, revokedVault);
+    const revoked = await status(revokedVault);
+    expect(revoked.valid).toBe(false);
+    expect(revoked.revokedAt).not.toBeNull();
+
+    const expiredEphemeral = Keypair.generate();
+    const expiredVault = await createVault(parent, expiredEphemeral, 1);
+    await approveDelegate(parent, expiredVault, expiredEphemeral.publicKey);
+
+    // The session stays live; only the delegation's own TTL runs out.
+    const ttlEphemeral = Keypair.generate();
+    const ttlVault = await createVault(parent, ttlEphemeral, 3600);
+    await approveDelegate(parent, ttlVault, ttlEphemeral.publicKey, 0, 1);
+
+    await sleep(3_000);
+    const expired = await status(expiredVault);
+    expect(expired.valid).toBe(false);
+    expect(expired.expired).toBe(true);
+    const ttlElapsed = await status(ttlVault);
+    expect(ttlElapsed.valid).toBe(false);
+    expect(ttlElapsed.revokedAt).toBeNull();
+    expect(ttlElapsed.expired).toBe(true);
+  });
+
+  it("records the trade venue in TradeExecuted", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 10_000);
+
+    const simulated = await program.methods
+      .executeTrade(new anchor.BN(5_000), VENUE, new anchor.BN(0))
+      .accounts({
+        vault: vaultPda,
+        ephemeral: ephemeral.publicKey,
+        delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        parentWallet: parent.publicKey,
+        tradeOutput: null,
+        targetProgram: DEX_PROGRAM,
+      })
+      .signers([ephemeral])
+      .simulate();
+    const event = simulated.events.find((e) => e.name === "TradeExecuted");
+    expect(event.data.venue.toBase58()).toBe(VENUE.toBase58());
+  });
+
+  it("rejects a session duration that is not positive or exceeds the maximum", async () => {
+    const parent = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const maxDuration = 7 * 24 * 60 * 60;
+    await expect(createVault(parent, Keypair.generate(), 0)).rejects.toThrow(/InvalidSessionDuration/);
+    await expect(createVault(parent, Keypair.generate(), -60)).rejects.toThrow(/InvalidSessionDuration/);
+    await expect(createVault(parent, Keypair.generate(), maxDuration + 1)).rejects.toThrow(
+      /InvalidSessionDuration/
+    );
+
+    const vaultPda = await createVault(parent, Keypair.generate(), maxDuration);
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.sessionExpiry.toNumber() - vault.sessionStart.toNumber()).toBe(maxDuration);
+  });
+
+  it("lets the delegate raise max_deposit within the parent-set allowance", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600, 100_000, 50_000);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+
+    const delegateTopUp = (amount: number) =>
+      program.methods
+        .delegateTopUpMaxDeposit(new anchor.BN(amount))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        })
+        .signers([ephemeral]);
+
+    await delegateTopUp(30_000).rpc();
+    await delegateTopUp(20_000).rpc();
+
+    let vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.maxDeposit.toNumber()).toBe(150_000);
+    expect(vaultAccount.toppedUpByDelegate.toNumber()).toBe(50_000);
+
+    // The allowance is cumulative: even one more lamport needs the parent.
+    await expect(delegateTopUp(1).rpc()).rejects.toThrow(/TopUpAllowanceExceeded/);
+
+    await program.methods
+      .topUpMaxDeposit(new anchor.BN(25_000))
+      .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+
+    vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.maxDeposit.toNumber()).toBe(175_000);
+    expect(vaultAccount.toppedUpByDelegate.toNumber()).toBe(50_000);
+  });
+
+  it("rejects a single delegate top-up larger than the allowance", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600, 100_000, 10_000);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+
+    await expect(
+      program.methods
+        .delegateTopUpMaxDeposit(new anchor.BN(10_001))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        })
+        .signers([ephemeral])
+        .rpc()
+    ).rejects.toThrow(/TopUpAllowanceExceeded/);
+  });
+
+  const revokeAndDistribute = async (
+    parent: Keypair,
+    vaultPda: PublicKey,
+    shares: { recipient: PublicKey; bps: number }[]
+  ) =>
+    program.methods
+      .revokeAndDistribute(shares)
+      .accounts({
+        vault: vaultPda,
+        parentWallet: parent.publicKey,
+        delegation: await primaryDelegationPda(vaultPda),
+      })
+      .remainingAccounts(
+        shares.map((s) => ({ pubkey: s.recipient, isSigner: false, isWritable: true }))
+      )
+      .signers([parent])
+      .rpc();
+
+  // Shares must leave every fresh recipient above the rent-exempt minimum.
+  const fundedVaultForDistribution = async (amount: number) => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, amount);
+    return { parent, vaultPda };
+  };
+
+  it("splits the remaining balance two ways on revoke", async () => {
+    const { parent, vaultPda } = await fundedVaultForDistribution(300_000_000);
+    const a = Keypair.generate().publicKey;
+    const b = Keypair.generate().publicKey;
+
+    await revokeAndDistribute(parent, vaultPda, [
+      { recipient: a, bps: 7_000 },
+      { recipient: b, bps: 3_000 },
+    ]);
+
+    expect(await provider.connection.getBalance(a)).toBe(210_000_000);
+    expect(await provider.connection.getBalance(b)).toBe(90_000_000);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).isActive).toBe(false);
+  });
+
+  it("splits the remaining balance three ways, giving dust to the last recipient", async () => {
+    const { parent, vaultPda } = await fundedVaultForDistribution(300_000_000);
+    const recipients = [0, 1, 2].map(() => Keypair.generate().publicKey);
+
+    await revokeAndDistribute(parent, vaultPda, [
+      { recipient: recipients[0], bps: 3_333 },
+      { recipient: recipients[1], bps: 3_333 },
+      { recipient: recipients[2], bps: 3_334 },
+    ]);
+
+    const balances = await Promise.all(recipients.map((r) => provider.connection.getBalance(r)));
+    expect(balances).toEqual([99_990_000, 99_990_000, 100_020_000]);
+  });
+
+  it("rejects a distribution whose shares do not sum to 10000 bps", async () => {
+    const { parent, vaultPda } = await fundedVaultForDistribution(300_000_000);
+
+    await expect(
+      revokeAndDistribute(parent, vaultPda, [
+        { recipient: Keypair.generate().publicKey, bps: 5_000 },
+        { recipient: Keypair.generate().publicKey, bps: 4_000 },
+      ])
+    ).rejects.toThrow(/InvalidDistribution/);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).isActive).toBe(true);
+  });
+
+  it("auto-revokes a single-use vault after its first trade", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600, 500_000_000, 0, true);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 20_000);
+
+    await trade(parent, ephemeral, vaultPda, 5_000);
+
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.isActive).toBe(false);
+    expect(vaultAccount.totalSpent.toNumber()).toBe(5_000);
+    const delegation = await program.account.vaultDelegation.fetch(
+      findDelegationPda(vaultPda, ephemeral.publicKey)
+    );
+    expect(delegation.revokedAt).not.toBeNull();
+
+    await expect(trade(parent, ephemeral, vaultPda, 5_000)).rejects.toThrow(/VaultInactive/);
+  });
+
+  it("allows grace trades just after expiry until the allowance runs out", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 2, 500_000_000, 0, false, 1, 60);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 20_000);
+    await sleep(3_000);
+
+    await trade(parent, ephemeral, vaultPda, 5_000);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).graceTrades).toBe(0);
+
+    await expect(trade(parent, ephemeral, vaultPda, 5_000)).rejects.toThrow(/SessionExpired/);
+  });
+
+  it("rejects grace trades once the grace period has passed", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 2, 500_000_000, 0, false, 3, 1);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 20_000);
+    await sleep(5_000);
+
+    await expect(trade(parent, ephemeral, vaultPda, 5_000)).rejects.toThrow(/SessionExpired/);
+  });
+
+  it("numbers vault events and acks a processed sequence", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 10_000);
+
+    // VaultCreated, DelegateApproved, AutoDeposit.
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.eventSeq.toNumber()).toBe(3);
+
+    const ack = (seq: number) =>
+      program.methods
+        .ackSeq(new anchor.BN(seq))
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc();
+
+    await ack(2);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).lastAckedSeq.toNumber()).toBe(2);
+
+    await expect(ack(4)).rejects.toThrow(/SeqOutOfRange/);
+    await expect(ack(1)).rejects.toThrow(/SeqOutOfRange/);
+  });
+
+  it("caps max_deposit at the program-wide ceiling", async () => {
+    const ceiling = 100_000_000_000;
+    const parent = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const atCeiling = await createVault(parent, Keypair.generate(), 3600, ceiling);
+    expect((await program.account.ephemeralVault.fetch(atCeiling)).maxDeposit.toNumber()).toBe(ceiling);
+
+    await expect(createVault(parent, Keypair.generate(), 3600, ceiling + 1)).rejects.toThrow(
+      /ExceedsProgramLimit/
+    );
+
+    const belowCeiling = await createVault(parent, Keypair.generate(), 3600, ceiling - 1);
+    const topUp = (amount: number) =>
+      program.methods
+        .topUpMaxDeposit(new anchor.BN(amount))
+        .accounts({ vault: belowCeiling, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc();
+
+    await topUp(1);
+    expect((await program.account.ephemeralVault.fetch(belowCeiling)).maxDeposit.toNumber()).toBe(
+      ceiling
+    );
+    await expect(topUp(1)).rejects.toThrow(/ExceedsProgramLimit/);
+  });
+
+  it("emergency_revoke sweeps the vault and blocks any trade after it", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 50_000);
+
+    const emergencyRevoke = () =>
+      program.methods
+        .emergencyRevoke()
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          refundRecipient: parent.publicKey,
+        })
+        .signers([parent]);
+    const tradeIx = () =>
+      program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram: DEX_PROGRAM,
+        })
+        .instruction();
+
+    // A trade ordered after the revoke in the same batch cannot slip through.
+    await expect(
+      emergencyRevoke()
+        .postInstructions([await tradeIx()])
+        .signers([parent, ephemeral])
+        .rpc()
+    ).rejects.toThrow(/VaultInactive/);
+
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(
+      (await provider.connection.getAccountInfo(vaultPda))!.data.length
+    );
+    await emergencyRevoke().rpc();
+
+    expect(await provider.connection.getBalance(vaultPda)).toBe(rent);
+    const vaultAccount = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vaultAccount.isActive).toBe(false);
+    const delegation = await program.account.vaultDelegation.fetch(
+      findDelegationPda(vaultPda, ephemeral.publicKey)
+    );
+    expect(delegation.revokedAt).not.toBeNull();
+
+    await expect(trade(parent, ephemeral, vaultPda, 1_000)).rejects.toThrow(/VaultInactive/);
+    // Safe to repeat on an already-revoked vault.
+    await emergencyRevoke().rpc();
+  });
+
+  it("aggregates the vault's delegations and rejects foreign or repeated ones", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 50_000);
+    await trade(parent, ephemeral, vaultPda, 5_000);
+    await trade(parent, ephemeral, vaultPda, 7_000);
+
+    const otherEphemeral = Keypair.generate();
+    const otherVault = await createVault(parent, otherEphemeral);
+    const otherDelegation = await approveDelegate(parent, otherVault, otherEphemeral.publicKey);
+
+    const view = (delegations: PublicKey[]) =>
+      program.methods
+        .viewDelegations()
+        .accounts({ vault: vaultPda })
+        .remainingAccounts(
+          delegations.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }))
+        )
+        .simulate();
+
+    const overview = (await view([delegationPda])).events[0].data;
+    expect(overview.count).toBe(1);
+    expect(overview.active).toBe(1);
+    expect(overview.revoked).toBe(0);
+    expect(overview.totalSpent.toNumber()).toBe(12_000);
+
+    await revoke(parent, vaultPda);
+    const afterRevoke = (await view([delegationPda])).events[0].data;
+    expect(afterRevoke.active).toBe(0);
+    expect(afterRevoke.revoked).toBe(1);
+
+    await expect(view([delegationPda, otherDelegation])).rejects.toThrow(/InvalidDelegationAccount/);
+    await expect(view([delegationPda, delegationPda])).rejects.toThrow(/InvalidDelegationAccount/);
+  });
+
+  it("emits a zero-reward VaultCleaned when the vault holds only rent", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 1);
+    const rent = await provider.connection.getMinimumBalanceForRentExemption(
+      (await provider.connection.getAccountInfo(vaultPda))!.data.length
+    );
+    expect(await provider.connection.getBalance(vaultPda)).toBe(rent);
+    await sleep(3_000);
+
+    const cleanup = program.methods
+      .cleanupVault()
+      .accounts({
+        vault: vaultPda,
+        parent: parent.publicKey,
+        cleaner: cleaner.publicKey,
+        parentWallet: parent.publicKey,
+        delegation: null,
+        refundRecipient: parent.publicKey,
+      })
+      .signers([cleaner]);
+
+    const cleaned = (await cleanup.simulate()).events.find((e) => e.name === "VaultCleaned");
+    expect(cleaned).toBeDefined();
+    expect(cleaned!.data.reward.toNumber()).toBe(0);
+
+    await cleanup.rpc();
+    expect(await provider.connection.getAccountInfo(vaultPda)).toBeNull();
+  });
+
+  it("ends the session at whichever of timestamp or slot expiry comes first", async () => {
+    const parent = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    // Slot bound is stricter: a 60s session at an assumed 30s per slot ends after 2 slots,
+    // long before the timestamp expires.
+    const slotBoundEphemeral = Keypair.generate();
+    const slotBound = await createVault(
+      parent, slotBoundEphemeral, 60, 500_000_000, 0, false, 0, 0, 30_000
+    );
+    const slotVault = await program.account.ephemeralVault.fetch(slotBound);
+    expect(slotVault.expirySlot.toNumber()).toBe(slotVault.createdSlot.toNumber() + 2);
+    await approveDelegate(parent, slotBound, slotBoundEphemeral.publicKey);
+    await deposit(parent, slotBound, 10_000);
+    await sleep(3_000);
+    expect(Date.now() / 1000).toBeLessThan(slotVault.sessionExpiry.toNumber());
+    await expect(trade(parent, slotBoundEphemeral, slotBound, 1_000)).rejects.toThrow(
+      /SessionExpired/
+    );
+
+    // Timestamp bound is stricter: at an assumed 1ms per slot the slot bound is far away.
+    const timeBoundEphemeral = Keypair.generate();
+    const timeBound = await createVault(
+      parent, timeBoundEphemeral, 2, 500_000_000, 0, false, 0, 0, 1
+    );
+    await approveDelegate(parent, timeBound, timeBoundEphemeral.publicKey);
+    await deposit(parent, timeBound, 10_000);
+    await sleep(4_000);
+    const timeVault = await program.account.ephemeralVault.fetch(timeBound);
+    expect(await provider.connection.getSlot()).toBeLessThan(timeVault.expirySlot.toNumber());
+    await expect(trade(parent, timeBoundEphemeral, timeBound, 1_000)).rejects.toThrow(
+      /SessionExpired/
+    );
+  });
+
+  it("rejects trades once the delegation passes its expiry slot", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+
+    const expirySlot = (await provider.connection.getSlot()) + 5;
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey, expirySlot);
+    const delegation = await program.account.vaultDelegation.fetch(delegationPda);
+    expect(delegation.expirySlot.toNumber()).toBe(expirySlot);
+
+    await deposit(parent, vaultPda, 10_000);
+    await trade(parent, ephemeral, vaultPda, 1_000);
+
+    while ((await provider.connection.getSlot()) <= expirySlot) {
+      await sleep(400);
+    }
+    await expect(trade(parent, ephemeral, vaultPda, 1_000)).rejects.toThrow(/DelegateExpired/);
+
+    // Zero disables the slot bound; re-approving clears the expired one.
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey, 0);
+    await trade(parent, ephemeral, vaultPda, 1_000);
+  });
+
+  it("lets the delegate execute only the trade the parent preauthorized", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 10_000);
+
+    const tradeHash = (fee: number, venue: PublicKey) =>
+      Array.from(
+        createHash("sha256")
+          .update(new anchor.BN(fee).toArrayLike(Buffer, "le", 8))
+          .update(venue.toBuffer())
+          .digest()
+      );
+    const preauthorize = (hash: number[]) =>
+      program.methods
+        .preauthorizeTrade(hash)
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc();
+
+    await preauthorize(tradeHash(1_000, VENUE));
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).preauthorizedTrade).toEqual(
+      tradeHash(1_000, VENUE)
+    );
+
+    await expect(trade(parent, ephemeral, vaultPda, 2_000)).rejects.toThrow(
+      /TradeNotPreauthorized/
+    );
+    await expect(
+      trade(parent, ephemeral, vaultPda, 1_000, Keypair.generate().publicKey)
+    ).rejects.toThrow(/TradeNotPreauthorized/);
+
+    await trade(parent, ephemeral, vaultPda, 1_000);
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.preauthorizedTrade).toBeNull();
+    expect(vault.totalSpent.toNumber()).toBe(1_000);
+
+    // Consumed: with no preauthorization pending the delegate trades freely again.
+    await trade(parent, ephemeral, vaultPda, 2_000);
+  });
+
+  it("extends an active session up to MAX_SESSION_DURATION from now", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral, 60);
+    const extend = (secs: number | anchor.BN, signer = parent) =>
+      program.methods
+        .extendSession(new anchor.BN(secs))
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([signer])
+        .rpc();
+
+    const before = await program.account.ephemeralVault.fetch(vaultPda);
+    await extend(3_600);
+    const after = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(after.sessionExpiry.toNumber()).toBe(before.sessionExpiry.toNumber() + 3_600);
+    expect(after.eventSeq.toNumber()).toBe(before.eventSeq.toNumber() + 1);
+
+    await expect(extend(0)).rejects.toThrow(/InvalidDuration/);
+    await expect(extend(8 * 24 * 60 * 60)).rejects.toThrow(/SessionTooLong/);
+    await expect(extend(new anchor.BN("9223372036854775807"))).rejects.toThrow(/MathOverflow/);
+    await expect(
+      program.methods
+        .extendSession(new anchor.BN(60))
+        .accounts({ vault: vaultPda, parentWallet: ephemeral.publicKey })
+        .signers([ephemeral])
+        .rpc()
+    ).rejects.toThrow();
+
+    const expiredEphemeral = Keypair.generate();
+    const expiredVault = await createVault(parent, expiredEphemeral, 1);
+    await sleep(2_000);
+    await expect(
+      program.methods
+        .extendSession(new anchor.BN(60))
+        .accounts({ vault: expiredVault, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc()
+    ).rejects.toThrow(/SessionExpired/);
+  });
+
+  it("counts approvals across approve, revoke and re-approve", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    const approveIx = () =>
+      program.methods
+        .approveDelegate(ephemeral.publicKey, new anchor.BN(0), new anchor.BN(0), purpose(""))
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          systemProgram: SystemProgram.programId,
+        });
+
+    const first = (await approveIx().signers([parent]).simulate()).events[0].data;
+    expect(first.approvalCount).toBe(1);
+    expect(first.reapproved).toBe(false);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    expect((await program.account.vaultDelegation.fetch(delegationPda)).approvalCount).toBe(1);
+
+    await revoke(parent, vaultPda);
+
+    const second = (await approveIx().signers([parent]).simulate()).events[0].data;
+    expect(second.approvalCount).toBe(2);
+    expect(second.reapproved).toBe(true);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    const delegation = await program.account.vaultDelegation.fetch(delegationPda);
+    expect(delegation.approvalCount).toBe(2);
+    expect(delegation.revokedAt).toBeNull();
+  });
+
+  it("caps each trade at the per_trade_limit set at creation", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(
+      parent, ephemeral, 3600, 500_000_000, 0, false, 0, 0, 0, 1_000
+    );
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).perTradeLimit.toNumber()).toBe(
+      1_000
+    );
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 10_000);
+
+    await expect(trade(parent, ephemeral, vaultPda, 1_001)).rejects.toThrow(
+      /PerTradeLimitExceeded/
+    );
+
+    const executed = (
+      await program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([ephemeral])
+        .simulate()
+    ).events[0].data;
+    expect(executed.feePaid.toNumber()).toBe(1_000);
+    expect(executed.perTradeLimit.toNumber()).toBe(1_000);
+    await trade(parent, ephemeral, vaultPda, 1_000);
+  });
+
+  it("gives each allowed delegate its own delegation that can be revoked independently", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const second = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+
+    // Not yet on the vault's allowed list.
+    await expect(approveDelegate(parent, vaultPda, second.publicKey)).rejects.toThrow(
+      /InvalidDelegate/
+    );
+
+    const allow = (delegate: PublicKey) =>
+      program.methods
+        .allowDelegate(delegate)
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc();
+    await allow(second.publicKey);
+    const allowed = (await program.account.ephemeralVault.fetch(vaultPda)).allowedDelegates;
+    expect(allowed[0].equals(ephemeral.publicKey)).toBe(true);
+    expect(allowed[1].equals(second.publicKey)).toBe(true);
+
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    const secondDelegation = await approveDelegate(parent, vaultPda, second.publicKey);
+    expect(secondDelegation.equals(findDelegationPda(vaultPda, ephemeral.publicKey))).toBe(false);
+    await deposit(parent, vaultPda, 10_000);
+
+    await trade(parent, ephemeral, vaultPda, 1_000);
+    await trade(parent, second, vaultPda, 1_000);
+
+    await program.methods
+      .revokeDelegate()
+      .accounts({
+        vault: vaultPda,
+        parentWallet: parent.publicKey,
+        delegation: secondDelegation,
+      })
+      .signers([parent])
+      .rpc();
+    await expect(trade(parent, second, vaultPda, 1_000)).rejects.toThrow(/DelegationRevoked/);
+    await trade(parent, ephemeral, vaultPda, 1_000);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).isActive).toBe(true);
+
+    // A delegate cannot trade through another delegate's delegation.
+    await expect(
+      program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: second.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([second])
+        .rpc()
+    ).rejects.toThrow(/ConstraintSeeds/);
+
+    await allow(Keypair.generate().publicKey);
+    await allow(Keypair.generate().publicKey);
+    await expect(allow(Keypair.generate().publicKey)).rejects.toThrow(/DelegateListFull/);
+  });
+
+  it("withdraws lamports beyond the accounted balance without ending the session", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 10_000);
+    // Trade fees are only accounted, so 4_000 of the deposit is now unowed excess.
+    await trade(parent, ephemeral, vaultPda, 4_000);
+
+    const withdraw = (amount: number) =>
+      program.methods
+        .withdrawExcess(new anchor.BN(amount))
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([parent]);
+
+    await expect(withdraw(4_001).rpc()).rejects.toThrow(/ExceedsWithdrawableExcess/);
+
+    const event = (await withdraw(3_000).simulate()).events[0].data;
+    expect(event.amount.toNumber()).toBe(3_000);
+    expect(event.remainingExcess.toNumber()).toBe(1_000);
+
+    const before = await provider.connection.getBalance(vaultPda);
+    await withdraw(4_000).rpc();
+    expect(await provider.connection.getBalance(vaultPda)).toBe(before - 4_000);
+
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.isActive).toBe(true);
+    // The accounted balance was untouched, so the delegate can still spend all of it.
+    await trade(parent, ephemeral, vaultPda, 6_000);
+  });
+
+  it("pays no cleanup reward when the parent disables it", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3);
+    await program.methods
+      .setCleanerRewardEnabled(false)
+      .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).cleanerRewardEnabled).toBe(false);
+
+    await deposit(parent, vaultPda, 50_000);
+    await sleep(4_000);
+
+    const vaultBalance = await provider.connection.getBalance(vaultPda);
+    const parentBefore = await provider.connection.getBalance(parent.publicKey);
+    const cleanerBefore = await provider.connection.getBalance(cleaner.publicKey);
+
+    const cleanup = program.methods
+      .cleanupVault()
+      .accounts({
+        vault: vaultPda,
+        parent: parent.publicKey,
+        cleaner: cleaner.publicKey,
+        parentWallet: parent.publicKey,
+        delegation: null,
+        refundRecipient: parent.publicKey,
+      })
+      .signers([cleaner]);
+
+    const cleaned = (await cleanup.simulate()).events.find((e) => e.name === "VaultCleaned");
+    expect(cleaned!.data.reward.toNumber()).toBe(0);
+
+    await cleanup.rpc();
+    expect(await provider.connection.getBalance(cleaner.publicKey)).toBe(cleanerBefore);
+    expect(await provider.connection.getBalance(parent.publicKey)).toBe(parentBefore + vaultBalance);
+  });
+
+  it("lets the parent raise max_deposit to a new cap but never lower it", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600, 100_000);
+    await deposit(parent, vaultPda, 100_000);
+    await expect(deposit(parent, vaultPda, 1)).rejects.toThrow(/OverDeposit/);
+
+    const increase = (newMax: number, signer = parent) =>
+      program.methods
+        .increaseMaxDeposit(new anchor.BN(newMax))
+        .accounts({ vault: vaultPda, parentWallet: signer.publicKey })
+        .signers([signer]);
+
+    const increased = (await increase(150_000).simulate()).events.find(
+      (e) => e.name === "MaxDepositIncreased"
+    );
+    expect(increased!.data.oldMaxDeposit.toNumber()).toBe(100_000);
+    expect(increased!.data.newMaxDeposit.toNumber()).toBe(150_000);
+    await increase(150_000).rpc();
+
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).maxDeposit.toNumber()).toBe(150_000);
+    await deposit(parent, vaultPda, 50_000);
+
+    await expect(increase(150_000).rpc()).rejects.toThrow(/MaxDepositNotIncreased/);
+    await expect(increase(120_000).rpc()).rejects.toThrow(/MaxDepositNotIncreased/);
+
+    const stranger = Keypair.generate();
+    await airdrop(stranger.publicKey);
+    await expect(increase(200_000, stranger).rpc()).rejects.toThrow();
+  });
+
+  it("rejects an ephemeral wallet that is the parent, the default key or a program account", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const existingVault = await createVault(parent, ephemeral);
+    const existingDelegation = await approveDelegate(parent, existingVault, ephemeral.publicKey);
+
+    const createWithEphemeral = (ephemeralWallet: PublicKey, argWallet = ephemeralWallet) =>
+      program.methods
+        .createVault(
+          new anchor.BN(3600),
+          new anchor.BN(500_000_000),
+          argWallet,
+          new anchor.BN(0),
+          false,
+          0,
+          new anchor.BN(0),
+          0,
+          new anchor.BN(0),
+          new anchor.BN(0),
+          allowedPrograms([DEX_PROGRAM])
+        )
+        .accounts({
+          parent: parent.publicKey,
+          ephemeralWallet,
+          vault: findVaultPda(parent.publicKey, ephemeralWallet),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([parent])
+        .rpc();
+
+    await expect(createWithEphemeral(existingVault)).rejects.toThrow(/InvalidEphemeralWallet/);
+    await expect(createWithEphemeral(parent.publicKey)).rejects.toThrow(/InvalidEphemeralWallet/);
+    await expect(createWithEphemeral(PublicKey.default)).rejects.toThrow(/InvalidEphemeralWallet/);
+    await expect(createWithEphemeral(existingDelegation)).rejects.toThrow(/InvalidEphemeralWallet/);
+
+    // The account seeding the vault PDA must be the wallet the vault records.
+    const mismatched = Keypair.generate().publicKey;
+    await expect(
+      createWithEphemeral(Keypair.generate().publicKey, mismatched)
+    ).rejects.toThrow(/InvalidEphemeralWallet/);
+
+    const normal = Keypair.generate();
+    await createWithEphemeral(normal.publicKey);
+    const vault = await program.account.ephemeralVault.fetch(
+      findVaultPda(parent.publicKey, normal.publicKey)
+    );
+    expect(vault.ephemeralWallet.toBase58()).toBe(normal.publicKey.toBase58());
+  });
+
+  it("enforces the minimum interval between trades and counts them", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(
+      parent, ephemeral, 3600, 500_000_000, 0, false, 0, 0, 0, 0, 2
+    );
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 100_000);
+
+    await trade(parent, ephemeral, vaultPda, 1_000);
+    let vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.tradeCount.toNumber()).toBe(1);
+    const firstTradeTs = vault.lastTradeTs.toNumber();
+    expect(firstTradeTs).toBeGreaterThan(0);
+
+    await expect(trade(parent, ephemeral, vaultPda, 1_000)).rejects.toThrow(/TradeCooldown/);
+
+    await sleep(3_000);
+    const second = program.methods
+      .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+      .accounts({
+        vault: vaultPda,
+        ephemeral: ephemeral.publicKey,
+        delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        parentWallet: parent.publicKey,
+        tradeOutput: null,
+        targetProgram: DEX_PROGRAM,
+      })
+      .signers([ephemeral]);
+    const executed = (await second.simulate()).events.find((e) => e.name === "TradeExecuted");
+    expect(executed!.data.tradeCount.toNumber()).toBe(2);
+    expect(executed!.data.lastTradeTs.toNumber()).toBeGreaterThanOrEqual(firstTradeTs + 2);
+    await second.rpc();
+
+    vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.tradeCount.toNumber()).toBe(2);
+    expect(vault.totalSpent.toNumber()).toBe(2_000);
+  });
+
+  it("closes the delegation during cleanup and returns its rent to the parent", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+
+    const otherEphemeral = Keypair.generate();
+    const otherVault = await createVault(parent, otherEphemeral);
+    const otherDelegation = await approveDelegate(parent, otherVault, otherEphemeral.publicKey);
+    await sleep(4_000);
+
+    const cleanup = (delegation: PublicKey) =>
+      program.methods
+        .cleanupVault()
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          cleaner: cleaner.publicKey,
+          parentWallet: parent.publicKey,
+          delegation,
+          refundRecipient: parent.publicKey,
+        })
+        .signers([cleaner]);
+
+    // Another vault's delegation cannot be swept along.
+    await expect(cleanup(otherDelegation).rpc()).rejects.toThrow(/InvalidDelegationAccount/);
+
+    const delegationRent = await provider.connection.getBalance(delegationPda);
+    const vaultBalance = await provider.connection.getBalance(vaultPda);
+    const parentBefore = await provider.connection.getBalance(parent.publicKey);
+
+    const cleaned = (await cleanup(delegationPda).simulate()).events.find(
+      (e) => e.name === "VaultCleaned"
+    );
+    expect(cleaned!.data.delegationRentReclaimed.toNumber()).toBe(delegationRent);
+    const reward = cleaned!.data.reward.toNumber();
+
+    await cleanup(delegationPda).rpc();
+    expect(await provider.connection.getAccountInfo(vaultPda)).toBeNull();
+    expect(await provider.connection.getAccountInfo(delegationPda)).toBeNull();
+    expect(await provider.connection.getBalance(parent.publicKey)).toBe(
+      parentBefore + vaultBalance - reward + delegationRent
+    );
+  });
+
+  it("rejects a non-zero min_output until the DEX CPI lands", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 100_000);
+
+    const tradeWithMinOutput = (minOutput: number) =>
+      program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(minOutput))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([ephemeral])
+        .rpc();
+
+    await expect(tradeWithMinOutput(1)).rejects.toThrow(/MinOutputUnsupported/);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).totalSpent.toNumber()).toBe(0);
+
+    await tradeWithMinOutput(0);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).totalSpent.toNumber()).toBe(1_000);
+  });
+
+  it("runs an SPL token vault from creation to cleanup", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+
+    const mint = await createMint(provider.connection, parent, parent.publicKey, null, 6);
+    const parentAta = await createAssociatedTokenAccount(
+      provider.connection,
+      parent,
+      mint,
+      parent.publicKey
+    );
+    await mintTo(provider.connection, parent, mint, parentAta, parent, 1_000_000);
+
+    const vaultPda = findVaultPda(parent.publicKey, ephemeral.publicKey);
+    const vaultAta = getAssociatedTokenAddressSync(mint, vaultPda, true);
+    await program.methods
+      .createTokenVault(
+        new anchor.BN(2),
+        new anchor.BN(500_000),
+        ephemeral.publicKey,
+        new anchor.BN(0),
+        new anchor.BN(0),
+        allowedPrograms([DEX_PROGRAM])
+      )
+      .accounts({
+        parent: parent.publicKey,
+        ephemeralWallet: ephemeral.publicKey,
+        vault: vaultPda,
+        tokenMint: mint,
+        vaultTokenAccount: vaultAta,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([parent])
+      .rpc();
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+
+    let vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.tokenMint.equals(mint)).toBe(true);
+    expect(vault.tokenAccount.equals(vaultAta)).toBe(true);
+
+    const depositToken = (amount: number) =>
+      program.methods
+        .autoDepositToken(new anchor.BN(amount))
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          parentTokenAccount: parentAta,
+          vaultTokenAccount: vaultAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([parent])
+        .rpc();
+    await expect(depositToken(500_001)).rejects.toThrow(/OverDeposit/);
+    await depositToken(400_000);
+    expect(Number((await getAccount(provider.connection, vaultAta)).amount)).toBe(400_000);
+
+    const tradeToken = (amount: number) =>
+      program.methods
+        .executeTokenTrade(new anchor.BN(amount), VENUE)
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([ephemeral])
+        .rpc();
+    await tradeToken(100_000);
+    await expect(tradeToken(300_001)).rejects.toThrow(/InsufficientVaultBalance/);
+    vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.tokenDeposited.toNumber()).toBe(400_000);
+    expect(vault.tokenSpent.toNumber()).toBe(100_000);
+    expect(vault.totalSpent.toNumber()).toBe(0);
+
+    const closeTokenAccount = () =>
+      program.methods
+        .closeTokenAccount()
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          parentTokenAccount: parentAta,
+          vaultTokenAccount: vaultAta,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    const cleanup = () =>
+      program.methods
+        .cleanupVault()
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          cleaner: cleaner.publicKey,
+          parentWallet: parent.publicKey,
+          delegation: null,
+          refundRecipient: parent.publicKey,
+        })
+        .signers([cleaner])
+        .rpc();
+
+    await expect(closeTokenAccount()).rejects.toThrow(/SessionNotExpired/);
+    await sleep(3_000);
+    await expect(cleanup()).rejects.toThrow(/TokenAccountOpen/);
+
+    await closeTokenAccount();
+    expect(await provider.connection.getAccountInfo(vaultAta)).toBeNull();
+    expect(Number((await getAccount(provider.connection, parentAta)).amount)).toBe(1_000_000);
+
+    await cleanup();
+    expect(await provider.connection.getAccountInfo(vaultPda)).toBeNull();
+  });
+
+  it("buckets trades by fee size and reports the histogram on cleanup", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 500_000);
+
+    // Bounds are exclusive: 10_000 lands in the middle bucket and 100_000 in the top one.
+    for (const fee of [1, 9_999, 10_000, 99_999, 100_000, 250_000]) {
+      await trade(parent, ephemeral, vaultPda, fee);
+    }
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.tradeHistogram.map((count) => count.toNumber())).toEqual([2, 2, 2]);
+
+    await sleep(4_000);
+    const histogram = (
+      await program.methods
+        .cleanupVault()
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          cleaner: cleaner.publicKey,
+          parentWallet: parent.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          refundRecipient: parent.publicKey,
+        })
+        .signers([cleaner])
+        .simulate()
+    ).events.find((e) => e.name === "TradeHistogram");
+    expect(histogram!.data.bounds.map((bound) => bound.toNumber())).toEqual([10_000, 100_000]);
+    expect(histogram!.data.counts.map((count) => count.toNumber())).toEqual([2, 2, 2]);
+  });
+
+  it("expires a delegation on its own TTL while the session stays live", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral, 3600);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey, 0, 2);
+    await deposit(parent, vaultPda, 100_000);
+
+    const delegation = await program.account.vaultDelegation.fetch(delegationPda);
+    expect(delegation.expiresAt.toNumber()).toBe(delegation.approvedAt.toNumber() + 2);
+
+    await trade(parent, ephemeral, vaultPda, 1_000);
+    await sleep(3_000);
+    await expect(trade(parent, ephemeral, vaultPda, 1_000)).rejects.toThrow(/DelegationExpired/);
+
+    // Re-approving without a TTL falls back to the session expiry.
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    expect((await program.account.vaultDelegation.fetch(delegationPda)).expiresAt.toNumber()).toBe(0);
+    await trade(parent, ephemeral, vaultPda, 1_000);
+  });
+
+  it("rejects trades routed through a program outside allowed_programs", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+
+    const vaultPda = await createVault(parent, ephemeral);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 100_000);
+
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.allowedPrograms[0].equals(DEX_PROGRAM)).toBe(true);
+
+    const tradeVia = (targetProgram: PublicKey) =>
+      program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram,
+        })
+        .signers([ephemeral])
+        .rpc();
+
+    await expect(tradeVia(SystemProgram.programId)).rejects.toThrow(/InvalidTargetProgram/);
+    await tradeVia(DEX_PROGRAM);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).totalSpent.toNumber()).toBe(1_000);
+
+    // A vault with an empty whitelist cannot trade at all.
+    const lockedEphemeral = Keypair.generate();
+    const lockedVault = await createVault(
+      parent,
+      lockedEphemeral,
+      3600,
+      500_000_000,
+      0,
+      false,
+      0,
+      0,
+      0,
+      0,
+      0,
+      []
+    );
+    await approveDelegate(parent, lockedVault, lockedEphemeral.publicKey);
+    await deposit(parent, lockedVault, 100_000);
+    await expect(trade(parent, lockedEphemeral, lockedVault, 1_000)).rejects.toThrow(
+      /InvalidTargetProgram/
+    );
+  });
+
+  it("labels a delegation with a purpose in the account and the event", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    const delegationPda = findDelegationPda(vaultPda, ephemeral.publicKey);
+
+    const approve = (label: number[]) =>
+      program.methods
+        .approveDelegate(ephemeral.publicKey, new anchor.BN(0), new anchor.BN(0), label)
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          delegation: delegationPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([parent]);
+
+    const approved = (await approve(purpose("spot-bot")).simulate()).events.find(
+      (e) => e.name === "DelegateApproved"
+    );
+    expect(approved!.data.purpose).toEqual(purpose("spot-bot"));
+
+    await approve(purpose("spot-bot")).rpc();
+    const delegation = await program.account.vaultDelegation.fetch(delegationPda);
+    expect(Buffer.from(delegation.purpose).toString("utf8").replace(/\0+$/, "")).toBe("spot-bot");
+
+    // Invalid UTF-8, and bytes after the zero padding, are both rejected.
+    const invalidUtf8 = purpose("");
+    invalidUtf8[0] = 0xff;
+    await expect(approve(invalidUtf8).rpc()).rejects.toThrow(/InvalidPurpose/);
+    const trailing = purpose("bot");
+    trailing[15] = 0x41;
+    await expect(approve(trailing).rpc()).rejects.toThrow(/InvalidPurpose/);
+  });
+
+  it("routes revoke and cleanup refunds to the configured recipient", async () => {
+    const parent = Keypair.generate();
+    const recipient = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(recipient.publicKey);
+    await airdrop(cleaner.publicKey);
+    const balance = (key: PublicKey) => provider.connection.getBalance(key);
+    const setRecipient = (vaultPda: PublicKey, key: PublicKey) =>
+      program.methods
+        .setRefundRecipient(key)
+        .accounts({ vault: vaultPda, parentWallet: parent.publicKey })
+        .signers([parent])
+        .rpc();
+
+    const revokedEphemeral = Keypair.generate();
+    const revokedVault = await createVault(parent, revokedEphemeral);
+    await approveDelegate(parent, revokedVault, revokedEphemeral.publicKey);
+    expect((await program.account.ephemeralVault.fetch(revokedVault)).refundRecipient).toEqual(
+      parent.publicKey
+    );
+    await deposit(parent, revokedVault, 200_000);
+    await expect(setRecipient(revokedVault, PublicKey.default)).rejects.toThrow(
+      /InvalidRefundRecipient/
+    );
+    await setRecipient(revokedVault, recipient.publicKey);
+
+    await expect(revoke(parent, revokedVault)).rejects.toThrow(/InvalidRefundRecipient/);
+    const beforeRevoke = await balance(recipient.publicKey);
+    await revoke(parent, revokedVault, recipient.publicKey);
+    expect((await balance(recipient.publicKey)) - beforeRevoke).toBe(200_000);
+
+    const emergencyEphemeral = Keypair.generate();
+    const emergencyVault = await createVault(parent, emergencyEphemeral);
+    await approveDelegate(parent, emergencyVault, emergencyEphemeral.publicKey);
+    await deposit(parent, emergencyVault, 200_000);
+    await setRecipient(emergencyVault, recipient.publicKey);
+    const emergencyRevoke = (refundRecipient: PublicKey) =>
+      program.methods
+        .emergencyRevoke()
+        .accounts({
+          vault: emergencyVault,
+          parentWallet: parent.publicKey,
+          delegation: findDelegationPda(emergencyVault, emergencyEphemeral.publicKey),
+          refundRecipient,
+        })
+        .signers([parent]);
+    await expect(emergencyRevoke(parent.publicKey).rpc()).rejects.toThrow(
+      /InvalidRefundRecipient/
+    );
+    const revokedEvent = (await emergencyRevoke(recipient.publicKey).simulate()).events.find(
+      (e) => e.name === "EmergencyRevoked"
+    );
+    expect(revokedEvent!.data.refundRecipient).toEqual(recipient.publicKey);
+    const beforeEmergency = await balance(recipient.publicKey);
+    await emergencyRevoke(recipient.publicKey).rpc();
+    expect((await balance(recipient.publicKey)) - beforeEmergency).toBe(200_000);
+
+    const expiredEphemeral = Keypair.generate();
+    const expiredVault = await createVault(parent, expiredEphemeral, 2);
+    await deposit(parent, expiredVault, 200_000);
+    await setRecipient(expiredVault, recipient.publicKey);
+    await sleep(3_000);
+
+    const cleanup = (refundRecipient: PublicKey) =>
+      program.methods
+        .cleanupVault()
+        .accounts({
+          vault: expiredVault,
+          parent: parent.publicKey,
+          cleaner: cleaner.publicKey,
+          parentWallet: parent.publicKey,
+          delegation: null,
+          refundRecipient,
+        })
+        .signers([cleaner]);
+    await expect(cleanup(parent.publicKey).rpc()).rejects.toThrow(/InvalidRefundRecipient/);
+
+    const cleaned = (await cleanup(recipient.publicKey).simulate()).events.find(
+      (e) => e.name === "VaultCleaned"
+    );
+    expect(cleaned!.data.refundRecipient).toEqual(recipient.publicKey);
+    const reward = cleaned!.data.reward.toNumber();
+
+    const beforeCleanup = await balance(recipient.publicKey);
+    await cleanup(recipient.publicKey).rpc();
+    expect((await balance(recipient.publicKey)) - beforeCleanup).toBe(200_000 - reward);
+  });
+
+  it("rotates the ephemeral wallet in place", async () => {
+    const parent = Keypair.generate();
+    const oldEphemeral = Keypair.generate();
+    const newEphemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, oldEphemeral);
+    const oldDelegation = await approveDelegate(parent, vaultPda, oldEphemeral.publicKey);
+    await deposit(parent, vaultPda, 200_000);
+    const newDelegation = findDelegationPda(vaultPda, newEphemeral.publicKey);
+
+    const rotate = (key: PublicKey) =>
+      program.methods
+        .rotateEphemeral(key)
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          oldDelegation,
+          newDelegation: findDelegationPda(vaultPda, key),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([parent]);
+    await expect(rotate(oldEphemeral.publicKey).rpc()).rejects.toThrow(/InvalidEphemeralWallet/);
+    await expect(rotate(parent.publicKey).rpc()).rejects.toThrow(/InvalidEphemeralWallet/);
+
+    const events = (await rotate(newEphemeral.publicKey).simulate()).events;
+    expect(events.map((e) => e.name)).toEqual([
+      "DelegateRevoked",
+      "EphemeralRotated",
+      "DelegateApproved",
+    ]);
+    await rotate(newEphemeral.publicKey).rpc();
+
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.ephemeralWallet).toEqual(newEphemeral.publicKey);
+    expect(vault.seedEphemeralWallet).toEqual(oldEphemeral.publicKey);
+    expect(findVaultPda(parent.publicKey, vault.seedEphemeralWallet)).toEqual(vaultPda);
+    expect((await program.account.vaultDelegation.fetch(oldDelegation)).revokedAt).not.toBeNull();
+    expect((await program.account.vaultDelegation.fetch(newDelegation)).delegate).toEqual(
+      newEphemeral.publicKey
+    );
+
+    await expect(trade(parent, oldEphemeral, vaultPda, 1_000)).rejects.toThrow(
+      /DelegationRevoked/
+    );
+    await trade(parent, newEphemeral, vaultPda, 1_000);
+    expect((await program.account.ephemeralVault.fetch(vaultPda)).totalSpent.toNumber()).toBe(1_000);
+  });
+
+  it("rejects a trade against another vault's delegation at the seeds constraint", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    await airdrop(parent.publicKey);
+    const vaultPda = await createVault(parent, ephemeral);
+    await deposit(parent, vaultPda, 50_000);
+    const otherVault = await createVault(parent, Keypair.generate());
+    await program.methods
+      .allowDelegate(ephemeral.publicKey)
+      .accounts({ vault: otherVault, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+    const foreignDelegation = await approveDelegate(parent, otherVault, ephemeral.publicKey);
+
+    await expect(
+      program.methods
+        .executeTrade(new anchor.BN(1_000), VENUE, new anchor.BN(0))
+        .accounts({
+          vault: vaultPda,
+          ephemeral: ephemeral.publicKey,
+          delegation: foreignDelegation,
+          parentWallet: parent.publicKey,
+          tradeOutput: null,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([ephemeral])
+        .rpc()
+    ).rejects.toThrow(/ConstraintSeeds/);
+    await expect(
+      program.methods
+        .depositAndTrade(new anchor.BN(10_000), new anchor.BN(1_000), VENUE)
+        .accounts({
+          vault: vaultPda,
+          parentWallet: parent.publicKey,
+          ephemeral: ephemeral.publicKey,
+          delegation: foreignDelegation,
+          systemProgram: SystemProgram.programId,
+          targetProgram: DEX_PROGRAM,
+        })
+        .signers([parent, ephemeral])
+        .rpc()
+    ).rejects.toThrow(/ConstraintSeeds/);
+  });
+
+  it("settles an expired vault without closing it", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+    const vaultPda = await createVault(parent, ephemeral, 2);
+    await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await deposit(parent, vaultPda, 200_000);
+    await sleep(3_000);
+
+    const settle = () =>
+      program.methods
+        .settleVault()
+        .accounts({
+          vault: vaultPda,
+          cleaner: cleaner.publicKey,
+          refundRecipient: parent.publicKey,
+        })
+        .signers([cleaner]);
+    const parentBefore = await provider.connection.getBalance(parent.publicKey);
+    const simulated = await settle().simulate();
+    expect(simulated.events.map((e) => e.name)).toEqual(["TradeHistogram", "VaultSettled"]);
+    await settle().rpc();
+
+    const info = await provider.connection.getAccountInfo(vaultPda);
+    expect(info).not.toBeNull();
+    expect(info!.lamports).toBe(
+      await provider.connection.getMinimumBalanceForRentExemption(info!.data.length)
+    );
+    const vault = await program.account.ephemeralVault.fetch(vaultPda);
+    expect(vault.isSettled).toBe(true);
+    expect(vault.isActive).toBe(false);
+    expect((await provider.connection.getBalance(parent.publicKey)) - parentBefore).toBeGreaterThan(
+      0
+    );
+
+    await expect(trade(parent, ephemeral, vaultPda, 1_000)).rejects.toThrow(/VaultInactive/);
+    await expect(settle().rpc()).rejects.toThrow(/VaultSettled/);
+
+    // The histogram was reported at settlement; closing the vault does not repeat it.
+    const cleaned = await program.methods
+      .cleanupVault()
+      .accounts({
+        vault: vaultPda,
+        parent: parent.publicKey,
+        cleaner: cleaner.publicKey,
+        parentWallet: parent.publicKey,
+        delegation: findDelegationPda(vaultPda, ephemeral.publicKey),
+        refundRecipient: parent.publicKey,
+      })
+      .signers([cleaner])
+      .simulate();
+    expect(cleaned.events.map((e) => e.name)).toEqual(["VaultCleaned"]);
+  });
+
+  it("pays the cleaner reward only to a cleaner holding the minimum balance", async () => {
+    const parent = Keypair.generate();
+    const poorCleaner = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(poorCleaner.publicKey, 5_000_000);
+    await airdrop(cleaner.publicKey, 10_000_000);
+
+    const expiredVault = async () => {
+      const vaultPda = await createVault(parent, Keypair.generate(), 2);
+      await deposit(parent, vaultPda, 200_000);
+      return vaultPda;
+    };
+    const poorVault = await expiredVault();
+    const fundedVault = await expiredVault();
+    const waivedVault = await expiredVault();
+    expect((await program.account.ephemeralVault.fetch(poorVault)).minCleanerBalance.toNumber()).toBe(
+      10_000_000
+    );
+    await program.methods
+      .setMinCleanerBalance(new anchor.BN(0))
+      .accounts({ vault: waivedVault, parentWallet: parent.publicKey })
+      .signers([parent])
+      .rpc();
+    await sleep(3_000);
+
+    const cleanup = (vaultPda: PublicKey, signer: Keypair) =>
+      program.methods
+        .cleanupVault()
+        .accounts({
+          vault: vaultPda,
+          parent: parent.publicKey,
+          cleaner: signer.publicKey,
+          parentWallet: parent.publicKey,
+          delegation: null,
+          refundRecipient: parent.publicKey,
+        })
+        .signers([signer]);
+    const cleanedEvent = async (vaultPda: PublicKey, signer: Keypair) => {
+      const { events } = await cleanup(vaultPda, signer).simulate();
+      return events.find((e) => e.name === "VaultCleaned")!.data;
+    };
+
+    // Below the minimum: the sweep completes, with the reward going to the refund recipient.
+    const unpaid = await cleanedEvent(poorVault, poorCleaner);
+    expect(unpaid.reward.toNumber()).toBe(0);
+    expect(unpaid.refunded.toNumber()).toBe(200_000);
+    await cleanup(poorVault, poorCleaner).rpc();
+    expect(await provider.connection.getBalance(poorCleaner.publicKey)).toBe(5_000_000);
+    expect(await provider.connection.getAccountInfo(poorVault)).toBeNull();
+
+    const paid = await cleanedEvent(fundedVault, cleaner);
+    expect(paid.reward.toNumber()).toBe(10_000);
+    expect(paid.refunded.toNumber()).toBe(190_000);
+    await cleanup(fundedVault, cleaner).rpc();
+    expect(await provider.connection.getBalance(cleaner.publicKey)).toBe(10_010_000);
+    expect(await provider.connection.getAccountInfo(fundedVault)).toBeNull();
+
+    // With the vault's minimum waived, the same under-funded cleaner is paid.
+    const waived = await cleanedEvent(waivedVault, poorCleaner);
+    expect(waived.reward.toNumber()).toBe(10_000);
+    await cleanup(waivedVault, poorCleaner).rpc();
+    expect(await provider.connection.getBalance(poorCleaner.publicKey)).toBe(5_010_000);
+  });
+
+  it("returns cleanup rent only to the vault's parent", async () => {
+    const parent = Keypair.generate();
+    const ephemeral = Keypair.generate();
+    const cleaner = Keypair.generate();
+    await airdrop(parent.publicKey);
+    await airdrop(cleaner.publicKey);
+    const vaultPda = await createVault(parent, ephemeral, 2);
+    const delegationPda = await approveDelegate(parent, vaultPda, ephemeral.publicKey);
+    await sleep(3_000);
+
+    const cleanup = (rentReceiver: PublicKey) =>
+      program.methods
+        .cleanupVault()
+        .accounts({
+          vault: vaultPda,
+          parent: rentReceiver,
+          cleaner: cleaner.publicKey,
+          parentWallet: parent.publicKey,
+          delegation: delegationPda,
+          refundRecipient: parent.publicKey,
+        })
+        .signers([cleaner])
+        .rpc();
+
+    await expect(cleanup(cleaner.publicKey)).rejects.toThrow(/ConstraintAddress/);
+    await cleanup(parent.publicKey);
+    expect(await provider.connection.getAccountInfo(vaultPda)).toBeNull();
+    expect(await provider.connection.getAccountInfo(delegationPda)).toBeNull();
+  });
 });